@@ -0,0 +1,76 @@
+use crate::cli::*;
+use crate::error::TanglError;
+use crate::git::conflict::ConflictCache;
+use clap::Command;
+
+#[derive(Clone, Debug)]
+pub struct CacheCommand;
+
+impl CommandDefinition for CacheCommand {
+    fn build_command(&self) -> Command {
+        Command::new("cache")
+            .about("Manage the cache of conflict check results")
+            .disable_help_subcommand(true)
+            .arg_required_else_help(true)
+    }
+    fn get_subcommands(&self) -> Vec<Box<dyn CommandImpl>> {
+        vec![Box::new(CacheClearCommand)]
+    }
+}
+
+impl CommandInterface for CacheCommand {}
+
+#[derive(Clone, Debug)]
+pub struct CacheClearCommand;
+
+impl CommandDefinition for CacheClearCommand {
+    fn build_command(&self) -> Command {
+        Command::new("clear")
+            .about("Remove all cached conflict check results")
+            .disable_help_subcommand(true)
+    }
+}
+
+impl CommandInterface for CacheClearCommand {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
+        let store = context.git.get_metadata_store();
+        let n_entries = store.load::<ConflictCache>()?.len();
+        store.remove::<ConflictCache>()?;
+        context.info(format!("Removed {} cached results", n_entries));
+        context.output("removed", n_entries);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::conflict::ConflictChecker;
+    use crate::git::interface::test_utils::{populate_with_features, prepare_empty_git_repo};
+    use crate::git::interface::{GitInterface, GitPath};
+    use crate::model::QualifiedPath;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cache_clear() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        let _ = ConflictChecker::new(&interface)
+            .check_all(&vec![
+                QualifiedPath::from("/main/feature/root/foo"),
+                QualifiedPath::from("/main/feature/root/bar"),
+            ])
+            .unwrap();
+        let store = interface.get_metadata_store();
+        assert!(store.exists::<ConflictCache>().unwrap());
+        let repo =
+            CommandRepository::new(Box::new(CacheCommand), GitPath::CustomDirectory(path_buf));
+        repo.execute(ArgSource::SUPPLIED(vec!["cache", "clear"]))
+            .unwrap();
+        assert!(!store.exists::<ConflictCache>().unwrap());
+    }
+}
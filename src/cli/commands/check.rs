@@ -1,19 +1,44 @@
 use crate::cli::completion::CompletionHelper;
 use crate::cli::*;
-use crate::git::conflict::{ConflictChecker, ConflictStatistics};
+use crate::error::TanglError;
+use crate::git::conflict::{ConflictChecker, ConflictSet, ConflictStatistic, ConflictStatistics};
+use crate::git::matrix::{ConflictMatrix, MatrixFormat};
 use crate::model::{
     ByQPathFilteringNodePathTransformer, HasBranchFilteringNodePathTransformer,
     NodePathTransformer, NodePathType, QPathFilteringMode, QualifiedPath,
 };
 use clap::{Arg, ArgAction, Command};
 use colored::Colorize;
-use std::error::Error;
 
 const SOURCE: &str = "source";
 const TARGETS: &str = "targets";
 const ALL: &str = "all";
+const JOBS: &str = "jobs";
+const NO_CACHE: &str = "no_cache";
+const ORDER: &str = "order";
+const DETAILS: &str = "details";
+const MATRIX: &str = "matrix";
 
-fn run_check(context: &CommandContext) -> Result<ConflictStatistics, Box<dyn Error>> {
+/// Returns the path if it names a feature with a branch.
+fn to_feature_path(
+    context: &CommandContext,
+    path: QualifiedPath,
+) -> Result<QualifiedPath, TanglError> {
+    match context
+        .git
+        .get_model()
+        .get_node_path(&path)
+        .map(|node| node.concretize())
+    {
+        Some(NodePathType::Feature(feature)) if feature.get_metadata().has_branch() => Ok(path),
+        _ => Err(TanglError::invalid_path(format!(
+            "{} is not a feature",
+            path
+        ))),
+    }
+}
+
+fn run_check(context: &CommandContext) -> Result<ConflictStatistics, TanglError> {
     let all = context
         .arg_helper
         .get_argument_value::<bool>(ALL)
@@ -30,38 +55,52 @@ fn run_check(context: &CommandContext) -> Result<ConflictStatistics, Box<dyn Err
         };
     let feature_root = match context.git.get_current_area()?.to_feature_root() {
         Some(path) => path,
-        None => return Err("Nothing to check: no features exist".into()),
+        None => return Err(TanglError::usage("Nothing to check: no features exist")),
     };
     let current_path = context.git.get_current_node_path()?;
-    let checker = ConflictChecker::new(&context.git);
-    let statistics: ConflictStatistics = match (all, maybe_feature, maybe_targets) {
+    let no_cache = context
+        .arg_helper
+        .get_argument_value::<bool>(NO_CACHE)
+        .unwrap_or(false);
+    let mut checker = ConflictChecker::new(&context.git).with_cache(!no_cache);
+    if let Some(jobs) = context.arg_helper.get_argument_value::<u16>(JOBS) {
+        checker = checker.with_jobs(jobs as usize);
+    }
+    let order = context
+        .arg_helper
+        .get_argument_value::<u16>(ORDER)
+        .unwrap_or(2) as usize;
+    // the features checked for higher-order conflicts and the feature all
+    // reported sets must contain
+    let (mut statistics, features, required): (
+        ConflictStatistics,
+        Vec<QualifiedPath>,
+        Option<QualifiedPath>,
+    ) = match (all, maybe_feature, maybe_targets) {
         // all AND source are not set => error
-        (false, None, _) => return Err("Feature must be provided if --all is not set".into()),
+        (false, None, _) => {
+            return Err(TanglError::usage(
+                "Feature must be provided if --all is not set",
+            ));
+        }
         // all is set => check all
         (true, _, _) => {
-            let all_features: Vec<QualifiedPath> = feature_root
+            let mut all_features: Vec<QualifiedPath> = feature_root
                 .iter_children_req()
+                .filter(|child| child.get_metadata().has_branch())
                 .map(|child| child.get_qualified_path())
                 .collect();
-            checker.check_all(&all_features)?.collect()
+            all_features.sort();
+            let statistics = checker.check_all(&all_features)?.collect();
+            (statistics, all_features, None)
         }
         // all is not set, source is set, target not => check source against all
         (false, Some(source), None) => {
-            let qualified_source = current_path.get_qualified_path() + source;
-            match context
-                .git
-                .get_model()
-                .get_node_path(&qualified_source)
-                .unwrap()
-                .concretize()
-            {
-                NodePathType::Feature(_) => {}
-                _ => {
-                    return Err(format!("{} is not a feature", qualified_source).into());
-                }
-            }
-            let all_other_features: Vec<QualifiedPath> = feature_root
+            let qualified_source =
+                to_feature_path(context, current_path.get_qualified_path() + source)?;
+            let mut all_other_features: Vec<QualifiedPath> = feature_root
                 .iter_children_req()
+                .filter(|child| child.get_metadata().has_branch())
                 .filter_map(|child| {
                     let path = child.get_qualified_path();
                     if path != qualified_source {
@@ -71,24 +110,84 @@ fn run_check(context: &CommandContext) -> Result<ConflictStatistics, Box<dyn Err
                     }
                 })
                 .collect();
-            checker
+            all_other_features.sort();
+            let statistics = checker
                 .check_1_to_n(&qualified_source, &all_other_features)?
-                .collect()
+                .collect();
+            all_other_features.insert(0, qualified_source.clone());
+            (statistics, all_other_features, Some(qualified_source))
         }
         (false, Some(source), Some(targets)) => {
-            let qualified_source = current_path.get_qualified_path() + source;
-            let qualified_targets: Vec<QualifiedPath> = targets
+            let qualified_source =
+                to_feature_path(context, current_path.get_qualified_path() + source)?;
+            let mut qualified_targets: Vec<QualifiedPath> = targets
                 .into_iter()
-                .map(|target| current_path.get_qualified_path() + QualifiedPath::from(target))
-                .collect();
-            checker
+                .map(|target| to_feature_path(context, current_path.get_qualified_path() + target))
+                .collect::<Result<_, _>>()?;
+            let statistics = checker
                 .check_1_to_n(&qualified_source, &qualified_targets)?
-                .collect()
+                .collect();
+            qualified_targets.insert(0, qualified_source.clone());
+            (statistics, qualified_targets, Some(qualified_source))
         }
     };
+    if order > 2 {
+        let sets = checker.check_higher_order(&features, order, &statistics)?;
+        statistics.extend_sets(sets.into_iter().filter(|set| match &required {
+            Some(required) => set.features().contains(required),
+            None => true,
+        }));
+    }
     Ok(statistics)
 }
 
+/// Logs the conflicting files of a pair and the clashing lines of both
+/// features.
+fn log_conflict_details(context: &mut CommandContext, conflict: &ConflictStatistic) {
+    let (l, r) = conflict.pair();
+    let (l, r) = (l.last().cloned(), r.last().cloned());
+    for file in conflict.files() {
+        context.warn(format!("  {}", file.path()));
+        for hunk in file.hunks() {
+            context.warn(format!(
+                "    {}: {}, {}: {}",
+                l.as_deref().unwrap_or_default(),
+                hunk.left(),
+                r.as_deref().unwrap_or_default(),
+                hunk.right()
+            ));
+        }
+    }
+}
+
+/// Fails if any check failed or found conflicts, so the exit code tells
+/// scripts whether the features can be merged.
+fn check_result(statistics: &ConflictStatistics) -> Result<(), TanglError> {
+    if statistics.n_errors() > 0 {
+        return Err(TanglError::git(format!(
+            "{} checks failed",
+            statistics.n_errors()
+        )));
+    }
+    let n_conflicts = statistics.n_conflict() + statistics.n_conflict_sets();
+    if n_conflicts > 0 {
+        return Err(TanglError::conflicts(format!(
+            "Found {} conflicts",
+            n_conflicts
+        )));
+    }
+    Ok(())
+}
+
+fn log_conflict_sets(context: &mut CommandContext, statistics: &ConflictStatistics) {
+    for set in statistics.iter_sets() {
+        match set {
+            ConflictSet::CONFLICT(_) => context.warn(set.to_string()),
+            ConflictSet::ERROR(_, _) => context.error(set.to_string()),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct CheckCommand;
 
@@ -111,33 +210,86 @@ impl CommandDefinition for CheckCommand {
                     .action(ArgAction::SetTrue)
                     .help("Check all features against each other"),
             )
+            .arg(
+                Arg::new(JOBS)
+                    .short('j')
+                    .long("jobs")
+                    .value_parser(clap::value_parser!(u16).range(1..))
+                    .help("Number of checks to run in parallel; defaults to the number of CPUs"),
+            )
+            .arg(
+                Arg::new(ORDER)
+                    .long("order")
+                    .value_parser(clap::value_parser!(u16).range(2..))
+                    .help("Also merge sets of up to this many features to find conflicts that no pair shows"),
+            )
+            .arg(
+                Arg::new(DETAILS)
+                    .long("details")
+                    .action(ArgAction::SetTrue)
+                    .help("Show the conflicting files and lines of each pair"),
+            )
+            .arg(
+                Arg::new(MATRIX)
+                    .long("matrix")
+                    .num_args(0..=1)
+                    .value_name("FORMAT")
+                    .value_parser(["terminal", "csv", "html"])
+                    .default_missing_value("terminal")
+                    .requires(ALL)
+                    .help("Print the results as a feature by feature table"),
+            )
+            .arg(
+                Arg::new(NO_CACHE)
+                    .long("no-cache")
+                    .action(ArgAction::SetTrue)
+                    .help("Merge all pairs again instead of using cached results"),
+            )
             .arg(verbose())
     }
 }
 
 impl CommandInterface for CheckCommand {
-    fn run_command(&self, context: &mut CommandContext) -> Result<(), Box<dyn Error>> {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
         let statistics = run_check(context)?;
+        if let Some(format) = context.arg_helper.get_argument_value::<String>(MATRIX) {
+            let feature_root = context.git.get_current_area()?.get_path_to_feature_root();
+            context.print(
+                ConflictMatrix::new(&feature_root, &statistics).render(MatrixFormat::from(format)),
+            );
+            log_conflict_sets(context, &statistics);
+            context.output("statistics", &statistics);
+            return check_result(&statistics);
+        }
+        let details = context
+            .arg_helper
+            .get_argument_value::<bool>(DETAILS)
+            .unwrap_or(false);
         for ok in statistics.iter_ok() {
             context.debug(ok)
         }
         for conflict in statistics.iter_conflicts() {
-            context.warn(conflict)
+            context.warn(conflict);
+            if details {
+                log_conflict_details(context, conflict);
+            }
         }
         for error in statistics.iter_errors() {
             context.error(error)
         }
-        if statistics.n_conflict() == 0 {
+        log_conflict_sets(context, &statistics);
+        if statistics.n_conflict() == 0 && statistics.n_conflict_sets() == 0 {
             context.info("No conflicts".green().to_string());
         }
-        Ok(())
+        context.output("statistics", &statistics);
+        check_result(&statistics)
     }
 
     fn shell_complete(
         &self,
         completion_helper: CompletionHelper,
         context: &mut CommandContext,
-    ) -> Result<Vec<String>, Box<dyn Error>> {
+    ) -> Result<Vec<String>, TanglError> {
         let currently_editing = completion_helper.currently_editing();
         let completion: Vec<String> = if currently_editing.is_some() {
             let feature_root = match context.git.get_current_area()?.to_feature_root() {
@@ -222,10 +374,12 @@ mod tests {
             Box::new(CheckCommand),
             GitPath::CustomDirectory(PathBuf::from(path.path())),
         );
-        let context = repo.build_context(
-            ArgSource::SUPPLIED(vec!["check", "--all"]),
-            ImportFormat::Native,
-        );
+        let context = repo
+            .build_context(
+                ArgSource::SUPPLIED(vec!["check", "--all"]),
+                ImportFormat::Native,
+            )
+            .unwrap();
         match run_check(&context) {
             Ok(statistics) => {
                 assert_eq!(statistics.n_ok(), 6);
@@ -238,6 +392,148 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_check_all_skips_features_without_branch() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        std::process::Command::new("git")
+            .args(["branch", "_main/_feature/_root/_abstract/leaf"])
+            .current_dir(&path_buf)
+            .output()
+            .unwrap();
+        let repo = CommandRepository::new(
+            Box::new(CheckCommand),
+            GitPath::CustomDirectory(PathBuf::from(path.path())),
+        );
+        let context = repo
+            .build_context(
+                ArgSource::SUPPLIED(vec!["check", "--all"]),
+                ImportFormat::Native,
+            )
+            .unwrap();
+        match run_check(&context) {
+            Ok(statistics) => {
+                assert_eq!(statistics.n_ok(), 10);
+                assert_eq!(statistics.n_conflict(), 0);
+                assert_eq!(statistics.n_errors(), 0);
+            }
+            Err(_) => panic!(),
+        }
+        let context = repo
+            .build_context(
+                ArgSource::SUPPLIED(vec!["check", "feature/root/foo", "feature/root/abstract"]),
+                ImportFormat::Native,
+            )
+            .unwrap();
+        match run_check(&context) {
+            Ok(_) => panic!("Should fail"),
+            Err(err) => assert_eq!(err.exit_code(), 3),
+        }
+    }
+
+    #[test]
+    fn test_check_all_parallel_is_deterministic() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        commit_file_on_branch(
+            path_buf.clone(),
+            "_main/_feature/_root/foo",
+            "shared",
+            "foo",
+        )
+        .unwrap();
+        commit_file_on_branch(
+            path_buf.clone(),
+            "_main/_feature/_root/bar",
+            "shared",
+            "bar",
+        )
+        .unwrap();
+        let repo = CommandRepository::new(
+            Box::new(CheckCommand),
+            GitPath::CustomDirectory(PathBuf::from(path.path())),
+        );
+        let run = |jobs: &str| {
+            let context = repo
+                .build_context(
+                    ArgSource::SUPPLIED(vec!["check", "--all", "--jobs", jobs]),
+                    ImportFormat::Native,
+                )
+                .unwrap();
+            let statistics = run_check(&context).unwrap();
+            (
+                statistics
+                    .iter_ok()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>(),
+                statistics
+                    .iter_conflicts()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let (ok, conflicts) = run("1");
+        assert_eq!(ok.len(), 5);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(run("4"), (ok, conflicts));
+    }
+
+    #[test]
+    fn test_check_details() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        commit_file_on_branch(
+            path_buf.clone(),
+            "_main/_feature/_root/foo",
+            "shared",
+            "1\nfoo\n3\n",
+        )
+        .unwrap();
+        commit_file_on_branch(
+            path_buf.clone(),
+            "_main/_feature/_root/bar",
+            "shared",
+            "1\nbar\nbar\n3\n",
+        )
+        .unwrap();
+        let repo = CommandRepository::new(
+            Box::new(CheckCommand),
+            GitPath::CustomDirectory(PathBuf::from(path.path())),
+        );
+        let context = repo
+            .build_context(
+                ArgSource::SUPPLIED(vec![
+                    "check",
+                    "--details",
+                    "/main/feature/root/foo",
+                    "/main/feature/root/bar",
+                ]),
+                ImportFormat::Native,
+            )
+            .unwrap();
+        let statistics = run_check(&context).unwrap();
+        let conflict = statistics.iter_conflicts().next().unwrap();
+        let files = conflict.files();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path(), "shared");
+        let hunk = &files[0].hunks()[0];
+        assert_eq!((hunk.left().start(), hunk.left().count()), (2, 1));
+        assert_eq!((hunk.right().start(), hunk.right().count()), (2, 2));
+        let result = repo.execute(ArgSource::SUPPLIED(vec![
+            "check",
+            "--details",
+            "/main/feature/root/foo",
+        ]));
+        assert!(matches!(result, Err(TanglError::Conflicts(_))));
+        assert_eq!(result.unwrap_err().exit_code(), 1);
+    }
+
     #[test]
     fn test_check_current_feature() {
         let path = TempDir::new().unwrap();
@@ -245,16 +541,19 @@ mod tests {
         prepare_empty_git_repo(path_buf.clone()).unwrap();
         populate_with_features(path_buf.clone()).unwrap();
         GitInterface::new(GitPath::CustomDirectory(path_buf))
+            .unwrap()
             .checkout(&QualifiedPath::from("/main/feature/root/foo"))
             .unwrap();
         let repo = CommandRepository::new(
             Box::new(CheckCommand),
             GitPath::CustomDirectory(PathBuf::from(path.path())),
         );
-        let context = repo.build_context(
-            ArgSource::SUPPLIED(vec!["check", "."]),
-            ImportFormat::Native,
-        );
+        let context = repo
+            .build_context(
+                ArgSource::SUPPLIED(vec!["check", "."]),
+                ImportFormat::Native,
+            )
+            .unwrap();
         match run_check(&context) {
             Ok(statistics) => {
                 assert_eq!(statistics.n_ok(), 3);
@@ -277,10 +576,12 @@ mod tests {
             Box::new(CheckCommand),
             GitPath::CustomDirectory(PathBuf::from(path.path())),
         );
-        let context = repo.build_context(
-            ArgSource::SUPPLIED(vec!["check", "."]),
-            ImportFormat::Native,
-        );
+        let context = repo
+            .build_context(
+                ArgSource::SUPPLIED(vec!["check", "."]),
+                ImportFormat::Native,
+            )
+            .unwrap();
         match run_check(&context) {
             Ok(_) => {
                 panic!("Should fail")
@@ -292,7 +593,7 @@ mod tests {
     }
 
     #[test]
-    fn test_check_specific_targets_relative_path() {
+    fn test_check_unknown_feature() {
         let path = TempDir::new().unwrap();
         let path_buf = PathBuf::from(path.path());
         prepare_empty_git_repo(path_buf.clone()).unwrap();
@@ -301,15 +602,41 @@ mod tests {
             Box::new(CheckCommand),
             GitPath::CustomDirectory(PathBuf::from(path.path())),
         );
-        let context = repo.build_context(
-            ArgSource::SUPPLIED(vec![
-                "check",
-                "feature/root/foo",
-                "feature/root/bar",
-                "feature/root/baz",
-            ]),
-            ImportFormat::Native,
+        for args in [
+            vec!["check", "nope"],
+            vec!["check", "feature/root/foo", "nope"],
+        ] {
+            let context = repo
+                .build_context(ArgSource::SUPPLIED(args), ImportFormat::Native)
+                .unwrap();
+            match run_check(&context) {
+                Ok(_) => panic!("Should fail"),
+                Err(err) => assert_eq!(err.exit_code(), 3),
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_specific_targets_relative_path() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let repo = CommandRepository::new(
+            Box::new(CheckCommand),
+            GitPath::CustomDirectory(PathBuf::from(path.path())),
         );
+        let context = repo
+            .build_context(
+                ArgSource::SUPPLIED(vec![
+                    "check",
+                    "feature/root/foo",
+                    "feature/root/bar",
+                    "feature/root/baz",
+                ]),
+                ImportFormat::Native,
+            )
+            .unwrap();
         match run_check(&context) {
             Ok(statistics) => {
                 assert_eq!(statistics.n_ok(), 2);
@@ -333,17 +660,20 @@ mod tests {
             GitPath::CustomDirectory(PathBuf::from(path.path())),
         );
         GitInterface::new(GitPath::CustomDirectory(path_buf))
+            .unwrap()
             .checkout(&QualifiedPath::from("/main/feature/root/foo"))
             .unwrap();
-        let context = repo.build_context(
-            ArgSource::SUPPLIED(vec![
-                "check",
-                "/main/feature/root/foo",
-                "/main/feature/root/bar",
-                "/main/feature/root/baz",
-            ]),
-            ImportFormat::Native,
-        );
+        let context = repo
+            .build_context(
+                ArgSource::SUPPLIED(vec![
+                    "check",
+                    "/main/feature/root/foo",
+                    "/main/feature/root/bar",
+                    "/main/feature/root/baz",
+                ]),
+                ImportFormat::Native,
+            )
+            .unwrap();
         match run_check(&context) {
             Ok(statistics) => {
                 assert_eq!(statistics.n_ok(), 2);
@@ -355,4 +685,57 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_check_json_output() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        commit_file_on_branch(
+            path_buf.clone(),
+            "_main/_feature/_root/foo",
+            "shared",
+            "foo",
+        )
+        .unwrap();
+        commit_file_on_branch(
+            path_buf.clone(),
+            "_main/_feature/_root/bar",
+            "shared",
+            "bar",
+        )
+        .unwrap();
+        let repo = CommandRepository::new(
+            Box::new(TangleCommand {}),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        let (result, document) = repo.execute_with_output(ArgSource::SUPPLIED(vec![
+            "tangl", "--output", "json", "check", "--all",
+        ]));
+        assert!(matches!(result, Err(TanglError::Conflicts(_))));
+        let document = document.unwrap();
+        assert_eq!(document["command"], "check");
+        assert_eq!(document["success"], false);
+        let pairs = document["data"]["statistics"]["pairs"].as_array().unwrap();
+        assert_eq!(pairs.len(), 6);
+        let conflicts: Vec<&serde_json::Value> = pairs
+            .iter()
+            .filter(|pair| pair["result"] == "conflict")
+            .collect();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0]["files"][0]["path"], "shared");
+
+        let (result, document) = repo.execute_with_output(ArgSource::SUPPLIED(vec![
+            "tangl", "check", "--output", "json",
+        ]));
+        assert!(matches!(result, Err(TanglError::InvalidPath(_))));
+        let document = document.unwrap();
+        assert_eq!(document["success"], false);
+        assert!(document["error"].is_string());
+
+        let (_, document) =
+            repo.execute_with_output(ArgSource::SUPPLIED(vec!["tangl", "check", "--all"]));
+        assert!(document.is_none());
+    }
 }
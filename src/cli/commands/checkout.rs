@@ -1,8 +1,8 @@
 use crate::cli::completion::*;
 use crate::cli::*;
+use crate::error::TanglError;
 use crate::model::QualifiedPath;
 use clap::{Arg, Command};
-use std::error::Error;
 
 #[derive(Clone, Debug)]
 pub struct CheckoutCommand;
@@ -15,12 +15,18 @@ impl CommandDefinition for CheckoutCommand {
     }
 }
 impl CommandInterface for CheckoutCommand {
-    fn run_command(&self, context: &mut CommandContext) -> Result<(), Box<dyn Error>> {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
         let branch = context
             .arg_helper
             .get_argument_value::<String>("branch")
             .unwrap();
         let full_target = context.git.get_current_qualified_path()? + QualifiedPath::from(branch);
+        if !context.git.get_model().has_branch(&full_target) {
+            return Err(TanglError::invalid_path(format!(
+                "{} does not exist",
+                full_target
+            )));
+        }
         let result = context.git.checkout(&full_target)?;
         context.log_from_output(&result);
         Ok(())
@@ -29,7 +35,7 @@ impl CommandInterface for CheckoutCommand {
         &self,
         completion_helper: CompletionHelper,
         context: &mut CommandContext,
-    ) -> Result<Vec<String>, Box<dyn Error>> {
+    ) -> Result<Vec<String>, TanglError> {
         let maybe_editing = completion_helper.currently_editing();
         if maybe_editing.is_none() {
             return Ok(vec![]);
@@ -1,7 +1,7 @@
 use crate::cli::completion::CompletionHelper;
 use crate::cli::*;
+use crate::error::TanglError;
 use clap::{Arg, ArgAction, Command};
-use std::error::Error;
 
 #[derive(Clone, Debug)]
 pub struct HiddenCompletionCommand;
@@ -16,7 +16,7 @@ impl CommandDefinition for HiddenCompletionCommand {
 }
 
 impl CommandInterface for HiddenCompletionCommand {
-    fn run_command(&self, context: &mut CommandContext) -> Result<(), Box<dyn Error>> {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
         let matches = context
             .arg_helper
             .get_argument_values::<String>("cli")
@@ -0,0 +1,369 @@
+use crate::cli::completion::*;
+use crate::cli::*;
+use crate::error::TanglError;
+use crate::git::interface::GitInterface;
+use crate::git::persistency::{Document, PersistencyError};
+use crate::model::*;
+use clap::{Arg, Command};
+use serde_json::{Value, json};
+
+const SOURCE: &str = "source";
+const KIND: &str = "kind";
+const TARGET: &str = "target";
+
+impl Document for Constraints {
+    const NAME: &'static str = "constraints";
+    const VERSION: u32 = 1;
+
+    fn migrate(_version: u32, data: Value) -> Result<Self, PersistencyError> {
+        Ok(serde_json::from_value(data)?)
+    }
+}
+
+pub fn load_constraints(git: &GitInterface) -> Result<Constraints, TanglError> {
+    Ok(git.get_metadata_store().load()?)
+}
+
+pub fn store_constraints(git: &GitInterface, constraints: &Constraints) -> Result<(), TanglError> {
+    Ok(git.get_metadata_store().store(constraints)?)
+}
+
+/// Builds the constraint given on the command line. With `require_branches`,
+/// both features must exist; constraints are removed by their stored paths,
+/// so features deleted in the meantime can still be named.
+fn constraint_from_args(
+    context: &CommandContext,
+    require_branches: bool,
+) -> Result<Constraint, TanglError> {
+    let feature_root = context.git.get_current_area()?.get_path_to_feature_root();
+    let mut paths = Vec::new();
+    for id in [SOURCE, TARGET] {
+        let path = feature_root.clone()
+            + QualifiedPath::from(context.arg_helper.get_argument_value::<String>(id).unwrap());
+        if require_branches && !context.git.get_model().has_branch(&path) {
+            return Err(TanglError::invalid_path(format!(
+                "{} is not a feature",
+                path
+            )));
+        }
+        paths.push(path);
+    }
+    let target = paths.pop().unwrap();
+    let source = paths.pop().unwrap();
+    if source == target {
+        return Err(TanglError::usage(
+            "A feature cannot be constrained by itself",
+        ));
+    }
+    let kind = context
+        .arg_helper
+        .get_argument_value::<String>(KIND)
+        .unwrap();
+    Ok(Constraint::new(ConstraintKind::from(kind), source, target))
+}
+
+fn constraint_args(command: Command) -> Command {
+    command
+        .arg(Arg::new(SOURCE).required(true).help("Constrained feature"))
+        .arg(
+            Arg::new(KIND)
+                .required(true)
+                .value_parser(["requires", "excludes"]),
+        )
+        .arg(
+            Arg::new(TARGET)
+                .required(true)
+                .help("Required or excluded feature"),
+        )
+}
+
+fn complete_constraint_args(
+    completion_helper: CompletionHelper,
+    context: &mut CommandContext,
+) -> Result<Vec<String>, TanglError> {
+    let maybe_feature_root = context.git.get_current_area()?.to_feature_root();
+    let result = match (completion_helper.currently_editing(), maybe_feature_root) {
+        (Some(arg), Some(feature_root)) => match arg.get_id().as_str() {
+            SOURCE | TARGET => completion_helper.complete_qualified_paths(
+                feature_root.get_qualified_path(),
+                HasBranchFilteringNodePathTransformer::new(true)
+                    .transform(feature_root.iter_children_req())
+                    .map(|path| path.get_qualified_path()),
+            ),
+            KIND => vec!["requires".to_string(), "excludes".to_string()],
+            _ => vec![],
+        },
+        _ => vec![],
+    };
+    Ok(result)
+}
+
+#[derive(Clone, Debug)]
+pub struct ConstraintCommand;
+
+impl CommandDefinition for ConstraintCommand {
+    fn build_command(&self) -> Command {
+        Command::new("constraint")
+            .about("Manage cross-tree constraints between features")
+            .disable_help_subcommand(true)
+            .arg_required_else_help(true)
+    }
+    fn get_subcommands(&self) -> Vec<Box<dyn CommandImpl>> {
+        vec![
+            Box::new(ConstraintAddCommand),
+            Box::new(ConstraintRemoveCommand),
+            Box::new(ConstraintListCommand),
+        ]
+    }
+}
+
+impl CommandInterface for ConstraintCommand {}
+
+#[derive(Clone, Debug)]
+pub struct ConstraintAddCommand;
+
+impl CommandDefinition for ConstraintAddCommand {
+    fn build_command(&self) -> Command {
+        constraint_args(
+            Command::new("add")
+                .about("Add a constraint")
+                .disable_help_subcommand(true),
+        )
+    }
+}
+
+impl CommandInterface for ConstraintAddCommand {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
+        let constraint = constraint_from_args(context, true)?;
+        let mut constraints = load_constraints(&context.git)?;
+        let feature_root = context.git.get_current_area()?.get_path_to_feature_root();
+        let display = constraint.display_relative_to(&feature_root);
+        if constraints.add(constraint) {
+            store_constraints(&context.git, &constraints)?;
+            context.info(format!("Added constraint {}", display));
+        } else {
+            context.info(format!("Constraint {} already exists", display));
+        }
+        Ok(())
+    }
+    fn shell_complete(
+        &self,
+        completion_helper: CompletionHelper,
+        context: &mut CommandContext,
+    ) -> Result<Vec<String>, TanglError> {
+        complete_constraint_args(completion_helper, context)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ConstraintRemoveCommand;
+
+impl CommandDefinition for ConstraintRemoveCommand {
+    fn build_command(&self) -> Command {
+        constraint_args(
+            Command::new("remove")
+                .about("Remove a constraint")
+                .disable_help_subcommand(true),
+        )
+    }
+}
+
+impl CommandInterface for ConstraintRemoveCommand {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
+        let constraint = constraint_from_args(context, false)?;
+        let mut constraints = load_constraints(&context.git)?;
+        let feature_root = context.git.get_current_area()?.get_path_to_feature_root();
+        let display = constraint.display_relative_to(&feature_root);
+        if !constraints.remove(&constraint) {
+            return Err(TanglError::usage(format!(
+                "Constraint {} does not exist",
+                display
+            )));
+        }
+        store_constraints(&context.git, &constraints)?;
+        context.info(format!("Removed constraint {}", display));
+        Ok(())
+    }
+    fn shell_complete(
+        &self,
+        completion_helper: CompletionHelper,
+        context: &mut CommandContext,
+    ) -> Result<Vec<String>, TanglError> {
+        complete_constraint_args(completion_helper, context)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ConstraintListCommand;
+
+impl CommandDefinition for ConstraintListCommand {
+    fn build_command(&self) -> Command {
+        Command::new("list")
+            .about("List all constraints of the current area")
+            .disable_help_subcommand(true)
+    }
+}
+
+impl CommandInterface for ConstraintListCommand {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
+        let constraints = load_constraints(&context.git)?;
+        let feature_root = context.git.get_current_area()?.get_path_to_feature_root();
+        let mut listed = Vec::new();
+        for constraint in constraints.iter_below(&feature_root) {
+            context.info(constraint.display_relative_to(&feature_root));
+            listed.push(json!({
+                "kind": constraint.kind(),
+                "from": constraint.source(),
+                "to": constraint.target(),
+            }));
+        }
+        if listed.is_empty() {
+            context.info("No constraints");
+        }
+        context.output("constraints", listed);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::commands::TangleCommand;
+    use crate::git::interface::GitPath;
+    use crate::git::interface::test_utils::{populate_with_features, prepare_empty_git_repo};
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_constraint_add_and_remove() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let repo = CommandRepository::new(
+            Box::new(ConstraintCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "constraint",
+            "add",
+            "root/foo",
+            "requires",
+            "root/bar",
+        ]))
+        .unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        let constraints = load_constraints(&interface).unwrap();
+        assert_eq!(
+            constraints.iter().collect::<Vec<_>>(),
+            vec![&Constraint::new(
+                ConstraintKind::Requires,
+                QualifiedPath::from("/main/feature/root/foo"),
+                QualifiedPath::from("/main/feature/root/bar"),
+            )]
+        );
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "constraint",
+            "remove",
+            "root/foo",
+            "requires",
+            "root/bar",
+        ]))
+        .unwrap();
+        assert!(load_constraints(&interface).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_constraint_remove_deleted_feature() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let repo = CommandRepository::new(
+            Box::new(ConstraintCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "constraint",
+            "add",
+            "root/foo",
+            "excludes",
+            "root/bar",
+        ]))
+        .unwrap();
+        std::process::Command::new("git")
+            .args(["branch", "-D", "_main/_feature/_root/bar"])
+            .current_dir(&path_buf)
+            .output()
+            .unwrap();
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "constraint",
+            "remove",
+            "root/foo",
+            "excludes",
+            "root/bar",
+        ]))
+        .unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        assert!(load_constraints(&interface).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_constraint_add_unknown_feature() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let repo = CommandRepository::new(
+            Box::new(ConstraintCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        match repo.execute(ArgSource::SUPPLIED(vec![
+            "constraint",
+            "add",
+            "root/foo",
+            "excludes",
+            "root/unknown",
+        ])) {
+            Ok(_) => panic!("Should fail"),
+            Err(_) => assert!(true),
+        }
+    }
+
+    #[test]
+    fn test_constraint_list_json_output() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let repo = CommandRepository::new(
+            Box::new(TangleCommand {}),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "tangl",
+            "constraint",
+            "add",
+            "root/foo",
+            "requires",
+            "root/bar",
+        ]))
+        .unwrap();
+        let (result, document) = repo.execute_with_output(ArgSource::SUPPLIED(vec![
+            "tangl",
+            "--output",
+            "json",
+            "constraint",
+            "list",
+        ]));
+        result.unwrap();
+        assert_eq!(
+            document.unwrap()["data"]["constraints"],
+            json!([{
+                "kind": "requires",
+                "from": "/main/feature/root/foo",
+                "to": "/main/feature/root/bar",
+            }])
+        );
+    }
+}
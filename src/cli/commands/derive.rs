@@ -1,30 +1,42 @@
 use crate::cli::completion::*;
 use crate::cli::*;
+use crate::error::TanglError;
 use crate::git::conflict::{ConflictChecker, ConflictStatistic, ConflictStatistics};
+use crate::git::interface::GitInterface;
+use crate::git::persistency::Document;
 use crate::model::{
-    ByQPathFilteringNodePathTransformer, ChainingNodePathTransformer,
+    ByQPathFilteringNodePathTransformer, ChainingNodePathTransformer, ConfigurationFormat,
+    ConfiguredFeature, DerivationRecord, DerivationRecords, DerivedFeature,
     HasBranchFilteringNodePathTransformer, NodePathTransformer, NodePathTransformers,
-    QPathFilteringMode, QualifiedPath,
+    ProductConfiguration, QPathFilteringMode, QualifiedPath, UVL_ROOT_NAME,
 };
+use crate::util::u8_to_string;
 use clap::{Arg, ArgAction, Command};
 use colored::Colorize;
 use petgraph::algo::maximal_cliques;
 use petgraph::graph::UnGraph;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
 use std::collections::HashMap;
-use std::error::Error;
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
 
 const FEATURES: &str = "features";
+const PRODUCT: &str = "product";
+const COMPLETE: &str = "complete";
+const RESOLVE: &str = "resolve";
+const CONTINUE: &str = "continue";
+const ABORT: &str = "abort";
+const CONFIG: &str = "config";
 
 fn map_paths_to_id(
-    paths: &Vec<QualifiedPath>,
+    paths: &[QualifiedPath],
 ) -> (HashMap<usize, QualifiedPath>, HashMap<QualifiedPath, usize>) {
     let mut id_to_path: HashMap<usize, QualifiedPath> = HashMap::new();
     let mut path_to_id: HashMap<QualifiedPath, usize> = HashMap::new();
-    let mut i = 0;
-    for path in paths.iter() {
+    for (i, path) in paths.iter().enumerate() {
         id_to_path.insert(i, path.clone());
         path_to_id.insert(path.clone(), i);
-        i += 1;
     }
     (id_to_path, path_to_id)
 }
@@ -37,8 +49,8 @@ fn build_edges(
         .iter_ok()
         .map(|element| match element {
             ConflictStatistic::OK((l, r)) => {
-                let left = path_to_id.get(l).unwrap().clone() as u32;
-                let right = path_to_id.get(r).unwrap().clone() as u32;
+                let left = *path_to_id.get(l).unwrap() as u32;
+                let right = *path_to_id.get(r).unwrap() as u32;
                 (left, right)
             }
             _ => unreachable!(),
@@ -46,12 +58,33 @@ fn build_edges(
         .collect()
 }
 
-fn get_max_clique(graph: &UnGraph<usize, ()>) -> Vec<usize> {
+/// Builds the compatibility graph, which contains a node for every path and
+/// an edge for every pair that merges without conflicts.
+fn build_graph(
+    conflict_data: &ConflictStatistics,
+    path_to_id: &HashMap<QualifiedPath, usize>,
+) -> UnGraph<usize, ()> {
+    let mut graph = UnGraph::<usize, ()>::default();
+    for id in 0..path_to_id.len() {
+        graph.add_node(id);
+    }
+    graph.extend_with_edges(build_edges(conflict_data, path_to_id));
+    graph
+}
+
+/// Returns the ids of the largest clique containing all required ids. Ties
+/// are broken in favor of lower ids, i.e. features selected first.
+fn get_max_clique(graph: &UnGraph<usize, ()>, required: &[usize]) -> Vec<usize> {
     let cliques = maximal_cliques(graph);
     let mut max_clique: Vec<usize> = Vec::new();
     for clique in cliques.iter() {
-        if clique.len() > max_clique.len() {
-            max_clique = clique.iter().map(|e| e.index()).collect();
+        let mut ids: Vec<usize> = clique.iter().map(|e| e.index()).collect();
+        if !required.iter().all(|id| ids.contains(id)) {
+            continue;
+        }
+        ids.sort();
+        if ids.len() > max_clique.len() || (ids.len() == max_clique.len() && ids < max_clique) {
+            max_clique = ids;
         }
     }
     max_clique
@@ -68,14 +101,161 @@ fn clique_to_paths(
     paths
 }
 
-fn make_post_derivation_message(features: &Vec<QualifiedPath>) -> String {
-    let mut base = "# DO NOT EDIT OR REMOVE THIS COMMIT\nDERIVATION FINISHED\n".to_string();
-    let strings = features
+/// Returns all paths a feature conflicts with.
+fn get_conflict_partners(
+    conflict_data: &ConflictStatistics,
+    feature: &QualifiedPath,
+) -> Vec<QualifiedPath> {
+    conflict_data
+        .iter_conflicts()
+        .filter_map(|element| match element {
+            ConflictStatistic::CONFLICT((l, r), _) if l == feature => Some(r.clone()),
+            ConflictStatistic::CONFLICT((l, r), _) if r == feature => Some(l.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+const LEGACY_DERIVATION_MARKER: &str = "DERIVATION FINISHED";
+const LEGACY_DERIVATION_HEADER: [&str; 2] = [
+    "# DO NOT EDIT OR REMOVE THIS COMMIT",
+    LEGACY_DERIVATION_MARKER,
+];
+
+impl Document for DerivationRecords {
+    const NAME: &'static str = "derivations";
+    const VERSION: u32 = 1;
+}
+
+fn extract_legacy_feature_names(message: &str) -> Vec<QualifiedPath> {
+    message
+        .trim()
+        .split("\n")
+        .filter(|line| !line.is_empty() && !LEGACY_DERIVATION_HEADER.contains(line))
+        .map(QualifiedPath::from)
+        .collect()
+}
+
+/// Builds a record from the "DERIVATION FINISHED" commit older versions of
+/// tangl created on the product branch.
+fn migrate_legacy_derivation(
+    git: &GitInterface,
+    product: &QualifiedPath,
+) -> Result<Option<DerivationRecord>, TanglError> {
+    let history = git.get_commit_history(product)?;
+    let legacy_commit = history
         .iter()
-        .map(|f| f.to_string())
-        .collect::<Vec<String>>();
-    base.push_str(strings.join("\n").as_str());
-    base
+        .find(|commit| commit.message().contains(LEGACY_DERIVATION_MARKER));
+    Ok(legacy_commit.map(|commit| {
+        let features = extract_legacy_feature_names(commit.message())
+            .into_iter()
+            .map(|path| DerivedFeature::new(path, None))
+            .collect();
+        DerivationRecord::new(product.clone(), features, commit.hash().clone())
+    }))
+}
+
+/// Returns the latest derivation record of a product. Products derived by
+/// older versions of tangl are migrated to a record on first access.
+pub fn load_derivation_record(
+    git: &GitInterface,
+    product: &QualifiedPath,
+) -> Result<Option<DerivationRecord>, TanglError> {
+    read_derivation_record(git, product, true)
+}
+
+/// Like [load_derivation_record], but only stores a migrated record if
+/// `store_migrated` is set.
+fn read_derivation_record(
+    git: &GitInterface,
+    product: &QualifiedPath,
+    store_migrated: bool,
+) -> Result<Option<DerivationRecord>, TanglError> {
+    let store = git.get_metadata_store();
+    let mut records = store.load::<DerivationRecords>()?;
+    if let Some(record) = records.latest(product) {
+        return Ok(Some(record.clone()));
+    }
+    let migrated = migrate_legacy_derivation(git, product)?;
+    if let Some(record) = migrated.as_ref()
+        && store_migrated
+    {
+        records.add(record.clone());
+        store.store(&records)?;
+    }
+    Ok(migrated)
+}
+
+pub fn store_derivation_record(
+    git: &GitInterface,
+    record: DerivationRecord,
+) -> Result<(), TanglError> {
+    Ok(git
+        .get_metadata_store()
+        .update(|records: &mut DerivationRecords| records.add(record))?)
+}
+
+pub fn remove_derivation_records(
+    git: &GitInterface,
+    product: &QualifiedPath,
+) -> Result<bool, TanglError> {
+    Ok(git
+        .get_metadata_store()
+        .update(|records: &mut DerivationRecords| records.remove(product))?)
+}
+
+/// Adds ancestors, mandatory features and, if `complete` is set, features
+/// required by constraints to the selection and validates the result against
+/// the groups and constraints of the feature model.
+fn complete_selection(
+    context: &CommandContext,
+    selected_features: &[QualifiedPath],
+    complete: bool,
+) -> Result<Vec<QualifiedPath>, TanglError> {
+    let feature_root = match context.git.get_current_area()?.to_feature_root() {
+        Some(path) => path,
+        None => return Err(TanglError::usage("Nothing to derive: no features exist")),
+    };
+    let feature_root_path = feature_root.get_qualified_path();
+    let constraints = load_constraints(&context.git)?;
+    let mut all_features = selected_features.to_vec();
+    loop {
+        let mut completed = feature_root.complete_selection(&all_features);
+        if complete {
+            completed = constraints.complete(&completed);
+        }
+        if completed.len() == all_features.len() {
+            break;
+        }
+        all_features = completed;
+    }
+    for added in all_features[selected_features.len()..].iter() {
+        context.info(format!(
+            "Adding required feature {}",
+            added.strip_n_left(feature_root_path.len())
+        ));
+    }
+
+    let group_violations = feature_root.check_groups(&all_features);
+    for violation in group_violations.iter() {
+        context.error(format!(
+            "Violated group: {}",
+            violation.display_relative_to(&feature_root_path)
+        ));
+    }
+    let violations = constraints.violations(&all_features);
+    for violation in violations.iter() {
+        context.error(format!(
+            "Violated constraint: {}",
+            violation.display_relative_to(&feature_root_path)
+        ));
+    }
+    if !group_violations.is_empty() || !violations.is_empty() {
+        return Err(TanglError::model(
+            "The selected features violate the feature model",
+        ));
+    }
+    Ok(all_features)
 }
 
 fn make_no_conflict_log() -> String {
@@ -86,6 +266,647 @@ fn make_conflict_log() -> String {
     "will produce conflicts".red().to_string()
 }
 
+/// Everything needed to derive a product or extend an existing one.
+struct DerivationPlan {
+    product_name: String,
+    target_path: QualifiedPath,
+    area_path: QualifiedPath,
+    feature_root_path: QualifiedPath,
+    current_path: QualifiedPath,
+    product_exists: bool,
+    previous: Option<DerivationRecord>,
+    new_features: Vec<QualifiedPath>,
+}
+
+/// Resolves the product and the features to derive. Returns `None` if the
+/// product already contains all features.
+fn plan_derivation(
+    context: &mut CommandContext,
+    product_name: String,
+    features: Vec<QualifiedPath>,
+    complete: bool,
+) -> Result<Option<DerivationPlan>, TanglError> {
+    let current_path = context.git.get_current_qualified_path()?;
+    let current_area = context.git.get_current_area()?;
+    let area_path = current_area.get_qualified_path();
+    let feature_root_path = current_area.get_path_to_feature_root();
+    let target_path =
+        current_area.get_path_to_product_root() + QualifiedPath::from(product_name.clone());
+    drop(current_area);
+
+    // an existing product is extended by the features it does not contain yet
+    let product_exists = context.git.get_model().has_branch(&target_path);
+    let previous = if product_exists {
+        match read_derivation_record(&context.git, &target_path, !context.is_dry_run())? {
+            Some(record) => Some(record),
+            None => {
+                return Err(TanglError::usage(format!(
+                    "Product {} exists, but was not derived by tangl",
+                    target_path
+                )));
+            }
+        }
+    } else {
+        None
+    };
+    let previous_features = previous
+        .as_ref()
+        .map(|record| record.feature_paths())
+        .unwrap_or_default();
+    let mut selected_features = previous_features.clone();
+    for path in features {
+        if !selected_features.contains(&path) {
+            selected_features.push(path);
+        }
+    }
+    let all_features = complete_selection(context, &selected_features, complete)?;
+    let new_features: Vec<QualifiedPath> = all_features
+        .into_iter()
+        .filter(|feature| !previous_features.contains(feature))
+        .collect();
+    if new_features.is_empty() {
+        context.info("All features are already part of the product");
+        context.output("product", &target_path);
+        context.output("merged", Vec::<QualifiedPath>::new());
+        return Ok(None);
+    }
+    Ok(Some(DerivationPlan {
+        product_name,
+        target_path,
+        area_path,
+        feature_root_path,
+        current_path,
+        product_exists,
+        previous,
+        new_features,
+    }))
+}
+
+/// A left-out feature and the features it conflicts with in combination.
+pub type CombinedConflict = (QualifiedPath, Vec<QualifiedPath>);
+
+/// Merges the whole selection in memory. Features that are pairwise
+/// compatible may still conflict when merged together; for every minimal
+/// conflicting subset, its last feature is left out until the rest merges
+/// cleanly. Returns the remaining selection and the left-out features with
+/// the features they conflict with in combination.
+pub fn trial_merge_selection(
+    context: &mut CommandContext,
+    mut selection: Vec<QualifiedPath>,
+    product: &QualifiedPath,
+) -> Result<(Vec<QualifiedPath>, Vec<CombinedConflict>), TanglError> {
+    let mut combined_conflicts = Vec::new();
+    if selection.len() < 3 {
+        return Ok((selection, combined_conflicts));
+    }
+    context.info("Merging the selection in memory");
+    let checker = ConflictChecker::new(&context.git).with_cache_writes(!context.is_dry_run());
+    while let Some(mut conflict) = checker.find_minimal_conflict(&selection)? {
+        let index = match conflict.iter().rposition(|path| path != product) {
+            Some(index) => index,
+            None => break,
+        };
+        let feature = conflict.remove(index);
+        selection.retain(|path| path != &feature);
+        combined_conflicts.push((feature, conflict));
+    }
+    Ok((selection, combined_conflicts))
+}
+
+/// Merges the largest set of conflict-free features into the product and
+/// reports the features that were left out.
+fn derive_conflict_free(
+    context: &mut CommandContext,
+    plan: DerivationPlan,
+) -> Result<(), TanglError> {
+    let DerivationPlan {
+        product_name,
+        target_path,
+        area_path,
+        feature_root_path,
+        current_path,
+        product_exists,
+        previous,
+        new_features,
+    } = plan;
+    context.info("Checking for conflicts");
+    let mut to_check = new_features.clone();
+    let mut required = vec![];
+    if product_exists {
+        to_check.insert(0, target_path.clone());
+        required.push(0);
+    }
+    let (id_to_path, path_to_id) = map_paths_to_id(&to_check);
+    let conflicts: ConflictStatistics = ConflictChecker::new(&context.git)
+        .with_cache_writes(!context.is_dry_run())
+        .check_all(&to_check)?
+        .collect();
+    if conflicts.n_errors() > 0 {
+        return Err(TanglError::git(
+            "Errors occurred while checking for conflicts.",
+        ));
+    }
+    let graph = build_graph(&conflicts, &path_to_id);
+    let max_clique = get_max_clique(&graph, &required);
+    let (selection, combined_conflicts) = trial_merge_selection(
+        context,
+        clique_to_paths(max_clique, &id_to_path),
+        &target_path,
+    )?;
+    let mergeable_features: Vec<QualifiedPath> = selection
+        .into_iter()
+        .filter(|path| path != &target_path)
+        .collect();
+    let left_out_features: Vec<QualifiedPath> = new_features
+        .iter()
+        .filter(|feature| !mergeable_features.contains(feature))
+        .cloned()
+        .collect();
+    let display_partner = |partner: QualifiedPath| {
+        if partner == target_path {
+            "the product".to_string()
+        } else {
+            partner.strip_n_left(feature_root_path.len()).to_string()
+        }
+    };
+    let mut left_out = vec![];
+    if !left_out_features.is_empty() {
+        context.info(
+            format!("Can merge {} features ", mergeable_features.len())
+                + make_no_conflict_log().as_str()
+                + ".",
+        );
+        context.info(
+            format!("{} features ", left_out_features.len()) + make_conflict_log().as_str() + ".",
+        );
+        for feature in left_out_features.iter() {
+            let (message, partners) = match combined_conflicts.iter().find(|(f, _)| f == feature) {
+                Some((_, others)) => (
+                    format!(
+                        "conflicts with {} combined",
+                        others
+                            .iter()
+                            .cloned()
+                            .map(display_partner)
+                            .collect::<Vec<String>>()
+                            .join(" and ")
+                    ),
+                    others.clone(),
+                ),
+                None => {
+                    let partners = get_conflict_partners(&conflicts, feature);
+                    (
+                        format!(
+                            "conflicts with {}",
+                            partners
+                                .iter()
+                                .cloned()
+                                .map(display_partner)
+                                .collect::<Vec<String>>()
+                                .join(", ")
+                        ),
+                        partners,
+                    )
+                }
+            };
+            left_out.push(json!({"feature": feature, "conflicts_with": partners}));
+            context.warn(format!(
+                "Leaving out {}: {}",
+                feature.strip_n_left(feature_root_path.len()),
+                message
+            ));
+        }
+    }
+    context.output("product", &target_path);
+    context.output("left_out", left_out);
+    if mergeable_features.is_empty() {
+        return Err(TanglError::conflicts(
+            "None of the features can be merged without conflicts",
+        ));
+    }
+
+    if context.is_dry_run() {
+        if !product_exists {
+            context.info(format!("Would create {} from {}", target_path, area_path));
+        }
+        context.info(format!("Would merge into {}:", target_path));
+        for (i, feature) in mergeable_features.iter().enumerate() {
+            context.info(format!(
+                "  {}. {}",
+                i + 1,
+                feature.strip_n_left(feature_root_path.len())
+            ));
+        }
+        context.info("Dry run, the repository was not changed.");
+        context.output("dry_run", true);
+        context.output("create", (!product_exists).then_some(&target_path));
+        context.output("merge", &mergeable_features);
+        return Ok(());
+    }
+    let derivation_commit = previous.as_ref().map(|record| record.commit().clone());
+    let mut derived_features = previous
+        .map(|record| record.features().clone())
+        .unwrap_or_default();
+    for feature in mergeable_features.iter() {
+        let commit = context.git.get_commit_hash(feature)?;
+        derived_features.push(DerivedFeature::new(feature.clone(), Some(commit)));
+    }
+    if !product_exists {
+        context.git.checkout(&area_path)?;
+        context.git.create_branch(&target_path)?;
+    }
+    context.git.checkout(&target_path)?;
+    let output = context.git.merge(&mergeable_features)?;
+    if !output.status.success() {
+        if context.git.is_merge_in_progress()? {
+            context.git.abort_merge()?;
+        }
+        context.git.checkout(&current_path)?;
+        if !product_exists {
+            context.git.delete_branch(&target_path)?;
+        }
+        return Err(TanglError::git(format!(
+            "Merging the features failed: {}",
+            u8_to_string(&output.stderr).trim()
+        )));
+    }
+    // extending a product keeps the commit of its first derivation
+    let commit = match derivation_commit {
+        Some(commit) => commit,
+        None => context.git.get_head_hash()?,
+    };
+    let record = DerivationRecord::new(target_path.clone(), derived_features, commit);
+    store_derivation_record(&context.git, record)?;
+    context.git.checkout(&current_path)?;
+    context.output("merged", &mergeable_features);
+    if left_out_features.is_empty() {
+        context.info("Derivation finished ".to_string() + make_no_conflict_log().as_str() + ".");
+    } else {
+        let missing = left_out_features
+            .iter()
+            .map(|feature| feature.strip_n_left(feature_root_path.len()).to_string())
+            .collect::<Vec<String>>();
+        context.info("Partial derivation finished.");
+        context.info(format!(
+            "Add the missing features after resolving their conflicts with: tangl derive -p {} {}",
+            product_name,
+            missing.join(" ")
+        ));
+    }
+    Ok(())
+}
+
+/// An interactive derivation that merges features one at a time and stops
+/// at conflicts until it is continued or aborted.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct DerivationState {
+    product: QualifiedPath,
+    original_branch: QualifiedPath,
+    /// The commit of the product before the derivation, `None` if the
+    /// derivation created the product.
+    base_commit: Option<String>,
+    /// The commit of the first derivation if an existing product is extended.
+    #[serde(default)]
+    derivation_commit: Option<String>,
+    features: Vec<DerivedFeature>,
+    pending: Vec<QualifiedPath>,
+    conflicting: Option<DerivedFeature>,
+}
+
+impl Document for DerivationState {
+    const NAME: &'static str = "derivation_state";
+    const VERSION: u32 = 1;
+}
+
+fn load_derivation_state(context: &CommandContext) -> Result<DerivationState, TanglError> {
+    let store = context.git.get_metadata_store();
+    if !store.exists::<DerivationState>()? {
+        return Err(TanglError::usage("No derivation in progress"));
+    }
+    Ok(store.load()?)
+}
+
+fn start_resolving_derivation(
+    context: &mut CommandContext,
+    plan: DerivationPlan,
+) -> Result<(), TanglError> {
+    let base_commit = if plan.product_exists {
+        Some(context.git.get_commit_hash(&plan.target_path)?)
+    } else {
+        context.git.checkout(&plan.area_path)?;
+        context.git.create_branch(&plan.target_path)?;
+        None
+    };
+    context.git.checkout(&plan.target_path)?;
+    let state = DerivationState {
+        product: plan.target_path,
+        original_branch: plan.current_path,
+        base_commit,
+        derivation_commit: plan.previous.as_ref().map(|record| record.commit().clone()),
+        features: plan
+            .previous
+            .map(|record| record.features().clone())
+            .unwrap_or_default(),
+        pending: plan.new_features,
+        conflicting: None,
+    };
+    merge_pending_features(context, state)
+}
+
+/// Merges the pending features one by one. Persists the state after every
+/// merge and stops at the first conflict.
+fn merge_pending_features(
+    context: &mut CommandContext,
+    mut state: DerivationState,
+) -> Result<(), TanglError> {
+    let store = context.git.get_metadata_store();
+    let feature_root_path = context.git.get_current_area()?.get_path_to_feature_root();
+    while !state.pending.is_empty() {
+        let feature = state.pending.remove(0);
+        let relative = feature.strip_n_left(feature_root_path.len());
+        let derived = DerivedFeature::new(
+            feature.clone(),
+            Some(context.git.get_commit_hash(&feature)?),
+        );
+        let output = context.git.merge(&vec![feature.clone()])?;
+        if output.status.success() {
+            context.info(format!("Merged {}", relative));
+            state.features.push(derived);
+            store.store(&state)?;
+            continue;
+        }
+        if !context.git.is_merge_in_progress()? {
+            state.pending.insert(0, feature);
+            store.store(&state)?;
+            return Err(TanglError::git(format!(
+                "Merging {} failed: {}",
+                relative,
+                u8_to_string(&output.stderr).trim()
+            )));
+        }
+        state.conflicting = Some(derived);
+        store.store(&state)?;
+        context.warn(format!(
+            "Merging {} {} in:",
+            relative,
+            "produced conflicts".red()
+        ));
+        let files = context.git.get_unmerged_files()?;
+        for file in files.iter() {
+            context.warn(format!("  {}", file));
+        }
+        context.output("product", &state.product);
+        context.output("finished", false);
+        context.output("conflicting", &feature);
+        context.output("files", files);
+        context.info(
+            "Resolve the conflicts and stage the files, then run 'tangl derive --continue'. \
+            Run 'tangl derive --abort' to cancel the derivation.",
+        );
+        return Ok(());
+    }
+    let commit = match state.derivation_commit {
+        Some(commit) => commit,
+        None => context.git.get_head_hash()?,
+    };
+    let record = DerivationRecord::new(state.product.clone(), state.features, commit);
+    context.output("product", &state.product);
+    context.output("finished", true);
+    context.output("features", record.feature_paths());
+    store_derivation_record(&context.git, record)?;
+    store.remove::<DerivationState>()?;
+    context.git.checkout(&state.original_branch)?;
+    context.info("Derivation finished.");
+    Ok(())
+}
+
+fn continue_derivation(context: &mut CommandContext) -> Result<(), TanglError> {
+    let mut state = load_derivation_state(context)?;
+    if context.git.get_current_qualified_path()? != state.product {
+        return Err(TanglError::usage(format!(
+            "Check out {} to continue the derivation",
+            state.product
+        )));
+    }
+    if let Some(conflicting) = state.conflicting.take() {
+        let unmerged = context.git.get_unmerged_files()?;
+        if !unmerged.is_empty() {
+            return Err(TanglError::conflicts(format!(
+                "Unresolved conflicts remain in {}",
+                unmerged.join(", ")
+            )));
+        }
+        if context.git.is_merge_in_progress()? {
+            let output = context.git.commit_merge()?;
+            if !output.status.success() {
+                return Err(TanglError::git(format!(
+                    "Cannot commit the merge: {}",
+                    u8_to_string(&output.stdout).trim()
+                )));
+            }
+            state.features.push(conflicting);
+        } else if context
+            .git
+            .is_ancestor(conflicting.path(), &state.product)?
+        {
+            // the user committed the merge already
+            state.features.push(conflicting);
+        } else {
+            let feature_root_path = context.git.get_current_area()?.get_path_to_feature_root();
+            context.warn(format!(
+                "{} is not merged, merging it again",
+                conflicting.path().strip_n_left(feature_root_path.len())
+            ));
+            state.pending.insert(0, conflicting.path().clone());
+        }
+    }
+    merge_pending_features(context, state)
+}
+
+fn abort_derivation(context: &mut CommandContext) -> Result<(), TanglError> {
+    let state = load_derivation_state(context)?;
+    if context.git.is_merge_in_progress()? {
+        context.git.abort_merge()?;
+    }
+    if let Some(base_commit) = state.base_commit.as_ref() {
+        context.git.checkout(&state.product)?;
+        context.git.reset_hard(base_commit)?;
+    }
+    context.git.checkout(&state.original_branch)?;
+    if state.base_commit.is_none() {
+        context.git.delete_branch(&state.product)?;
+    }
+    context
+        .git
+        .get_metadata_store()
+        .remove::<DerivationState>()?;
+    context.info(format!("Aborted the derivation of {}", state.product));
+    context.output("aborted", &state.product);
+    Ok(())
+}
+
+/// Reads a configuration file, or all configuration files of a directory
+/// ordered by file name.
+fn load_configurations(path: &Path) -> Result<Vec<ProductConfiguration>, TanglError> {
+    if !path.is_dir() {
+        return Ok(vec![load_configuration(path)?]);
+    }
+    let mut files: Vec<PathBuf> = read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    files.retain(|file| file.is_file() && ConfigurationFormat::from_file_extension(file).is_some());
+    files.sort();
+    if files.is_empty() {
+        return Err(TanglError::usage(format!(
+            "No configurations found in {}",
+            path.display()
+        )));
+    }
+    files.iter().map(|file| load_configuration(file)).collect()
+}
+
+fn load_configuration(path: &Path) -> Result<ProductConfiguration, TanglError> {
+    let format = ConfigurationFormat::from_file_extension(path).ok_or_else(|| {
+        TanglError::usage(format!(
+            "Unsupported configuration {}, expected a .json or .uvl-config file",
+            path.display()
+        ))
+    })?;
+    let data = read_to_string(path)?;
+    let default_name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    ProductConfiguration::parse(&data, format, &default_name)
+        .map_err(|err| TanglError::model(format!("Cannot read {}: {}", path.display(), err)))
+}
+
+/// Maps the features of a configuration to the feature branches they refer
+/// to. Features selected by name must have a unique name; the abstract
+/// features the UVL export adds for areas are ignored.
+fn resolve_configuration(
+    context: &CommandContext,
+    configuration: &ProductConfiguration,
+) -> Result<Vec<QualifiedPath>, TanglError> {
+    let area = context.git.get_current_area()?;
+    let area_name = area.get_name().clone();
+    let feature_root = match area.to_feature_root() {
+        Some(path) => path,
+        None => return Err(TanglError::usage("Nothing to derive: no features exist")),
+    };
+    let feature_root_path = feature_root.get_qualified_path();
+    let mut features = vec![];
+    for feature in configuration.features() {
+        let path = match feature {
+            ConfiguredFeature::Path(path) => feature_root_path.clone() + path.clone(),
+            ConfiguredFeature::Name(name) => {
+                let matches: Vec<QualifiedPath> = feature_root
+                    .iter_children_req()
+                    .filter(|path| path.get_name() == name && path.get_metadata().has_branch())
+                    .map(|path| path.get_qualified_path())
+                    .collect();
+                match matches.len() {
+                    0 if name == &area_name || name == UVL_ROOT_NAME => continue,
+                    0 => {
+                        return Err(TanglError::invalid_path(format!(
+                            "Feature {} does not exist",
+                            name
+                        )));
+                    }
+                    1 => matches[0].clone(),
+                    _ => {
+                        return Err(TanglError::model(format!(
+                            "Feature name {} is ambiguous: {}",
+                            name,
+                            matches
+                                .iter()
+                                .map(|path| path.strip_n_left(feature_root_path.len()).to_string())
+                                .collect::<Vec<String>>()
+                                .join(", ")
+                        )));
+                    }
+                }
+            }
+        };
+        if !context.git.get_model().has_branch(&path) {
+            return Err(TanglError::invalid_path(format!(
+                "Feature {} does not exist",
+                path
+            )));
+        }
+        features.push(path);
+    }
+    Ok(features)
+}
+
+fn derive(
+    context: &mut CommandContext,
+    product_name: String,
+    features: Vec<QualifiedPath>,
+    complete: bool,
+    resolve: bool,
+) -> Result<(), TanglError> {
+    let plan = match plan_derivation(context, product_name, features, complete)? {
+        Some(plan) => plan,
+        None => return Ok(()),
+    };
+    if context.is_dry_run() {
+        return derive_conflict_free(context, plan);
+    }
+    with_clean_working_tree(context, |context| {
+        if resolve {
+            start_resolving_derivation(context, plan)
+        } else {
+            derive_conflict_free(context, plan)
+        }
+    })
+}
+
+/// Derives every configuration of a directory. A failed derivation is
+/// reported and does not stop the remaining ones.
+fn derive_batch(
+    context: &mut CommandContext,
+    configurations: Vec<ProductConfiguration>,
+    complete: bool,
+) -> Result<(), TanglError> {
+    let n_configurations = configurations.len();
+    let mut derivations = vec![];
+    let mut errors = vec![];
+    for configuration in configurations {
+        context.info(format!("Deriving {}", configuration.name()));
+        let result = resolve_configuration(context, &configuration).and_then(|features| {
+            derive(
+                context,
+                configuration.name().clone(),
+                features,
+                complete,
+                false,
+            )
+        });
+        let mut output = context.take_output();
+        output.insert("success".to_string(), Value::Bool(result.is_ok()));
+        if let Err(err) = result {
+            context.error(format!("Cannot derive {}: {}", configuration.name(), err));
+            output.insert("error".to_string(), Value::String(err.to_string()));
+            errors.push(err);
+        }
+        derivations.push(Value::Object(output));
+    }
+    context.output("derivations", derivations);
+    if errors.is_empty() {
+        if !context.is_dry_run() {
+            context.info(format!("Derived {} products", n_configurations));
+        }
+        return Ok(());
+    }
+    context.error(format!(
+        "{} of {} products could not be derived",
+        errors.len(),
+        n_configurations
+    ));
+    Err(errors.remove(0))
+}
+
 #[derive(Clone, Debug)]
 pub struct DeriveCommand;
 
@@ -94,82 +915,114 @@ impl CommandDefinition for DeriveCommand {
         Command::new("derive")
             .about("Derive a product")
             .disable_help_subcommand(true)
-            .arg(Arg::new(FEATURES).action(ArgAction::Append).required(true))
             .arg(
-                Arg::new("product")
+                Arg::new(FEATURES)
+                    .action(ArgAction::Append)
+                    .required_unless_present_any([CONTINUE, ABORT, CONFIG]),
+            )
+            .arg(
+                Arg::new(PRODUCT)
                     .short('p')
-                    .required(true)
-                    .help("Specifies the name of the resulting product branch"),
+                    .required_unless_present_any([CONTINUE, ABORT, CONFIG])
+                    .help("Specifies the name of the resulting product branch. An existing product is extended by the features it does not contain yet"),
+            )
+            .arg(
+                Arg::new(COMPLETE)
+                    .long("complete")
+                    .action(ArgAction::SetTrue)
+                    .help("Add all features required by constraints to the selection"),
+            )
+            .arg(
+                Arg::new(RESOLVE)
+                    .long("resolve")
+                    .action(ArgAction::SetTrue)
+                    .help("Merge the features one at a time and stop at conflicts to resolve them"),
+            )
+            .arg(
+                Arg::new(CONFIG)
+                    .long("config")
+                    .conflicts_with_all([FEATURES, PRODUCT])
+                    .help("Derive the product of a configuration file (.json or .uvl-config), or of every configuration file in a directory"),
+            )
+            .arg(dry_run().conflicts_with_all([RESOLVE, CONTINUE, ABORT]))
+            .arg(autostash().conflicts_with_all([CONTINUE, ABORT]))
+            .arg(
+                Arg::new(CONTINUE)
+                    .long("continue")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with_all([FEATURES, PRODUCT, CONFIG, RESOLVE, ABORT])
+                    .help("Continue a derivation after resolving conflicts"),
+            )
+            .arg(
+                Arg::new(ABORT)
+                    .long("abort")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with_all([FEATURES, PRODUCT, CONFIG, RESOLVE])
+                    .help("Abort a derivation and restore the previous state"),
             )
     }
 }
 
 impl CommandInterface for DeriveCommand {
-    fn run_command(&self, context: &mut CommandContext) -> Result<(), Box<dyn Error>> {
-        let target_product_name = context
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
+        let flag = |id: &str| context.arg_helper.get_argument_value::<bool>(id) == Some(true);
+        if flag(ABORT) {
+            return abort_derivation(context);
+        }
+        if flag(CONTINUE) {
+            return continue_derivation(context);
+        }
+        let resolve = flag(RESOLVE);
+        if context
+            .git
+            .get_metadata_store()
+            .exists::<DerivationState>()?
+        {
+            return Err(TanglError::usage(
+                "A derivation is in progress, use --continue to finish or --abort to cancel it",
+            ));
+        }
+        let complete = flag(COMPLETE);
+        if let Some(config) = context.arg_helper.get_argument_value::<String>(CONFIG) {
+            let config = PathBuf::from(config);
+            let mut configurations = load_configurations(&config)?;
+            if config.is_dir() {
+                if resolve {
+                    return Err(TanglError::usage(
+                        "--resolve derives a single product and cannot be used with a directory",
+                    ));
+                }
+                return derive_batch(context, configurations, complete);
+            }
+            let configuration = configurations.remove(0);
+            let features = resolve_configuration(context, &configuration)?;
+            return derive(
+                context,
+                configuration.name().clone(),
+                features,
+                complete,
+                resolve,
+            );
+        }
+        let product_name = context
             .arg_helper
-            .get_argument_value::<String>("product")
+            .get_argument_value::<String>(PRODUCT)
             .unwrap();
-        let current_path = context.git.get_current_qualified_path()?;
-        let current_area = context.git.get_current_area()?;
-        let target_path =
-            current_area.get_path_to_product_root() + QualifiedPath::from(target_product_name);
-
-        let all_features = context
+        let feature_root_path = context.git.get_current_area()?.get_path_to_feature_root();
+        let features = context
             .arg_helper
             .get_argument_values::<String>(FEATURES)
             .unwrap()
             .into_iter()
-            .map(|e| current_area.get_path_to_feature_root() + QualifiedPath::from(e))
-            .collect::<Vec<_>>();
-
-        context.info("Checking for conflicts");
-        let (id_to_path, path_to_id) = map_paths_to_id(&all_features);
-        let conflicts: ConflictStatistics = ConflictChecker::new(&context.git)
-            .check_all(&all_features)?
+            .map(|feature| feature_root_path.clone() + QualifiedPath::from(feature))
             .collect();
-        if conflicts.n_errors() > 0 {
-            return Err("Errors occurred while checking for conflicts.".into());
-        }
-        let edges = build_edges(&conflicts, &path_to_id);
-        let graph = UnGraph::<usize, ()>::from_edges(&edges);
-        let max_clique = get_max_clique(&graph);
-        let mergeable_features = clique_to_paths(max_clique, &id_to_path);
-        if mergeable_features.len() == all_features.len() {
-            let area_path = current_area.get_qualified_path();
-            drop(current_area);
-            context.git.checkout(&area_path)?;
-            context.git.create_branch(&target_path)?;
-            context.git.checkout(&target_path)?;
-            context.git.merge(&all_features)?;
-            context
-                .git
-                .empty_commit(make_post_derivation_message(&all_features).as_str())?;
-            context.git.checkout(&current_path)?;
-            context
-                .info("Derivation finished ".to_string() + make_no_conflict_log().as_str() + ".");
-        } else {
-            context.info(
-                format!("Can merge {} features ", mergeable_features.len())
-                    + make_no_conflict_log().as_str()
-                    + ".",
-            );
-            context.info(
-                format!(
-                    "{} features ",
-                    all_features.len() - mergeable_features.len()
-                ) + make_conflict_log().as_str()
-                    + ".",
-            );
-            context.info("A partial derivation will be performed with all conflict-free features.")
-        }
-        Ok(())
+        derive(context, product_name, features, complete, resolve)
     }
     fn shell_complete(
         &self,
         completion_helper: CompletionHelper,
         context: &mut CommandContext,
-    ) -> Result<Vec<String>, Box<dyn Error>> {
+    ) -> Result<Vec<String>, TanglError> {
         let maybe_feature_root = context.git.get_current_area()?.to_feature_root();
         if maybe_feature_root.is_none() {
             return Ok(vec![]);
@@ -214,9 +1067,15 @@ impl CommandInterface for DeriveCommand {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::git::interface::test_utils::{populate_with_features, prepare_empty_git_repo};
+    use crate::git::conflict::{ConflictCache, MergeResult};
+    use crate::git::interface::test_utils::{
+        commit_file_on_branch, populate_with_features, prepare_empty_git_repo,
+    };
     use crate::git::interface::{GitInterface, GitPath};
-    use crate::model::NodePathProductNavigation;
+    use crate::model::{
+        Constraint, ConstraintKind, GroupKind, NodePathProductNavigation, Variability,
+    };
+    use std::fs;
     use std::path::PathBuf;
     use tempfile::TempDir;
 
@@ -233,7 +1092,7 @@ mod tests {
             "derive", "-p", "myprod", "root/foo", "root/bar", "root/baz",
         ])) {
             Ok(_) => {
-                let interface = GitInterface::in_directory(PathBuf::from(path.path()));
+                let interface = GitInterface::in_directory(PathBuf::from(path.path())).unwrap();
                 interface
                     .get_current_area()
                     .unwrap()
@@ -247,7 +1106,104 @@ mod tests {
     }
 
     #[test]
-    fn test_derivation_commit() {
+    fn test_derivation_constraints() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        let mut constraints = load_constraints(&interface).unwrap();
+        constraints.add(Constraint::new(
+            ConstraintKind::Requires,
+            QualifiedPath::from("/main/feature/root/foo"),
+            QualifiedPath::from("/main/feature/root/bar"),
+        ));
+        constraints.add(Constraint::new(
+            ConstraintKind::Excludes,
+            QualifiedPath::from("/main/feature/root/bar"),
+            QualifiedPath::from("/main/feature/root/baz"),
+        ));
+        store_constraints(&interface, &constraints).unwrap();
+        let repo = CommandRepository::new(
+            Box::new(DeriveCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        match repo.execute(ArgSource::SUPPLIED(vec![
+            "derive", "-p", "myprod", "root/foo",
+        ])) {
+            Ok(_) => panic!("Should fail"),
+            Err(_) => assert!(true),
+        }
+        match repo.execute(ArgSource::SUPPLIED(vec![
+            "derive",
+            "-p",
+            "myprod",
+            "--complete",
+            "root/foo",
+            "root/baz",
+        ])) {
+            Ok(_) => panic!("Should fail"),
+            Err(_) => assert!(true),
+        }
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "derive",
+            "-p",
+            "myprod",
+            "--complete",
+            "root/foo",
+        ]))
+        .unwrap();
+        let interface = GitInterface::in_directory(path_buf).unwrap();
+        assert!(
+            interface
+                .get_model()
+                .has_branch(&QualifiedPath::from("/main/product/myprod"))
+        );
+    }
+
+    #[test]
+    fn test_derivation_groups() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let mut interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        interface
+            .update_variability(vec![
+                (
+                    QualifiedPath::from("/main/feature/root"),
+                    Variability::new(false, GroupKind::Alternative),
+                ),
+                (
+                    QualifiedPath::from("/main/feature/root/baz"),
+                    Variability::new(true, GroupKind::And),
+                ),
+            ])
+            .unwrap();
+        let repo = CommandRepository::new(
+            Box::new(DeriveCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        match repo.execute(ArgSource::SUPPLIED(vec![
+            "derive", "-p", "myprod", "root/foo", "root/bar",
+        ])) {
+            Ok(_) => panic!("Should fail"),
+            Err(_) => assert!(true),
+        }
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "derive", "-p", "myprod", "root/foo",
+        ]))
+        .unwrap();
+        let interface = GitInterface::in_directory(path_buf).unwrap();
+        assert!(
+            interface
+                .get_model()
+                .has_branch(&QualifiedPath::from("/main/product/myprod"))
+        );
+    }
+
+    #[test]
+    fn test_derivation_record() {
         let path = TempDir::new().unwrap();
         prepare_empty_git_repo(PathBuf::from(path.path())).unwrap();
         populate_with_features(PathBuf::from(path.path())).unwrap();
@@ -255,32 +1211,532 @@ mod tests {
             Box::new(DeriveCommand),
             GitPath::CustomDirectory(PathBuf::from(path.path())),
         );
-        match repo.execute(ArgSource::SUPPLIED(vec![
+        repo.execute(ArgSource::SUPPLIED(vec![
             "derive", "-p", "myprod", "root/foo", "root/bar", "root/baz",
-        ])) {
-            Ok(_) => {
-                let interface = GitInterface::in_directory(PathBuf::from(path.path()));
-                let product = interface
-                    .get_current_area()
-                    .unwrap()
-                    .to_product_root()
-                    .unwrap()
-                    .to_product(&QualifiedPath::from("myprod"))
-                    .unwrap();
-                let commits = interface
-                    .get_commit_history(&product.get_qualified_path())
-                    .unwrap();
-                let derivation_commit = commits[0].clone();
-                assert_eq!(
-                    derivation_commit.message(),
-                    &make_post_derivation_message(&vec![
-                        QualifiedPath::from("/main/feature/root/foo"),
-                        QualifiedPath::from("/main/feature/root/bar"),
-                        QualifiedPath::from("/main/feature/root/baz"),
-                    ]),
-                )
-            }
-            Err(e) => panic!("{}", e),
+        ]))
+        .unwrap();
+        let interface = GitInterface::in_directory(PathBuf::from(path.path())).unwrap();
+        let product = QualifiedPath::from("/main/product/myprod");
+        let record = load_derivation_record(&interface, &product)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            record.feature_paths(),
+            vec![
+                QualifiedPath::from("/main/feature/root/foo"),
+                QualifiedPath::from("/main/feature/root/bar"),
+                QualifiedPath::from("/main/feature/root/baz"),
+                QualifiedPath::from("/main/feature/root"),
+            ]
+        );
+        for feature in record.features() {
+            assert_eq!(
+                feature.commit().unwrap(),
+                &interface.get_commit_hash(feature.path()).unwrap()
+            );
+        }
+        assert_eq!(
+            record.commit(),
+            &interface.get_commit_hash(&product).unwrap()
+        );
+        assert_eq!(record.tangl_version(), env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn test_legacy_derivation_migration() {
+        let path = TempDir::new().unwrap();
+        prepare_empty_git_repo(PathBuf::from(path.path())).unwrap();
+        populate_with_features(PathBuf::from(path.path())).unwrap();
+        let mut interface = GitInterface::in_directory(PathBuf::from(path.path())).unwrap();
+        let product = QualifiedPath::from("/main/product/legacy");
+        interface.create_branch(&product).unwrap();
+        interface.checkout(&product).unwrap();
+        interface
+            .empty_commit(
+                "# DO NOT EDIT OR REMOVE THIS COMMIT\nDERIVATION FINISHED\n/main/feature/root/foo",
+            )
+            .unwrap();
+        let legacy_commit = interface.get_head_hash().unwrap();
+        interface.empty_commit("product change").unwrap();
+        let record = read_derivation_record(&interface, &product, false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.commit(), &legacy_commit);
+        assert!(
+            !interface
+                .get_metadata_store()
+                .exists::<DerivationRecords>()
+                .unwrap()
+        );
+        let record = load_derivation_record(&interface, &product)
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.commit(), &legacy_commit);
+        assert_eq!(
+            record.features(),
+            &vec![DerivedFeature::new(
+                QualifiedPath::from("/main/feature/root/foo"),
+                None
+            )]
+        );
+        assert!(
+            interface
+                .get_metadata_store()
+                .load::<DerivationRecords>()
+                .unwrap()
+                .latest(&product)
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_derivation_from_configuration() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let configs = TempDir::new().unwrap();
+        let config = configs.path().join("myprod.json");
+        std::fs::write(&config, r#"{"features": ["root/foo"]}"#).unwrap();
+        let repo = CommandRepository::new(
+            Box::new(DeriveCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "derive",
+            "--config",
+            config.to_str().unwrap(),
+        ]))
+        .unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        let record =
+            load_derivation_record(&interface, &QualifiedPath::from("/main/product/myprod"))
+                .unwrap()
+                .unwrap();
+        assert_eq!(
+            record.feature_paths(),
+            vec![
+                QualifiedPath::from("/main/feature/root/foo"),
+                QualifiedPath::from("/main/feature/root"),
+            ]
+        );
+
+        std::fs::write(&config, r#"{"features": ["root/missing"]}"#).unwrap();
+        let result = repo.execute(ArgSource::SUPPLIED(vec![
+            "derive",
+            "--config",
+            config.to_str().unwrap(),
+        ]));
+        assert_eq!(result.unwrap_err().exit_code(), 3);
+    }
+
+    #[test]
+    fn test_derivation_batch() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let configs = TempDir::new().unwrap();
+        std::fs::write(
+            configs.path().join("a.uvl-config"),
+            r#"{"file": "model.uvl", "config": {"main": true, "root": true, "bar": true, "baz": false}}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            configs.path().join("b.json"),
+            r#"{"name": "other", "features": ["root/baz"]}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            configs.path().join("c.uvl-config"),
+            r#"{"config": {"unknown": true}}"#,
+        )
+        .unwrap();
+        std::fs::write(configs.path().join("notes.txt"), "not a configuration").unwrap();
+        let repo = CommandRepository::new(
+            Box::new(DeriveCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        let result = repo.execute(ArgSource::SUPPLIED(vec![
+            "derive",
+            "--config",
+            configs.path().to_str().unwrap(),
+        ]));
+        assert_eq!(result.unwrap_err().exit_code(), 3);
+
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        let model = interface.get_model();
+        assert!(model.has_branch(&QualifiedPath::from("/main/product/a")));
+        assert!(model.has_branch(&QualifiedPath::from("/main/product/other")));
+        assert!(!model.has_branch(&QualifiedPath::from("/main/product/c")));
+        let record = load_derivation_record(&interface, &QualifiedPath::from("/main/product/a"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            record.feature_paths(),
+            vec![
+                QualifiedPath::from("/main/feature/root/bar"),
+                QualifiedPath::from("/main/feature/root"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_derivation_dry_run() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/foo", "file1", "foo")
+            .unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/bar", "file1", "bar")
+            .unwrap();
+        let repo = CommandRepository::new(
+            Box::new(TangleCommand {}),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        let (result, document) = repo.execute_with_output(ArgSource::SUPPLIED(vec![
+            "tangl",
+            "--output",
+            "json",
+            "derive",
+            "--dry-run",
+            "-p",
+            "myprod",
+            "root/foo",
+            "root/bar",
+        ]));
+        result.unwrap();
+        let data = &document.unwrap()["data"];
+        assert_eq!(data["create"], "/main/product/myprod");
+        assert_eq!(
+            data["merge"],
+            json!(["/main/feature/root/foo", "/main/feature/root"])
+        );
+        assert_eq!(data["left_out"][0]["feature"], "/main/feature/root/bar");
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        assert!(
+            !interface
+                .get_model()
+                .has_branch(&QualifiedPath::from("/main/product/myprod"))
+        );
+        assert!(
+            interface
+                .get_metadata_store()
+                .load::<DerivationRecords>()
+                .unwrap()
+                .iter()
+                .next()
+                .is_none()
+        );
+        assert!(
+            !interface
+                .get_metadata_store()
+                .exists::<ConflictCache>()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_derivation_single_feature() {
+        let path = TempDir::new().unwrap();
+        prepare_empty_git_repo(PathBuf::from(path.path())).unwrap();
+        populate_with_features(PathBuf::from(path.path())).unwrap();
+        let repo = CommandRepository::new(
+            Box::new(DeriveCommand),
+            GitPath::CustomDirectory(PathBuf::from(path.path())),
+        );
+        repo.execute(ArgSource::SUPPLIED(vec!["derive", "-p", "myprod", "root"]))
+            .unwrap();
+        let interface = GitInterface::in_directory(PathBuf::from(path.path())).unwrap();
+        assert!(
+            interface
+                .get_model()
+                .has_branch(&QualifiedPath::from("/main/product/myprod"))
+        );
+    }
+
+    #[test]
+    fn test_failed_derivation_removes_product() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/foo", "file2", "foo")
+            .unwrap();
+        // git refuses to overwrite the untracked file while merging foo
+        fs::write(path_buf.join("file2"), "untracked").unwrap();
+        let repo = CommandRepository::new(
+            Box::new(DeriveCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        let result = repo.execute(ArgSource::SUPPLIED(vec![
+            "derive", "-p", "myprod", "root", "root/foo",
+        ]));
+        assert_eq!(result.unwrap_err().exit_code(), 5);
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        assert!(
+            !interface
+                .get_model()
+                .has_branch(&QualifiedPath::from("/main/product/myprod"))
+        );
+        assert_eq!(
+            interface.get_current_qualified_path().unwrap(),
+            QualifiedPath::from("/main")
+        );
+    }
+
+    #[test]
+    fn test_partial_derivation() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/foo", "file1", "foo")
+            .unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/bar", "file1", "bar")
+            .unwrap();
+        let repo = CommandRepository::new(
+            Box::new(DeriveCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "derive", "-p", "myprod", "root/foo", "root/bar", "root/baz",
+        ]))
+        .unwrap();
+        let product = QualifiedPath::from("/main/product/myprod");
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        let record = load_derivation_record(&interface, &product)
+            .unwrap()
+            .unwrap();
+        let derivation_commit = record.commit().clone();
+        assert_eq!(
+            record.feature_paths(),
+            vec![
+                QualifiedPath::from("/main/feature/root/foo"),
+                QualifiedPath::from("/main/feature/root/baz"),
+                QualifiedPath::from("/main/feature/root"),
+            ]
+        );
+        assert_eq!(
+            interface.get_current_qualified_path().unwrap(),
+            QualifiedPath::from("/main")
+        );
+
+        // still conflicting with the product
+        assert!(
+            repo.execute(ArgSource::SUPPLIED(vec![
+                "derive", "-p", "myprod", "root/bar"
+            ]))
+            .is_err()
+        );
+        // resolve the conflict on the feature and add it to the product
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/bar", "file1", "foo")
+            .unwrap();
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "derive", "-p", "myprod", "root/bar",
+        ]))
+        .unwrap();
+        let record = load_derivation_record(&interface, &product)
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.features().len(), 4);
+        // extending keeps the commit of the first derivation
+        assert_eq!(record.commit(), &derivation_commit);
+    }
+
+    #[test]
+    fn test_derivation_leaves_out_combined_conflict() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        for feature in ["foo", "bar", "baz"] {
+            let branch = format!("_main/_feature/_root/{}", feature);
+            commit_file_on_branch(path_buf.clone(), &branch, feature, feature).unwrap();
         }
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        // git rarely produces conflicts that no pair shows, so fake one
+        let commits: Vec<String> = ["root/foo", "root/bar", "root/baz", "root"]
+            .into_iter()
+            .map(|f| {
+                let path = QualifiedPath::from(format!("/main/feature/{}", f));
+                interface.get_commit_hash(&path).unwrap()
+            })
+            .collect();
+        interface
+            .get_metadata_store()
+            .update(|cache: &mut ConflictCache| {
+                cache.insert(&commits[..3], MergeResult::Conflict(vec![]));
+                cache.insert(&commits, MergeResult::Conflict(vec![]));
+            })
+            .unwrap();
+        let repo = CommandRepository::new(
+            Box::new(DeriveCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "derive", "-p", "myprod", "root/foo", "root/bar", "root/baz",
+        ]))
+        .unwrap();
+        let record =
+            load_derivation_record(&interface, &QualifiedPath::from("/main/product/myprod"))
+                .unwrap()
+                .unwrap();
+        assert_eq!(
+            record.feature_paths(),
+            vec![
+                QualifiedPath::from("/main/feature/root/foo"),
+                QualifiedPath::from("/main/feature/root/bar"),
+                QualifiedPath::from("/main/feature/root"),
+            ]
+        );
+    }
+
+    fn prepare_conflicting_features(path_buf: &PathBuf) {
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/foo", "file1", "foo")
+            .unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/bar", "file1", "bar")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_derivation_resolve_continue() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_conflicting_features(&path_buf);
+        let repo = CommandRepository::new(
+            Box::new(DeriveCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "derive",
+            "-p",
+            "myprod",
+            "--resolve",
+            "root/foo",
+            "root/bar",
+        ]))
+        .unwrap();
+        let product = QualifiedPath::from("/main/product/myprod");
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        assert_eq!(interface.get_current_qualified_path().unwrap(), product);
+        assert!(interface.is_merge_in_progress().unwrap());
+        assert_eq!(interface.get_unmerged_files().unwrap(), vec!["file1"]);
+        assert!(
+            repo.execute(ArgSource::SUPPLIED(vec!["derive", "-p", "other", "root"]))
+                .is_err()
+        );
+        assert!(
+            repo.execute(ArgSource::SUPPLIED(vec!["derive", "--continue"]))
+                .is_err()
+        );
+
+        std::fs::write(path_buf.join("file1"), "foobar").unwrap();
+        std::process::Command::new("git")
+            .args(["-C", path_buf.to_str().unwrap(), "add", "file1"])
+            .output()
+            .unwrap();
+        repo.execute(ArgSource::SUPPLIED(vec!["derive", "--continue"]))
+            .unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        assert_eq!(
+            interface.get_current_qualified_path().unwrap(),
+            QualifiedPath::from("/main")
+        );
+        let record = load_derivation_record(&interface, &product)
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.features().len(), 3);
+        assert!(
+            !interface
+                .get_metadata_store()
+                .exists::<DerivationState>()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_derivation_continue_after_merge_abort() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_conflicting_features(&path_buf);
+        let repo = CommandRepository::new(
+            Box::new(DeriveCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "derive",
+            "-p",
+            "myprod",
+            "--resolve",
+            "root/foo",
+            "root/bar",
+        ]))
+        .unwrap();
+        std::process::Command::new("git")
+            .args(["-C", path_buf.to_str().unwrap(), "merge", "--abort"])
+            .output()
+            .unwrap();
+        repo.execute(ArgSource::SUPPLIED(vec!["derive", "--continue"]))
+            .unwrap();
+        let product = QualifiedPath::from("/main/product/myprod");
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        assert!(interface.is_merge_in_progress().unwrap());
+        assert!(
+            load_derivation_record(&interface, &product)
+                .unwrap()
+                .is_none()
+        );
+        let state: DerivationState = interface.get_metadata_store().load().unwrap();
+        assert_eq!(
+            state.conflicting.map(|feature| feature.path().clone()),
+            Some(QualifiedPath::from("/main/feature/root/bar"))
+        );
+    }
+
+    #[test]
+    fn test_derivation_resolve_abort() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_conflicting_features(&path_buf);
+        let repo = CommandRepository::new(
+            Box::new(DeriveCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        assert!(
+            repo.execute(ArgSource::SUPPLIED(vec!["derive", "--abort"]))
+                .is_err()
+        );
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "derive",
+            "-p",
+            "myprod",
+            "--resolve",
+            "root/foo",
+            "root/bar",
+        ]))
+        .unwrap();
+        repo.execute(ArgSource::SUPPLIED(vec!["derive", "--abort"]))
+            .unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        assert_eq!(
+            interface.get_current_qualified_path().unwrap(),
+            QualifiedPath::from("/main")
+        );
+        assert!(!interface.is_merge_in_progress().unwrap());
+        assert!(
+            !interface
+                .get_model()
+                .has_branch(&QualifiedPath::from("/main/product/myprod"))
+        );
+        assert!(
+            !interface
+                .get_metadata_store()
+                .exists::<DerivationState>()
+                .unwrap()
+        );
     }
 }
@@ -0,0 +1,104 @@
+use crate::cli::*;
+use crate::error::TanglError;
+use crate::model::{Area, ExportFormat, ModelExporter, NodePath, NodePathType};
+use clap::{Arg, ArgAction, Command};
+
+const FORMAT: &str = "format";
+const ALL: &str = "all";
+
+fn collect_areas(context: &CommandContext, all: bool) -> Result<Vec<NodePath<Area>>, TanglError> {
+    if !all {
+        return Ok(vec![context.git.get_current_area()?]);
+    }
+    let mut areas = context
+        .git
+        .get_model()
+        .get_virtual_root()
+        .iter_children()
+        .filter_map(|path| match path.concretize() {
+            NodePathType::Area(area) => Some(area),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    areas.sort_by(|a, b| a.get_name().cmp(b.get_name()));
+    Ok(areas)
+}
+
+#[derive(Clone, Debug)]
+pub struct ExportCommand;
+
+impl CommandDefinition for ExportCommand {
+    fn build_command(&self) -> Command {
+        Command::new("export")
+            .about("Export the feature model")
+            .disable_help_subcommand(true)
+            .arg(
+                Arg::new(FORMAT)
+                    .long("format")
+                    .value_parser(["uvl", "json", "dot"])
+                    .default_value("uvl")
+                    .help("Format to export to"),
+            )
+            .arg(
+                Arg::new(ALL)
+                    .long("all")
+                    .action(ArgAction::SetTrue)
+                    .help("Export all areas instead of the current one"),
+            )
+    }
+}
+
+impl CommandInterface for ExportCommand {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
+        let format = context
+            .arg_helper
+            .get_argument_value::<String>(FORMAT)
+            .unwrap();
+        let all = context
+            .arg_helper
+            .get_argument_value::<bool>(ALL)
+            .unwrap_or(false);
+        let areas = collect_areas(context, all)?;
+        let exported = ModelExporter::new(ExportFormat::from(format)).export(&areas);
+        if context.is_json() {
+            context.output("export", exported);
+        } else {
+            print!("{}", exported);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::interface::GitPath;
+    use crate::git::interface::test_utils::{populate_with_features, prepare_empty_git_repo};
+    use crate::model::ImportFormat;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_export_collect_all_areas() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let repo =
+            CommandRepository::new(Box::new(ExportCommand), GitPath::CustomDirectory(path_buf));
+        let context = repo
+            .build_context(
+                ArgSource::SUPPLIED(vec!["export", "--all"]),
+                ImportFormat::Native,
+            )
+            .unwrap();
+        let areas = collect_areas(&context, true).unwrap();
+        assert_eq!(
+            areas
+                .iter()
+                .map(|area| area.get_qualified_path())
+                .collect::<Vec<_>>(),
+            vec!["/main"]
+        );
+    }
+}
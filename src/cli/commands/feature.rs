@@ -1,44 +1,112 @@
 use crate::cli::completion::*;
 use crate::cli::*;
+use crate::error::TanglError;
 use crate::model::*;
-use clap::{Arg, Command};
-use std::error::Error;
+use crate::util::u8_to_string;
+use clap::{Arg, ArgAction, Command};
+use serde_json::json;
 
-fn add_feature(feature: QualifiedPath, context: &mut CommandContext) -> Result<(), Box<dyn Error>> {
+const MANDATORY: &str = "mandatory";
+const OPTIONAL: &str = "optional";
+const GROUP: &str = "group";
+
+fn resolve_feature_path(
+    feature: QualifiedPath,
+    context: &CommandContext,
+) -> Result<QualifiedPath, TanglError> {
     let node_path = context.git.get_current_node_path()?;
     let current_path = match node_path.concretize() {
         NodePathType::Area(path) => path.get_path_to_feature_root(),
         NodePathType::Feature(path) => path.get_qualified_path(),
         _ => {
-            return Err(Box::new(CommandError::new(
+            return Err(TanglError::usage(
                 "Cannot create feature: Current branch is not a feature or area branch",
-            )));
+            ));
         }
     };
-    let target_path = current_path + feature;
-    let output = context.git.create_branch(&target_path)?;
+    Ok(current_path + feature)
+}
+fn add_feature(
+    target_path: &QualifiedPath,
+    context: &mut CommandContext,
+) -> Result<(), TanglError> {
+    let output = context.git.create_branch(target_path)?;
     context.log_from_output(&output);
     context.info(format!(
         "Created new feature {}",
         target_path.strip_n_left(2)
     ));
+    context.output("created", target_path);
     Ok(())
 }
-fn delete_feature(
-    feature: QualifiedPath,
+fn update_variability(
+    target_path: &QualifiedPath,
     context: &mut CommandContext,
-) -> Result<(), Box<dyn Error>> {
+    mandatory: Option<bool>,
+    group: Option<GroupKind>,
+) -> Result<(), TanglError> {
+    let mut variability = match context.git.get_model().get_node_path(target_path) {
+        Some(path) => path.get_metadata().variability().clone(),
+        None => {
+            return Err(TanglError::invalid_path(format!(
+                "{} is not a feature",
+                target_path
+            )));
+        }
+    };
+    if let Some(mandatory) = mandatory {
+        variability.set_mandatory(mandatory);
+    }
+    if let Some(group) = group {
+        variability.set_group(group);
+    }
+    context.info(format!(
+        "{} is {} with {} group",
+        target_path.strip_n_left(3),
+        if variability.is_mandatory() {
+            MANDATORY
+        } else {
+            OPTIONAL
+        },
+        variability.group()
+    ));
+    context.output(
+        "variability",
+        json!({
+            "feature": target_path,
+            "mandatory": variability.is_mandatory(),
+            "group": variability.group().to_string(),
+        }),
+    );
+    context
+        .git
+        .update_variability(vec![(target_path.clone(), variability)])?;
+    Ok(())
+}
+fn delete_feature(feature: QualifiedPath, context: &mut CommandContext) -> Result<(), TanglError> {
     let area = context.git.get_current_area()?;
     let complete_path = area.get_path_to_feature_root() + feature;
+    drop(area);
+    if !context.git.get_model().has_branch(&complete_path) {
+        return Err(TanglError::invalid_path(format!(
+            "{} is not a feature",
+            complete_path
+        )));
+    }
     let output = context.git.delete_branch(&complete_path)?;
-    context.log_from_output(&output);
+    if !output.status.success() {
+        return Err(TanglError::git(u8_to_string(&output.stderr).trim()));
+    }
+    context.info(u8_to_string(&output.stdout));
+    context.output("deleted", complete_path);
     Ok(())
 }
-fn print_feature_tree(context: &mut CommandContext, show_tags: bool) -> Result<(), Box<dyn Error>> {
+fn print_feature_tree(context: &mut CommandContext, show_tags: bool) -> Result<(), TanglError> {
     let area = context.git.get_current_area()?;
     match area.to_feature_root() {
         Some(path) => {
             context.info(path.display_tree(show_tags));
+            context.output("tree", ExportedNode::build(&path));
         }
         None => {}
     }
@@ -54,11 +122,30 @@ impl CommandDefinition for FeatureCommand {
             .disable_help_subcommand(true)
             .arg(Arg::new("feature").help("Creates new feature as the child of the current one. Requires to be checked out on a feature branch."))
             .arg(Arg::new("delete").short('D').help("Deletes a feature branch"))
+            .arg(
+                Arg::new(MANDATORY)
+                    .long("mandatory")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with(OPTIONAL)
+                    .help("Marks the feature as mandatory for its parent"),
+            )
+            .arg(
+                Arg::new(OPTIONAL)
+                    .long("optional")
+                    .action(ArgAction::SetTrue)
+                    .help("Marks the feature as optional for its parent"),
+            )
+            .arg(
+                Arg::new(GROUP)
+                    .long("group")
+                    .value_parser(["and", "or", "alternative"])
+                    .help("Sets how the children of the feature may be selected"),
+            )
             .arg(show_tags())
     }
 }
 impl CommandInterface for FeatureCommand {
-    fn run_command(&self, context: &mut CommandContext) -> Result<(), Box<dyn Error>> {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
         let maybe_feature_name = context.arg_helper.get_argument_value::<String>("feature");
         let maybe_delete = context.arg_helper.get_argument_value::<String>("delete");
         let show_tags = context
@@ -72,9 +159,27 @@ impl CommandInterface for FeatureCommand {
             }
             None => {}
         }
+        let mandatory = if context.arg_helper.get_argument_value::<bool>(MANDATORY) == Some(true) {
+            Some(true)
+        } else if context.arg_helper.get_argument_value::<bool>(OPTIONAL) == Some(true) {
+            Some(false)
+        } else {
+            None
+        };
+        let group = context
+            .arg_helper
+            .get_argument_value::<String>(GROUP)
+            .map(GroupKind::from);
         match maybe_feature_name {
             Some(feature_name) => {
-                add_feature(QualifiedPath::from(feature_name), context)?;
+                let target_path = resolve_feature_path(QualifiedPath::from(feature_name), context)?;
+                let has_changes = mandatory.is_some() || group.is_some();
+                if !(has_changes && context.git.get_model().has_branch(&target_path)) {
+                    add_feature(&target_path, context)?;
+                }
+                if has_changes {
+                    update_variability(&target_path, context, mandatory, group)?;
+                }
             }
             None => {
                 print_feature_tree(context, show_tags)?;
@@ -86,7 +191,7 @@ impl CommandInterface for FeatureCommand {
         &self,
         completion_helper: CompletionHelper,
         context: &mut CommandContext,
-    ) -> Result<Vec<String>, Box<dyn Error>> {
+    ) -> Result<Vec<String>, TanglError> {
         let result = match completion_helper.currently_editing() {
             Some(arg) => match arg.get_id().as_str() {
                 "delete" => {
@@ -144,7 +249,7 @@ mod tests {
         );
         match repo.execute(ArgSource::SUPPLIED(vec!["feature", "root"])) {
             Ok(_) => {
-                let interface = GitInterface::in_directory(path_buf);
+                let interface = GitInterface::in_directory(path_buf).unwrap();
                 check_existence(&interface).unwrap();
                 let branch_history = interface
                     .get_commit_history(&QualifiedPath::from("/main/feature/root"))
@@ -174,7 +279,7 @@ mod tests {
         let path_buf = PathBuf::from(path.path());
         prepare_empty_git_repo(path_buf.clone()).unwrap();
         populate_with_features(path_buf.clone()).unwrap();
-        let interface = GitInterface::in_directory(path_buf.clone());
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
         interface
             .checkout(&QualifiedPath::from("/main/feature/root/foo"))
             .unwrap();
@@ -186,7 +291,7 @@ mod tests {
         );
         match repo.execute(ArgSource::SUPPLIED(vec!["feature", "root/foo/1"])) {
             Ok(_) => {
-                let interface = GitInterface::in_directory(path_buf);
+                let interface = GitInterface::in_directory(path_buf).unwrap();
                 check_existence(&interface).unwrap();
                 let branch_history = interface
                     .get_commit_history(&QualifiedPath::from("/main/feature/root/foo/1"))
@@ -200,6 +305,49 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_feature_variability() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let repo = CommandRepository::new(
+            Box::new(FeatureCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "feature",
+            "root",
+            "--group",
+            "alternative",
+        ]))
+        .unwrap();
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "feature",
+            "root/new",
+            "--mandatory",
+        ]))
+        .unwrap();
+        let interface = GitInterface::in_directory(path_buf).unwrap();
+        let variability = |path: &str| {
+            interface
+                .get_model()
+                .get_node_path(&QualifiedPath::from(path))
+                .unwrap()
+                .get_metadata()
+                .variability()
+                .clone()
+        };
+        assert_eq!(
+            variability("/main/feature/root"),
+            Variability::new(false, GroupKind::Alternative)
+        );
+        assert_eq!(
+            variability("/main/feature/root/new"),
+            Variability::new(true, GroupKind::And)
+        );
+    }
+
     #[test]
     fn test_feature_add_error() {
         let path = TempDir::new().unwrap();
@@ -207,7 +355,7 @@ mod tests {
         prepare_empty_git_repo(path_buf.clone()).unwrap();
         populate_with_features(path_buf.clone()).unwrap();
         populate_with_products(path_buf.clone()).unwrap();
-        let interface = GitInterface::in_directory(path_buf.clone());
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
         interface
             .checkout(&QualifiedPath::from("/main/product/myprod"))
             .unwrap();
@@ -220,4 +368,26 @@ mod tests {
             Err(_) => assert!(true),
         }
     }
+
+    #[test]
+    fn test_feature_delete() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let repo = CommandRepository::new(
+            Box::new(FeatureCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        let result = repo.execute(ArgSource::SUPPLIED(vec!["feature", "-D", "nope"]));
+        assert_eq!(result.unwrap_err().exit_code(), 3);
+        repo.execute(ArgSource::SUPPLIED(vec!["feature", "-D", "root/foo"]))
+            .unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        assert!(
+            !interface
+                .get_model()
+                .has_branch(&QualifiedPath::from("/main/feature/root/foo"))
+        );
+    }
 }
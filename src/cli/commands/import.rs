@@ -0,0 +1,146 @@
+use crate::cli::*;
+use crate::error::TanglError;
+use crate::model::{ImportFormat, ModelImporter, QualifiedPath};
+use clap::{Arg, Command};
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+const FILE: &str = "file";
+
+fn resolve_format(context: &CommandContext, file: &Path) -> Result<ImportFormat, TanglError> {
+    match context.import_format {
+        ImportFormat::Native => match ImportFormat::from_file_extension(file) {
+            Some(format) => Ok(format),
+            None => Err(TanglError::usage(format!(
+                "Cannot determine the format of {}; please specify it with --import-format",
+                file.display()
+            ))),
+        },
+        ref format => Ok(format.clone()),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ImportCommand;
+
+impl CommandDefinition for ImportCommand {
+    fn build_command(&self) -> Command {
+        Command::new("import")
+            .about("Import a feature model and create missing feature branches")
+            .disable_help_subcommand(true)
+            .arg(
+                Arg::new(FILE)
+                    .required(true)
+                    .help("Feature model file to import"),
+            )
+    }
+}
+
+impl CommandInterface for ImportCommand {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
+        let file = PathBuf::from(
+            context
+                .arg_helper
+                .get_argument_value::<String>(FILE)
+                .unwrap(),
+        );
+        let format = resolve_format(context, &file)?;
+        let data = read_to_string(&file)?;
+        let area = context.git.get_current_area()?;
+        let area_path = area.get_qualified_path();
+        let feature_root = area.get_path_to_feature_root();
+        drop(area);
+
+        let imported = ModelImporter::new(format).import(&data, &feature_root)?;
+        let mut n_existing = 0;
+        let mut created = Vec::new();
+        for path in imported.get_qualified_paths_with_branches() {
+            if context.git.get_model().has_branch(path) {
+                n_existing += 1;
+                continue;
+            }
+            let parent = path.strip_n_right(path.len() - 1);
+            let start: QualifiedPath = if context.git.get_model().has_branch(&parent) {
+                parent
+            } else {
+                area_path.clone()
+            };
+            context.git.create_branch_from(path, &start)?;
+            context.info(format!("Created new feature {}", path.strip_n_left(3)));
+            created.push(path.clone());
+        }
+        if n_existing > 0 {
+            context.info(format!("{} features already exist", n_existing));
+        }
+        let variability = imported
+            .collect_variability()
+            .into_iter()
+            .collect::<Vec<_>>();
+        context.git.update_variability(variability)?;
+        context.output("created", created);
+        context.output("existing", n_existing);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::interface::test_utils::{populate_with_features, prepare_empty_git_repo};
+    use crate::git::interface::{GitInterface, GitPath};
+    use crate::model::GroupKind;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_import_uvl_creates_missing_features() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let model_dir = TempDir::new().unwrap();
+        let mut model_file = PathBuf::from(model_dir.path());
+        model_file.push("model.uvl");
+        fs::write(
+            &model_file,
+            "features\n    root\n        optional\n            foo\n                or\n                    new1\n            new2\n",
+        )
+        .unwrap();
+        let repo = CommandRepository::new(
+            Box::new(ImportCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        match repo.execute(ArgSource::SUPPLIED(vec![
+            "import",
+            model_file.to_str().unwrap(),
+        ])) {
+            Ok(_) => {
+                let interface = GitInterface::in_directory(path_buf).unwrap();
+                let model = interface.get_model();
+                assert!(model.has_branch(&QualifiedPath::from("/main/feature/root/foo/new1")));
+                assert!(model.has_branch(&QualifiedPath::from("/main/feature/root/new2")));
+                assert!(model.has_branch(&QualifiedPath::from("/main/feature/root/bar")));
+                let foo = model
+                    .get_node_path(&QualifiedPath::from("/main/feature/root/foo"))
+                    .unwrap();
+                assert_eq!(foo.get_metadata().variability().group(), &GroupKind::Or);
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn test_import_unknown_format() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        let repo = CommandRepository::new(
+            Box::new(ImportCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        match repo.execute(ArgSource::SUPPLIED(vec!["import", "model.txt"])) {
+            Ok(_) => panic!("Should fail"),
+            Err(_) => assert!(true),
+        }
+    }
+}
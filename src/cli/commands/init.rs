@@ -1,6 +1,6 @@
 use crate::cli::*;
+use crate::error::TanglError;
 use clap::Command;
-use std::error::Error;
 
 #[derive(Clone, Debug)]
 pub struct InitCommand;
@@ -14,7 +14,7 @@ impl CommandDefinition for InitCommand {
 }
 
 impl CommandInterface for InitCommand {
-    fn run_command(&self, context: &mut CommandContext) -> Result<(), Box<dyn Error>> {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
         let output = context.git.initialize_repo()?;
         context.log_from_output(&output);
         Ok(())
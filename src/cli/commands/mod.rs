@@ -1,8 +1,12 @@
+mod cache;
 mod check;
 mod checkout;
 mod complete;
+mod constraint;
 mod derive;
+mod export;
 mod feature;
+mod import;
 mod init;
 mod product;
 mod spread;
@@ -11,12 +15,17 @@ mod tag;
 mod tangl;
 mod tree;
 mod untie;
+mod validate;
 
+pub use cache::*;
 pub use check::*;
 pub use checkout::*;
 pub use complete::*;
+pub use constraint::*;
 pub use derive::*;
+pub use export::*;
 pub use feature::*;
+pub use import::*;
 pub use init::*;
 pub use product::*;
 pub use spread::*;
@@ -25,3 +34,4 @@ pub use tag::*;
 pub use tangl::*;
 pub use tree::*;
 pub use untie::*;
+pub use validate::*;
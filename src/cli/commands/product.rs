@@ -1,30 +1,231 @@
 use crate::cli::completion::*;
 use crate::cli::*;
+use crate::error::TanglError;
+use crate::git::conflict::{ConflictChecker, ConflictStatistic};
 use crate::model::*;
+use crate::util::u8_to_string;
 use clap::{Arg, Command};
-use std::error::Error;
+use serde_json::json;
 
-fn delete_product(
-    product: QualifiedPath,
-    context: &mut CommandContext,
-) -> Result<(), Box<dyn Error>> {
+const PRODUCT: &str = "product";
+
+fn delete_product(product: QualifiedPath, context: &mut CommandContext) -> Result<(), TanglError> {
     let area = context.git.get_current_area()?;
     let complete_path = area.get_path_to_product_root() + product;
+    drop(area);
+    if !context.git.get_model().has_branch(&complete_path) {
+        return Err(TanglError::invalid_path(format!(
+            "{} is not a product",
+            complete_path
+        )));
+    }
     let output = context.git.delete_branch(&complete_path)?;
-    context.log_from_output(&output);
+    if !output.status.success() {
+        return Err(TanglError::git(u8_to_string(&output.stderr).trim()));
+    }
+    context.info(u8_to_string(&output.stdout));
+    remove_derivation_records(&context.git, &complete_path)?;
+    context.output("deleted", complete_path);
     Ok(())
 }
-fn print_product_tree(context: &mut CommandContext) -> Result<(), Box<dyn Error>> {
+fn print_product_tree(context: &mut CommandContext) -> Result<(), TanglError> {
     let area = context.git.get_current_area()?;
     match area.to_product_root() {
         Some(path) => {
             context.info(path.display_tree(false));
+            context.output("tree", ExportedNode::build(&path));
         }
         None => {}
     }
     Ok(())
 }
 
+/// Returns the features of a record whose branches moved since the
+/// derivation, with their current commit. Features of migrated records have
+/// no commit and always count as moved; deleted features are skipped.
+fn find_moved_features(
+    context: &mut CommandContext,
+    record: &DerivationRecord,
+) -> Result<Vec<(QualifiedPath, String)>, TanglError> {
+    let mut moved = vec![];
+    for feature in record.features() {
+        if !context.git.get_model().has_branch(feature.path()) {
+            context.warn(format!(
+                "Feature {} no longer exists, keeping its derived state",
+                feature.path()
+            ));
+            continue;
+        }
+        let tip = context.git.get_commit_hash(feature.path())?;
+        if feature.commit() != Some(&tip) {
+            moved.push((feature.path().clone(), tip));
+        }
+    }
+    Ok(moved)
+}
+
+/// Merges the features that moved since the derivation into the product and
+/// stores a new derivation record with their new commits. Features conflicting with the product
+/// keep the commit they were derived with.
+fn update_product(context: &mut CommandContext) -> Result<(), TanglError> {
+    let product_name = context
+        .arg_helper
+        .get_argument_value::<String>(PRODUCT)
+        .unwrap();
+    let current_path = context.git.get_current_qualified_path()?;
+    let area = context.git.get_current_area()?;
+    let feature_root_path = area.get_path_to_feature_root();
+    let product = area.get_path_to_product_root() + QualifiedPath::from(product_name);
+    drop(area);
+    if !context.git.get_model().has_branch(&product) {
+        return Err(TanglError::invalid_path(format!(
+            "Product {} does not exist",
+            product
+        )));
+    }
+    let record = match load_derivation_record(&context.git, &product)? {
+        Some(record) => record,
+        None => {
+            return Err(TanglError::usage(format!(
+                "Product {} was not derived by tangl",
+                product
+            )));
+        }
+    };
+    context.output("product", &product);
+    let moved = find_moved_features(context, &record)?;
+    if moved.is_empty() {
+        context.info("All features are up to date");
+        context.output("left_out", Vec::<QualifiedPath>::new());
+        context.output("updated", Vec::<QualifiedPath>::new());
+        return Ok(());
+    }
+    let relative = |path: &QualifiedPath| path.strip_n_left(feature_root_path.len());
+    for (feature, _) in moved.iter() {
+        context.info(format!("{} has new commits", relative(feature)));
+    }
+
+    context.info("Checking for conflicts");
+    let moved_paths: Vec<QualifiedPath> = moved.iter().map(|(path, _)| path.clone()).collect();
+    let statistics: Vec<ConflictStatistic> = ConflictChecker::new(&context.git)
+        .check_1_to_n(&product, &moved_paths)?
+        .collect();
+    if statistics
+        .iter()
+        .any(|statistic| matches!(statistic, ConflictStatistic::ERROR(_, _)))
+    {
+        return Err(TanglError::git(
+            "Errors occurred while checking for conflicts.",
+        ));
+    }
+    let mut left_out: Vec<CombinedConflict> = statistics
+        .iter()
+        .filter_map(|statistic| match statistic {
+            ConflictStatistic::CONFLICT((_, feature), _) => {
+                Some((feature.clone(), vec![product.clone()]))
+            }
+            _ => None,
+        })
+        .collect();
+    let mut selection = vec![product.clone()];
+    selection.extend(
+        moved_paths
+            .iter()
+            .filter(|path| !left_out.iter().any(|(feature, _)| feature == *path))
+            .cloned(),
+    );
+    let (selection, combined_conflicts) = trial_merge_selection(context, selection, &product)?;
+    left_out.extend(combined_conflicts);
+    let updated: Vec<QualifiedPath> = selection
+        .into_iter()
+        .filter(|path| path != &product)
+        .collect();
+
+    for (feature, partners) in left_out.iter() {
+        let partners = partners
+            .iter()
+            .map(|partner| {
+                if partner == &product {
+                    "the product".to_string()
+                } else {
+                    relative(partner).to_string()
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" and ");
+        context.warn(format!(
+            "Not updating {}: conflicts with {}",
+            relative(feature),
+            partners
+        ));
+    }
+    context.output(
+        "left_out",
+        left_out
+            .iter()
+            .map(|(feature, partners)| json!({"feature": feature, "conflicts_with": partners}))
+            .collect::<Vec<_>>(),
+    );
+    if updated.is_empty() {
+        return Err(TanglError::conflicts(
+            "None of the features can be updated without conflicts",
+        ));
+    }
+
+    with_clean_working_tree(context, |context| {
+        context.git.checkout(&product)?;
+        let output = context.git.merge(&updated)?;
+        if !output.status.success() {
+            context.git.abort_merge()?;
+            context.git.checkout(&current_path)?;
+            return Err(TanglError::git(format!(
+                "Merging the features failed: {}",
+                u8_to_string(&output.stderr).trim()
+            )));
+        }
+        let features = record
+            .features()
+            .iter()
+            .map(|feature| {
+                match moved
+                    .iter()
+                    .find(|(path, _)| path == feature.path() && updated.contains(path))
+                {
+                    Some((path, tip)) => DerivedFeature::new(path.clone(), Some(tip.clone())),
+                    None => feature.clone(),
+                }
+            })
+            .collect();
+        // the derivation commit is kept, so untie still finds the commits made
+        // to the product before the update
+        let new_record = DerivationRecord::new(product.clone(), features, record.commit().clone());
+        store_derivation_record(&context.git, new_record)?;
+        context.git.checkout(&current_path)?;
+        Ok(())
+    })?;
+    context.output("updated", &updated);
+    context.info(format!("Updated {} features", updated.len()));
+    Ok(())
+}
+
+fn complete_products(
+    completion_helper: CompletionHelper,
+    context: &mut CommandContext,
+) -> Result<Vec<String>, TanglError> {
+    let maybe_product_root = context.git.get_current_area()?.to_product_root();
+    Ok(match maybe_product_root {
+        Some(path) => completion_helper.complete_qualified_paths(
+            path.get_qualified_path(),
+            HasBranchFilteringNodePathTransformer::new(true)
+                .transform(path.iter_children_req())
+                .map(|path| path.get_qualified_path()),
+        ),
+        None => {
+            vec![]
+        }
+    })
+}
+
 #[derive(Clone, Debug)]
 pub struct ProductCommand;
 impl CommandDefinition for ProductCommand {
@@ -38,9 +239,15 @@ impl CommandDefinition for ProductCommand {
                     .help("Deletes a product branch"),
             )
     }
+    fn get_subcommands(&self) -> Vec<Box<dyn CommandImpl>> {
+        vec![Box::new(ProductUpdateCommand)]
+    }
 }
 impl CommandInterface for ProductCommand {
-    fn run_command(&self, context: &mut CommandContext) -> Result<(), Box<dyn Error>> {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
+        if context.arg_helper.get_matches().subcommand().is_some() {
+            return Ok(());
+        }
         let maybe_delete = context.arg_helper.get_argument_value::<String>("delete");
         match maybe_delete {
             Some(delete) => {
@@ -57,23 +264,10 @@ impl CommandInterface for ProductCommand {
         &self,
         completion_helper: CompletionHelper,
         context: &mut CommandContext,
-    ) -> Result<Vec<String>, Box<dyn Error>> {
+    ) -> Result<Vec<String>, TanglError> {
         let result = match completion_helper.currently_editing() {
             Some(arg) => match arg.get_id().as_str() {
-                "delete" => {
-                    let maybe_feature_root = context.git.get_current_area()?.to_product_root();
-                    match maybe_feature_root {
-                        Some(path) => completion_helper.complete_qualified_paths(
-                            path.get_qualified_path(),
-                            HasBranchFilteringNodePathTransformer::new(true)
-                                .transform(path.iter_children_req())
-                                .map(|path| path.get_qualified_path()),
-                        ),
-                        None => {
-                            vec![]
-                        }
-                    }
-                }
+                "delete" => complete_products(completion_helper, context)?,
                 _ => {
                     vec![]
                 }
@@ -85,3 +279,129 @@ impl CommandInterface for ProductCommand {
         Ok(result)
     }
 }
+
+#[derive(Clone, Debug)]
+pub struct ProductUpdateCommand;
+
+impl CommandDefinition for ProductUpdateCommand {
+    fn build_command(&self) -> Command {
+        Command::new("update")
+            .about("Merge new commits of the features of a product into it")
+            .disable_help_subcommand(true)
+            .arg(Arg::new(PRODUCT).required(true))
+            .arg(autostash())
+    }
+}
+
+impl CommandInterface for ProductUpdateCommand {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
+        update_product(context)
+    }
+    fn shell_complete(
+        &self,
+        completion_helper: CompletionHelper,
+        context: &mut CommandContext,
+    ) -> Result<Vec<String>, TanglError> {
+        match completion_helper.currently_editing() {
+            Some(arg) if arg.get_id() == PRODUCT => complete_products(completion_helper, context),
+            _ => Ok(vec![]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::commands::TangleCommand;
+    use crate::git::interface::test_utils::{
+        commit_file_on_branch, populate_with_features, prepare_empty_git_repo,
+    };
+    use crate::git::interface::{GitInterface, GitPath};
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_product_update() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let repo = CommandRepository::new(
+            Box::new(TangleCommand {}),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "tangl", "derive", "-p", "myprod", "root/foo", "root/baz",
+        ]))
+        .unwrap();
+        let (result, document) = repo.execute_with_output(ArgSource::SUPPLIED(vec![
+            "tangl", "--output", "json", "product", "update", "myprod",
+        ]));
+        result.unwrap();
+        assert_eq!(document.unwrap()["data"]["updated"], json!([]));
+
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/foo", "file2", "foo")
+            .unwrap();
+        commit_file_on_branch(
+            path_buf.clone(),
+            "_main/_product/myprod",
+            "file3",
+            "product",
+        )
+        .unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/baz", "file3", "baz")
+            .unwrap();
+        let old_record = load_derivation_record(
+            &GitInterface::in_directory(path_buf.clone()).unwrap(),
+            &QualifiedPath::from("/main/product/myprod"),
+        )
+        .unwrap()
+        .unwrap();
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "tangl", "product", "update", "myprod",
+        ]))
+        .unwrap();
+
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        let product = QualifiedPath::from("/main/product/myprod");
+        let record = load_derivation_record(&interface, &product)
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.feature_paths(), old_record.feature_paths());
+        assert_eq!(record.commit(), old_record.commit());
+        let commit_of = |record: &DerivationRecord, feature: &str| {
+            record
+                .features()
+                .iter()
+                .find(|f| f.path() == &QualifiedPath::from(feature))
+                .unwrap()
+                .commit()
+                .cloned()
+        };
+        let foo = "/main/feature/root/foo";
+        let baz = "/main/feature/root/baz";
+        assert_eq!(
+            commit_of(&record, foo).unwrap(),
+            interface
+                .get_commit_hash(&QualifiedPath::from(foo))
+                .unwrap()
+        );
+        // baz conflicts with the product and keeps its derived commit
+        assert_eq!(commit_of(&record, baz), commit_of(&old_record, baz));
+        assert!(
+            interface
+                .get_files_managed_by_branch(&product)
+                .unwrap()
+                .contains(&"file2".to_string())
+        );
+        assert_eq!(
+            interface.get_current_qualified_path().unwrap(),
+            QualifiedPath::from("/main")
+        );
+
+        let result = repo.execute(ArgSource::SUPPLIED(vec![
+            "tangl", "product", "update", "myprod",
+        ]));
+        assert_eq!(result.unwrap_err().exit_code(), 1);
+    }
+}
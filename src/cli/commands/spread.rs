@@ -1,7 +1,71 @@
 use crate::cli::*;
-use crate::model::NodePathType;
-use clap::Command;
-use std::error::Error;
+use crate::error::TanglError;
+use crate::git::conflict::{ConflictChecker, ConflictStatistic};
+use crate::git::persistency::Document;
+use crate::model::{NodeType, QualifiedPath};
+use crate::util::u8_to_string;
+use clap::{Arg, ArgAction, Command};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+
+const CONTINUE: &str = "continue";
+const SKIP: &str = "skip";
+const ABORT: &str = "abort";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SpreadResult {
+    Merged,
+    UpToDate,
+    /// Merged after the user resolved its conflicts.
+    Resolved,
+    /// Conflicted and was skipped.
+    Skipped,
+    Conflicted,
+}
+
+impl SpreadResult {
+    fn label(&self) -> String {
+        match self {
+            SpreadResult::Merged => "merged".green().to_string(),
+            SpreadResult::UpToDate => "up to date".to_string(),
+            SpreadResult::Resolved => "merged after resolving conflicts".green().to_string(),
+            SpreadResult::Skipped => "conflicted, skipped".red().to_string(),
+            SpreadResult::Conflicted => "conflicted".red().to_string(),
+        }
+    }
+}
+
+/// A child and the commit it pointed to before the spread.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SpreadChild {
+    branch: QualifiedPath,
+    base_commit: String,
+    result: SpreadResult,
+}
+
+/// A spread that merges into one child at a time and stops at conflicts
+/// until it is continued, the child is skipped or the spread is aborted.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct SpreadState {
+    branch: QualifiedPath,
+    done: Vec<SpreadChild>,
+    pending: Vec<QualifiedPath>,
+    conflicting: Option<SpreadChild>,
+}
+
+impl Document for SpreadState {
+    const NAME: &'static str = "spread_state";
+    const VERSION: u32 = 1;
+}
+
+fn load_spread_state(context: &CommandContext) -> Result<SpreadState, TanglError> {
+    let store = context.git.get_metadata_store();
+    if !store.exists::<SpreadState>()? {
+        return Err(TanglError::usage("No spread in progress"));
+    }
+    Ok(store.load()?)
+}
 
 #[derive(Clone, Debug)]
 pub struct SpreadCommand;
@@ -11,27 +75,502 @@ impl CommandDefinition for SpreadCommand {
         Command::new("spread")
             .about("Spread commits across children")
             .disable_help_subcommand(true)
+            .arg(dry_run().conflicts_with_all([CONTINUE, SKIP, ABORT]))
+            .arg(autostash().conflicts_with_all([CONTINUE, SKIP, ABORT]))
+            .arg(
+                Arg::new(CONTINUE)
+                    .long("continue")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with_all([SKIP, ABORT])
+                    .help("Continue a spread after resolving conflicts"),
+            )
+            .arg(
+                Arg::new(SKIP)
+                    .long("skip")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with(ABORT)
+                    .help("Leave out the conflicting child and continue a spread"),
+            )
+            .arg(
+                Arg::new(ABORT)
+                    .long("abort")
+                    .action(ArgAction::SetTrue)
+                    .help("Abort a spread and reset all children it merged into"),
+            )
     }
 }
 
-impl CommandInterface for SpreadCommand {
-    fn run_command(&self, context: &mut CommandContext) -> Result<(), Box<dyn Error>> {
-        let current_path = context.git.get_current_node_path()?;
-        let current_branch = current_path.get_qualified_path();
-        let merge_argument = vec![current_branch.clone()];
-        for path in current_path.iter_children_req() {
-            let qualified_path = path.get_qualified_path();
-            match path.concretize() {
-                NodePathType::Tag(_) => {}
-                _ => {
-                    context.info(format!("Spreading to {}", qualified_path));
-                    context.git.checkout(&qualified_path)?;
-                    context.git.merge(&merge_argument)?;
-                }
+/// Prints the branches the current branch would be merged into and which
+/// of the merges would conflict.
+fn print_spread_plan(
+    context: &mut CommandContext,
+    branch: &QualifiedPath,
+    children: Vec<QualifiedPath>,
+) -> Result<(), TanglError> {
+    let statistics: Vec<ConflictStatistic> = ConflictChecker::new(&context.git)
+        .with_cache_writes(false)
+        .check_1_to_n(branch, &children)?
+        .collect();
+    let mut conflicts = vec![];
+    context.info(format!("Would merge {} into:", branch));
+    for statistic in statistics.iter() {
+        let (_, child) = statistic.pair();
+        match statistic {
+            ConflictStatistic::OK(_) => context.info(format!("  {}", child)),
+            ConflictStatistic::CONFLICT(_, files) => {
+                let files: Vec<&str> = files.iter().map(|file| file.path().as_str()).collect();
+                context.warn(format!(
+                    "  {} {} in {}",
+                    child,
+                    "will produce conflicts".red(),
+                    files.join(", ")
+                ));
+                conflicts.push(child.clone());
             }
+            ConflictStatistic::ERROR(_, err) => {
+                return Err(TanglError::git(format!(
+                    "Cannot check {} for conflicts: {}",
+                    child, err
+                )));
+            }
+        }
+    }
+    context.info("Dry run, the repository was not changed.");
+    context.output("dry_run", true);
+    context.output("branch", branch);
+    context.output("children", children);
+    context.output("conflicts", conflicts);
+    Ok(())
+}
+
+/// Merges the branch into the pending children one by one. Persists the
+/// state after every child and stops at the first conflict.
+fn merge_pending_children(
+    context: &mut CommandContext,
+    mut state: SpreadState,
+) -> Result<(), TanglError> {
+    let store = context.git.get_metadata_store();
+    let merge_argument = vec![state.branch.clone()];
+    while !state.pending.is_empty() {
+        let branch = state.pending.remove(0);
+        let base_commit = context.git.get_commit_hash(&branch)?;
+        let mut child = SpreadChild {
+            branch: branch.clone(),
+            base_commit,
+            result: SpreadResult::UpToDate,
+        };
+        if context.git.is_ancestor(&state.branch, &branch)? {
+            context.info(format!("{} is up to date", branch));
+            state.done.push(child);
+            store.store(&state)?;
+            continue;
+        }
+        context.info(format!("Spreading to {}", branch));
+        if let Err(err) = context.git.checkout(&branch) {
+            state.pending.insert(0, branch);
+            store.store(&state)?;
+            return Err(err.into());
+        }
+        let output = context.git.merge(&merge_argument)?;
+        if output.status.success() && context.git.is_ancestor(&state.branch, &branch)? {
+            child.result = SpreadResult::Merged;
+            state.done.push(child);
+            store.store(&state)?;
+            continue;
+        }
+        if !context.git.is_merge_in_progress()? {
+            state.pending.insert(0, branch.clone());
+            store.store(&state)?;
+            return Err(TanglError::git(format!(
+                "Merging into {} failed: {}",
+                branch,
+                u8_to_string(&output.stderr).trim()
+            )));
+        }
+        child.result = SpreadResult::Conflicted;
+        state.conflicting = Some(child);
+        store.store(&state)?;
+        context.warn(format!(
+            "Merging into {} {} in:",
+            branch,
+            "produced conflicts".red()
+        ));
+        let files = context.git.get_unmerged_files()?;
+        for file in files.iter() {
+            context.warn(format!("  {}", file));
+        }
+        context.output("branch", &state.branch);
+        context.output("finished", false);
+        context.output("conflicting", &branch);
+        context.output("files", files);
+        context.info(
+            "Resolve the conflicts and stage the files, then run 'tangl spread --continue'. \
+            Run 'tangl spread --skip' to leave out this child or 'tangl spread --abort' to cancel the spread.",
+        );
+        return Ok(());
+    }
+    store.remove::<SpreadState>()?;
+    context.git.checkout(&state.branch)?;
+    print_summary(context, &state);
+    Ok(())
+}
+
+fn print_summary(context: &mut CommandContext, state: &SpreadState) {
+    context.info(format!("Spread {}:", state.branch));
+    for child in state.done.iter() {
+        context.info(format!("  {}: {}", child.branch, child.result.label()));
+    }
+    let results: Vec<serde_json::Value> = state
+        .done
+        .iter()
+        .map(|child| serde_json::json!({"branch": child.branch, "result": child.result}))
+        .collect();
+    let children: Vec<&QualifiedPath> = state.done.iter().map(|child| &child.branch).collect();
+    context.output("branch", &state.branch);
+    context.output("finished", true);
+    context.output("children", children);
+    context.output("results", results);
+}
+
+fn start_spread(context: &mut CommandContext) -> Result<(), TanglError> {
+    if context.git.get_metadata_store().exists::<SpreadState>()? {
+        return Err(TanglError::usage(
+            "A spread is in progress, use --continue or --skip to finish or --abort to cancel it",
+        ));
+    }
+    let current_path = context.git.get_current_node_path()?;
+    let branch = current_path.get_qualified_path();
+    let mut children: Vec<QualifiedPath> = current_path
+        .iter_children_req()
+        .filter(|path| !matches!(path.get_type(), NodeType::Tag))
+        .filter(|path| path.get_metadata().has_branch())
+        .map(|path| path.get_qualified_path())
+        .collect();
+    drop(current_path);
+    // parents sort before their children
+    children.sort();
+    if context.is_dry_run() {
+        return print_spread_plan(context, &branch, children);
+    }
+    // fail before any state is stored if a child cannot be resolved
+    for child in children.iter() {
+        context.git.get_commit_hash(child)?;
+    }
+    let state = SpreadState {
+        branch,
+        done: vec![],
+        pending: children,
+        conflicting: None,
+    };
+    with_clean_working_tree(context, |context| {
+        let store = context.git.get_metadata_store();
+        store.store(&state)?;
+        let result = merge_pending_children(context, state);
+        if result.is_err() {
+            // there is nothing to abort if no child was merged into yet
+            let state = load_spread_state(context)?;
+            if state.conflicting.is_none()
+                && state
+                    .done
+                    .iter()
+                    .all(|child| child.result == SpreadResult::UpToDate)
+            {
+                store.remove::<SpreadState>()?;
+            }
+        }
+        result
+    })
+}
+
+/// Loads the state and checks that the conflicting child is checked out.
+fn load_conflicting_state(context: &CommandContext) -> Result<SpreadState, TanglError> {
+    let state = load_spread_state(context)?;
+    if let Some(conflicting) = state.conflicting.as_ref()
+        && context.git.get_current_qualified_path()? != conflicting.branch
+    {
+        return Err(TanglError::usage(format!(
+            "Check out {} to continue the spread",
+            conflicting.branch
+        )));
+    }
+    Ok(state)
+}
+
+fn continue_spread(context: &mut CommandContext) -> Result<(), TanglError> {
+    let mut state = load_conflicting_state(context)?;
+    if let Some(mut conflicting) = state.conflicting.take() {
+        let unmerged = context.git.get_unmerged_files()?;
+        if !unmerged.is_empty() {
+            return Err(TanglError::conflicts(format!(
+                "Unresolved conflicts remain in {}",
+                unmerged.join(", ")
+            )));
         }
-        context.git.checkout(&current_branch)?;
-        context.info("Success");
-        Ok(())
+        if context.git.is_merge_in_progress()? {
+            let output = context.git.commit_merge()?;
+            if !output.status.success() {
+                return Err(TanglError::git(format!(
+                    "Cannot commit the merge: {}",
+                    u8_to_string(&output.stdout).trim()
+                )));
+            }
+        }
+        if context
+            .git
+            .is_ancestor(&state.branch, &conflicting.branch)?
+        {
+            conflicting.result = SpreadResult::Resolved;
+            state.done.push(conflicting);
+        } else {
+            context.warn(format!(
+                "{} is not merged, merging it again",
+                conflicting.branch
+            ));
+            state.pending.insert(0, conflicting.branch);
+        }
+    }
+    merge_pending_children(context, state)
+}
+
+fn skip_child(context: &mut CommandContext) -> Result<(), TanglError> {
+    let mut state = load_conflicting_state(context)?;
+    let mut conflicting = match state.conflicting.take() {
+        Some(conflicting) => conflicting,
+        None => return Err(TanglError::usage("No conflicting child to skip")),
+    };
+    if context.git.is_merge_in_progress()? {
+        context.git.abort_merge()?;
+    }
+    // the user may have committed the merge already
+    if context.git.get_commit_hash(&conflicting.branch)? != conflicting.base_commit {
+        context.git.reset_hard(&conflicting.base_commit)?;
+    }
+    context.warn(format!("Skipped {}", conflicting.branch));
+    conflicting.result = SpreadResult::Skipped;
+    state.done.push(conflicting);
+    merge_pending_children(context, state)
+}
+
+fn abort_spread(context: &mut CommandContext) -> Result<(), TanglError> {
+    let state = load_spread_state(context)?;
+    if context.git.is_merge_in_progress()? {
+        context.git.abort_merge()?;
+    }
+    for child in state.done.iter().chain(state.conflicting.iter()) {
+        if context.git.get_commit_hash(&child.branch)? != child.base_commit {
+            context.git.checkout(&child.branch)?;
+            context.git.reset_hard(&child.base_commit)?;
+        }
+    }
+    context.git.checkout(&state.branch)?;
+    context.git.get_metadata_store().remove::<SpreadState>()?;
+    context.info(format!("Aborted the spread of {}", state.branch));
+    context.output("aborted", &state.branch);
+    Ok(())
+}
+
+impl CommandInterface for SpreadCommand {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
+        let flag = |id: &str| context.arg_helper.get_argument_value::<bool>(id) == Some(true);
+        if flag(ABORT) {
+            return abort_spread(context);
+        }
+        if flag(SKIP) {
+            return skip_child(context);
+        }
+        if flag(CONTINUE) {
+            return continue_spread(context);
+        }
+        start_spread(context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::interface::test_utils::{
+        commit_file_on_branch, populate_with_features, prepare_empty_git_repo,
+    };
+    use crate::git::interface::{GitInterface, GitPath};
+    use serde_json::{Value, json};
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+    use tempfile::TempDir;
+
+    fn git(path: &PathBuf, args: &[&str]) {
+        process::Command::new("git")
+            .arg("-C")
+            .arg(path)
+            .args(args)
+            .output()
+            .unwrap();
+    }
+
+    fn spread(repo: &CommandRepository, args: &[&'static str]) -> Value {
+        let mut all = vec!["tangl", "--output", "json", "spread"];
+        all.extend(args);
+        let (result, document) = repo.execute_with_output(ArgSource::SUPPLIED(all));
+        result.unwrap();
+        document.unwrap()["data"].clone()
+    }
+
+    fn prepare(path_buf: &PathBuf, conflicting: &str) -> (GitInterface, CommandRepository) {
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/root", "file1", "root").unwrap();
+        commit_file_on_branch(path_buf.clone(), conflicting, "file1", "child").unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        interface
+            .checkout(&QualifiedPath::from("/main/feature/root"))
+            .unwrap();
+        let repo = CommandRepository::new(
+            Box::new(TangleCommand {}),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        (interface, repo)
+    }
+
+    #[test]
+    fn test_spread_skip() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        let (interface, repo) = prepare(&path_buf, "_main/_feature/_root/bar");
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/foo", "file2", "foo")
+            .unwrap();
+        git(
+            &path_buf,
+            &[
+                "branch",
+                "-f",
+                "_main/_feature/_root/baz",
+                "_main/_feature/root",
+            ],
+        );
+        let bar = QualifiedPath::from("/main/feature/root/bar");
+        let bar_commit = interface.get_commit_hash(&bar).unwrap();
+
+        let data = spread(&repo, &[]);
+        assert_eq!(data["finished"], false);
+        assert_eq!(data["conflicting"], "/main/feature/root/bar");
+        assert_eq!(interface.get_current_qualified_path().unwrap(), bar);
+        assert!(interface.is_merge_in_progress().unwrap());
+        let result = repo.execute(ArgSource::SUPPLIED(vec!["tangl", "spread"]));
+        assert_eq!(result.unwrap_err().exit_code(), 2);
+
+        let data = spread(&repo, &["--skip"]);
+        assert_eq!(data["finished"], true);
+        assert_eq!(
+            data["results"],
+            json!([
+                {"branch": "/main/feature/root/bar", "result": "skipped"},
+                {"branch": "/main/feature/root/baz", "result": "up_to_date"},
+                {"branch": "/main/feature/root/foo", "result": "merged"},
+            ])
+        );
+        assert_eq!(interface.get_commit_hash(&bar).unwrap(), bar_commit);
+        assert_eq!(
+            interface.get_current_qualified_path().unwrap(),
+            QualifiedPath::from("/main/feature/root")
+        );
+        assert!(
+            !interface
+                .get_metadata_store()
+                .exists::<SpreadState>()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_spread_abort_and_continue() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        let (interface, repo) = prepare(&path_buf, "_main/_feature/_root/foo");
+        let root = QualifiedPath::from("/main/feature/root");
+        let bar = QualifiedPath::from("/main/feature/root/bar");
+        let foo = QualifiedPath::from("/main/feature/root/foo");
+        let bar_commit = interface.get_commit_hash(&bar).unwrap();
+
+        assert_eq!(spread(&repo, &[])["conflicting"], "/main/feature/root/foo");
+        assert!(interface.is_ancestor(&root, &bar).unwrap());
+        spread(&repo, &["--abort"]);
+        assert_eq!(interface.get_commit_hash(&bar).unwrap(), bar_commit);
+        assert_eq!(interface.get_current_qualified_path().unwrap(), root);
+        assert!(!interface.is_merge_in_progress().unwrap());
+
+        spread(&repo, &[]);
+        let result = repo.execute(ArgSource::SUPPLIED(vec!["tangl", "spread", "--continue"]));
+        assert_eq!(result.unwrap_err().exit_code(), 1);
+        fs::write(path_buf.join("file1"), "resolved").unwrap();
+        git(&path_buf, &["add", "file1"]);
+        let data = spread(&repo, &["--continue"]);
+        assert_eq!(data["finished"], true);
+        assert_eq!(
+            data["results"][2],
+            json!({"branch": "/main/feature/root/foo", "result": "resolved"})
+        );
+        assert!(interface.is_ancestor(&root, &foo).unwrap());
+        assert_eq!(interface.get_current_qualified_path().unwrap(), root);
+    }
+
+    #[test]
+    fn test_spread_continue_after_merge_abort() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        let (interface, repo) = prepare(&path_buf, "_main/_feature/_root/foo");
+        let foo = QualifiedPath::from("/main/feature/root/foo");
+
+        spread(&repo, &[]);
+        git(&path_buf, &["merge", "--abort"]);
+        let data = spread(&repo, &["--continue"]);
+        assert_eq!(data["finished"], false);
+        assert_eq!(data["conflicting"], "/main/feature/root/foo");
+        assert_eq!(interface.get_current_qualified_path().unwrap(), foo);
+        assert!(interface.is_merge_in_progress().unwrap());
+    }
+
+    #[test]
+    fn test_spread_skips_nodes_without_branch() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        git(
+            &path_buf,
+            &["branch", "_main/_feature/_root/_abstract/leaf"],
+        );
+        commit_file_on_branch(path_buf.clone(), "main", "file2", "main").unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        let repo = CommandRepository::new(
+            Box::new(TangleCommand {}),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        let children = json!([
+            "/main/feature/root",
+            "/main/feature/root/abstract/leaf",
+            "/main/feature/root/bar",
+            "/main/feature/root/baz",
+            "/main/feature/root/foo",
+        ]);
+
+        let data = spread(&repo, &["--dry-run"]);
+        assert_eq!(data["children"], children);
+        assert_eq!(data["conflicts"], json!([]));
+
+        let data = spread(&repo, &[]);
+        assert_eq!(data["finished"], true);
+        assert_eq!(data["children"], children);
+        let leaf = QualifiedPath::from("/main/feature/root/abstract/leaf");
+        assert!(
+            interface
+                .is_ancestor(&QualifiedPath::from("/main"), &leaf)
+                .unwrap()
+        );
+        assert!(
+            !interface
+                .get_metadata_store()
+                .exists::<SpreadState>()
+                .unwrap()
+        );
     }
 }
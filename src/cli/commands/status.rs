@@ -1,6 +1,6 @@
 use crate::cli::*;
+use crate::error::TanglError;
 use clap::Command;
-use std::error::Error;
 
 #[derive(Clone, Debug)]
 pub struct StatusCommand;
@@ -15,9 +15,15 @@ impl CommandDefinition for StatusCommand {
 }
 
 impl CommandInterface for StatusCommand {
-    fn run_command(&self, context: &mut CommandContext) -> Result<(), Box<dyn Error>> {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
         let output = context.git.status()?;
         context.log_from_output(&output);
+        if context.is_json() {
+            let branch = context.git.get_current_qualified_path().ok();
+            context.output("branch", branch);
+            let files = context.git.get_file_status()?;
+            context.output("files", files);
+        }
         Ok(())
     }
 }
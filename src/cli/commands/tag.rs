@@ -1,7 +1,8 @@
 use crate::cli::*;
+use crate::error::TanglError;
 use crate::model::QualifiedPath;
+use crate::util::u8_to_string;
 use clap::{Arg, Command};
-use std::error::Error;
 
 #[derive(Clone, Debug)]
 pub struct TagCommand;
@@ -17,26 +18,48 @@ impl CommandDefinition for TagCommand {
 }
 
 impl CommandInterface for TagCommand {
-    fn run_command(&self, context: &mut CommandContext) -> Result<(), Box<dyn Error>> {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
         let tag = context.arg_helper.get_argument_value::<String>("tag");
         let delete = context.arg_helper.get_argument_value::<String>("delete");
 
         match delete {
             Some(delete) => {
-                let output = context.git.delete_tag(&QualifiedPath::from(delete))?;
-                context.log_from_output(&output);
+                let tag_path = QualifiedPath::from(delete.clone());
+                if !context
+                    .git
+                    .get_current_node_path()?
+                    .get_tags()
+                    .contains(&tag_path)
+                {
+                    return Err(TanglError::invalid_path(format!(
+                        "Tag {} does not exist on the current branch",
+                        delete
+                    )));
+                }
+                let output = context.git.delete_tag(&tag_path)?;
+                if !output.status.success() {
+                    return Err(TanglError::git(u8_to_string(&output.stderr).trim()));
+                }
+                context.info(u8_to_string(&output.stdout));
+                context.output("deleted", delete);
                 return Ok(());
             }
             None => {}
         }
         match tag {
             Some(tag) => {
-                let output = context.git.create_tag(&QualifiedPath::from(tag))?;
-                context.log_from_output(&output);
+                let output = context.git.create_tag(&QualifiedPath::from(tag.clone()))?;
+                if !output.status.success() {
+                    return Err(TanglError::git(u8_to_string(&output.stderr).trim()));
+                }
+                context.info(u8_to_string(&output.stdout));
+                context.output("created", tag);
             }
             None => {
                 let current_branch = context.git.get_current_node_path()?;
-                let tags = current_branch.get_tags();
+                let mut tags = current_branch.get_tags();
+                tags.sort();
+                context.output("tags", &tags);
                 if tags.is_empty() {
                     context.info("No tags on current branch");
                 } else {
@@ -1,8 +1,9 @@
 use crate::cli::completion::CompletionHelper;
 use crate::cli::*;
+use crate::error::{EXIT_CODES, TanglError};
+use crate::logging::log_to_stderr;
 use crate::model::ImportFormat;
 use clap::{Arg, ArgAction, Command};
-use std::error::Error;
 
 #[derive(Clone, Debug)]
 pub struct TangleCommand {}
@@ -12,6 +13,7 @@ impl CommandDefinition for TangleCommand {
         Command::new("tangl")
             .arg_required_else_help(true)
             .allow_external_subcommands(true)
+            .after_help(EXIT_CODES)
             .arg(
                 Arg::new("format")
                     .short('f')
@@ -19,6 +21,14 @@ impl CommandDefinition for TangleCommand {
                     .default_value("native")
                     .help("Specify file import format for all commands"),
             )
+            .arg(
+                Arg::new("output")
+                    .long("output")
+                    .global(true)
+                    .value_parser(["text", "json"])
+                    .default_value("text")
+                    .help("Print the result as one JSON document on stdout and logs on stderr"),
+            )
     }
     fn get_subcommands(&self) -> Vec<Box<dyn CommandImpl>> {
         vec![
@@ -26,8 +36,13 @@ impl CommandDefinition for TangleCommand {
             Box::new(TreeCommand),
             Box::new(DeriveCommand),
             Box::new(CheckCommand),
+            Box::new(CacheCommand),
             Box::new(CheckoutCommand),
             Box::new(InitCommand),
+            Box::new(ImportCommand),
+            Box::new(ExportCommand),
+            Box::new(ConstraintCommand),
+            Box::new(ValidateCommand),
             Box::new(FeatureCommand),
             Box::new(ProductCommand),
             Box::new(TagCommand),
@@ -39,12 +54,18 @@ impl CommandDefinition for TangleCommand {
 }
 
 impl CommandInterface for TangleCommand {
-    fn run_command(&self, context: &mut CommandContext) -> Result<(), Box<dyn Error>> {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
         let format = context
             .arg_helper
             .get_argument_value::<String>("format")
             .unwrap();
         context.import_format = ImportFormat::from(format);
+        let output = context
+            .arg_helper
+            .get_argument_value::<String>("output")
+            .unwrap();
+        context.output_format = OutputFormat::from(output);
+        log_to_stderr(context.is_json());
         Ok(())
     }
 
@@ -52,7 +73,7 @@ impl CommandInterface for TangleCommand {
         &self,
         completion_helper: CompletionHelper,
         _context: &mut CommandContext,
-    ) -> Result<Vec<String>, Box<dyn Error>> {
+    ) -> Result<Vec<String>, TanglError> {
         match completion_helper.currently_editing() {
             Some(value) => match value.get_id().as_str() {
                 "format" => Ok(vec![
@@ -60,6 +81,7 @@ impl CommandInterface for TangleCommand {
                     "waffle".to_string(),
                     "uvl".to_string(),
                 ]),
+                "output" => Ok(vec!["text".to_string(), "json".to_string()]),
                 _ => Ok(vec![]),
             },
             None => Ok(vec![]),
@@ -1,6 +1,7 @@
 use crate::cli::*;
+use crate::error::TanglError;
+use crate::model::ExportedNode;
 use clap::Command;
-use std::error::Error;
 
 #[derive(Clone, Debug)]
 pub struct TreeCommand;
@@ -15,7 +16,7 @@ impl CommandDefinition for TreeCommand {
 }
 
 impl CommandInterface for TreeCommand {
-    fn run_command(&self, context: &mut CommandContext) -> Result<(), Box<dyn Error>> {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
         let show_tags = context
             .arg_helper
             .get_argument_value::<bool>("show_tags")
@@ -23,6 +24,7 @@ impl CommandInterface for TreeCommand {
         let current_node_path = context.git.get_current_node_path()?;
         let tree = current_node_path.display_tree(show_tags);
         context.info(tree);
+        context.output("tree", ExportedNode::build(&current_node_path));
         Ok(())
     }
 }
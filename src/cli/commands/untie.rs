@@ -1,22 +1,10 @@
 use crate::cli::*;
+use crate::error::TanglError;
+use crate::git::conflict::MergeResult;
 use crate::model::{NodePathType, QualifiedPath};
+use crate::util::u8_to_string;
 use clap::{Arg, Command};
-use std::error::Error;
-
-fn extract_feature_names(message: &str) -> Vec<QualifiedPath> {
-    let to_filter = vec!["# DO NOT EDIT OR REMOVE THIS COMMIT", "DERIVATION FINISHED"];
-    let trimmed = message.trim();
-    trimmed
-        .split("\n")
-        .filter_map(|e| {
-            if !to_filter.contains(&e) {
-                Some(QualifiedPath::from(e))
-            } else {
-                None
-            }
-        })
-        .collect()
-}
+use colored::Colorize;
 
 #[derive(Clone, Debug)]
 pub struct UntieCommand;
@@ -38,51 +26,67 @@ impl CommandDefinition for UntieCommand {
                     .long("feature")
                     .help("Feature to untie to"),
             )
+            .arg(dry_run())
+            .arg(autostash())
     }
 }
 
 impl CommandInterface for UntieCommand {
-    fn run_command(&self, context: &mut CommandContext) -> Result<(), Box<dyn Error>> {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
         let current = match context.git.get_current_node_path()?.concretize() {
             NodePathType::Product(path) => path,
             _ => {
-                return Err("Not on product branch".into());
+                return Err(TanglError::usage("Not on product branch"));
             }
         };
         let maybe_commit = context.arg_helper.get_argument_value::<String>("commit");
         let maybe_feature = context.arg_helper.get_argument_value::<String>("feature");
-        let mut commit_history = context
-            .git
-            .get_commit_history(&current.get_qualified_path())?;
-        if commit_history.is_empty() {
+        let product_path = current.get_qualified_path();
+        drop(current);
+        let record = match load_derivation_record(&context.git, &product_path)? {
+            Some(record) => record,
+            None => return Err(TanglError::usage("No derivation found for the product")),
+        };
+        let commit_history = context.git.get_commit_history(&product_path)?;
+        if !commit_history
+            .iter()
+            .any(|commit| commit.hash() == record.commit())
+        {
+            return Err(TanglError::usage(
+                "The derivation commit is not part of the product branch",
+            ));
+        }
+        // commits of the derivation and of the features merged by later
+        // updates are not changes made to the product
+        let mut derived = vec![record.commit().clone()];
+        derived.extend(
+            record
+                .features()
+                .iter()
+                .filter_map(|feature| feature.commit().cloned()),
+        );
+        let own_commits = context.git.get_commits_excluding(&product_path, &derived)?;
+        let product_commits: Vec<_> = commit_history
+            .iter()
+            .filter(|commit| own_commits.contains(commit.hash()))
+            .collect();
+        if product_commits.is_empty() {
             context.info("No commits on product");
             return Ok(());
         }
         let hash: String = match maybe_commit {
             Some(commit) => commit,
-            None => commit_history.get(0).unwrap().hash().clone(),
+            None => product_commits[0].hash().clone(),
         };
-        let mut has_valid = false;
-        let mut derivation_found = false;
-        let mut features: Vec<QualifiedPath> = Vec::new();
-        commit_history.reverse();
-        for commit in commit_history.iter() {
-            if commit.message().contains("DERIVATION FINISHED") {
-                if commit.hash() == &hash {
-                    return Err("Derivation commit cannot be untied".into());
-                }
-                derivation_found = true;
-                features.extend(extract_feature_names(&commit.message()));
-            } else {
-                if derivation_found && commit.hash() == &hash {
-                    has_valid = true;
-                    break;
-                }
-            }
+        if &hash == record.commit() {
+            return Err(TanglError::usage("Derivation commit cannot be untied"));
         }
-        if !has_valid {
-            return Err("Commit not found after initial derivation".into());
+        if !product_commits.iter().any(|commit| commit.hash() == &hash) {
+            return Err(TanglError::usage(
+                "Commit not found after initial derivation",
+            ));
         }
+        let features = record.feature_paths();
         let files_of_commit = context.git.get_files_changed_by_commit(&hash)?;
         let filtered = features
             .into_iter()
@@ -99,24 +103,234 @@ impl CommandInterface for UntieCommand {
             .collect::<Vec<QualifiedPath>>();
         let feature: QualifiedPath = match maybe_feature {
             Some(feature) => QualifiedPath::from(feature),
-            None => {
-                match filtered.len() {
-                    0 => { return Err("There are no features matching all changed files. Please choose one manually with the --feature parameter.".into()) }
-                    1 => { QualifiedPath::from(filtered[0].clone()) },
-                    _ => { return Err("There are multiple potential untie targets. Please choose one manually with the --feature parameter.".into() ) }
+            None => match filtered.len() {
+                0 => {
+                    return Err(TanglError::usage(
+                        "There are no features matching all changed files. Please choose one manually with the --feature parameter.",
+                    ));
+                }
+                1 => filtered[0].clone(),
+                _ => {
+                    return Err(TanglError::usage(
+                        "There are multiple potential untie targets. Please choose one manually with the --feature parameter.",
+                    ));
                 }
             },
         };
-        let current_path = context.git.get_current_qualified_path()?;
-        context.git.checkout(&feature)?;
-        let output = context.git.cherry_pick(&hash)?;
-        if !output.status.success() {
-            context.git.abort_merge()?;
-            context.info(format!("Unable to untie commit {}", &hash));
-        } else {
-            context.info(format!("Untied commit {} to {}", &hash, &feature));
+        if context.is_dry_run() {
+            let files = match context.git.cherry_pick_tree(&hash, &feature)? {
+                MergeResult::Clean => {
+                    context.info(format!("Would cherry-pick {} onto {}", &hash, &feature));
+                    vec![]
+                }
+                MergeResult::Conflict(files) => {
+                    let files: Vec<String> = files.iter().map(|file| file.path().clone()).collect();
+                    context.warn(format!(
+                        "Cherry-picking {} onto {} {} in {}",
+                        &hash,
+                        &feature,
+                        "will produce conflicts".red(),
+                        files.join(", ")
+                    ));
+                    files
+                }
+            };
+            context.info("Dry run, the repository was not changed.");
+            context.output("dry_run", true);
+            context.output("commit", &hash);
+            context.output("feature", &feature);
+            context.output("conflicts", files);
+            return Ok(());
         }
-        context.git.checkout(&current_path)?;
+        context.output("commit", &hash);
+        context.output("feature", &feature);
+        with_clean_working_tree(context, |context| {
+            let current_path = context.git.get_current_qualified_path()?;
+            context.git.checkout(&feature)?;
+            let output = context.git.cherry_pick(&hash)?;
+            if !output.status.success() {
+                // the guard aborts the cherry-pick and returns to the product
+                return Err(TanglError::git(format!(
+                    "Unable to untie commit {}: {}",
+                    &hash,
+                    u8_to_string(&output.stderr).trim()
+                )));
+            }
+            context.info(format!("Untied commit {} to {}", &hash, &feature));
+            context.git.checkout(&current_path)?;
+            Ok(())
+        })?;
+        context.output("untied", true);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::commands::TangleCommand;
+    use crate::git::interface::test_utils::{
+        commit_file_on_branch, populate_with_features, prepare_empty_git_repo,
+    };
+    use crate::git::interface::{GitInterface, GitPath};
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_untie_after_derivation() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        CommandRepository::new(
+            Box::new(DeriveCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        )
+        .execute(ArgSource::SUPPLIED(vec![
+            "derive", "-p", "myprod", "root/foo",
+        ]))
+        .unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        interface
+            .checkout(&QualifiedPath::from("/main/product/myprod"))
+            .unwrap();
+        let repo = CommandRepository::new(
+            Box::new(UntieCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        assert!(repo.execute(ArgSource::SUPPLIED(vec!["untie"])).is_ok());
+
+        fs::write(path_buf.join("file1"), "change").unwrap();
+        process::Command::new("git")
+            .args(["-C", path_buf.to_str().unwrap(), "commit", "-am", "change"])
+            .output()
+            .unwrap();
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "untie",
+            "-f",
+            "/main/feature/root/foo",
+        ]))
+        .unwrap();
+        let foo_history = interface
+            .get_commit_history(&QualifiedPath::from("/main/feature/root/foo"))
+            .unwrap();
+        assert_eq!(foo_history[0].message(), "change");
+    }
+
+    #[test]
+    fn test_untie_dry_run() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        CommandRepository::new(
+            Box::new(DeriveCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        )
+        .execute(ArgSource::SUPPLIED(vec![
+            "derive", "-p", "myprod", "root/foo",
+        ]))
+        .unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        let product = QualifiedPath::from("/main/product/myprod");
+        let foo = QualifiedPath::from("/main/feature/root/foo");
+        interface.checkout(&product).unwrap();
+        fs::write(path_buf.join("file1"), "change").unwrap();
+        process::Command::new("git")
+            .args(["-C", path_buf.to_str().unwrap(), "commit", "-am", "change"])
+            .output()
+            .unwrap();
+        let commit = interface.get_head_hash().unwrap();
+        assert!(
+            interface
+                .cherry_pick_tree(&commit, &foo)
+                .unwrap()
+                .is_clean()
+        );
+        commit_file_on_branch(
+            path_buf.clone(),
+            "_main/_feature/_root/foo",
+            "file1",
+            "other",
+        )
+        .unwrap();
+        let foo_commit = interface.get_commit_hash(&foo).unwrap();
+        assert!(
+            !interface
+                .cherry_pick_tree(&commit, &foo)
+                .unwrap()
+                .is_clean()
+        );
+
+        CommandRepository::new(
+            Box::new(UntieCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        )
+        .execute(ArgSource::SUPPLIED(vec![
+            "untie",
+            "--dry-run",
+            "-f",
+            "/main/feature/root/foo",
+        ]))
+        .unwrap();
+        assert_eq!(interface.get_commit_hash(&foo).unwrap(), foo_commit);
+        assert_eq!(interface.get_current_qualified_path().unwrap(), product);
+        assert!(interface.get_file_status().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_untie_after_product_update() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let repo = CommandRepository::new(
+            Box::new(TangleCommand {}),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "tangl", "derive", "-p", "myprod", "root/foo",
+        ]))
+        .unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_product/myprod", "file1", "change")
+            .unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        let product = QualifiedPath::from("/main/product/myprod");
+        let product_commit = interface.get_commit_hash(&product).unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/foo", "file2", "foo")
+            .unwrap();
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "tangl", "product", "update", "myprod",
+        ]))
+        .unwrap();
+        interface.checkout(&product).unwrap();
+
+        // neither the merge of the update nor the feature commit are untied
+        let (result, document) = repo.execute_with_output(ArgSource::SUPPLIED(vec![
+            "tangl",
+            "--output",
+            "json",
+            "untie",
+            "--dry-run",
+            "-f",
+            "/main/feature/root/foo",
+        ]));
+        result.unwrap();
+        assert_eq!(document.unwrap()["data"]["commit"], product_commit.as_str());
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "tangl",
+            "untie",
+            "-c",
+            product_commit.as_str(),
+            "-f",
+            "/main/feature/root/foo",
+        ]))
+        .unwrap();
+        let foo_history = interface
+            .get_commit_history(&QualifiedPath::from("/main/feature/root/foo"))
+            .unwrap();
+        assert_eq!(foo_history[0].message(), "Change file1");
+    }
+}
@@ -0,0 +1,309 @@
+use crate::cli::completion::*;
+use crate::cli::*;
+use crate::error::TanglError;
+use crate::model::*;
+use clap::{Arg, ArgAction, Command};
+use colored::Colorize;
+
+const FEATURES: &str = "features";
+const PARTIAL: &str = "partial";
+const DEAD: &str = "dead";
+const FALSE_OPTIONAL: &str = "false_optional";
+const COUNT: &str = "count";
+
+fn validate_selection(
+    context: &mut CommandContext,
+    formula: &FeatureModelFormula,
+    feature_root_path: &QualifiedPath,
+    features: Vec<String>,
+    partial: bool,
+) -> Result<(), TanglError> {
+    let mut selection = Vec::new();
+    for feature in features {
+        let path = feature_root_path.clone() + QualifiedPath::from(feature);
+        if !formula.contains(&path) {
+            return Err(TanglError::invalid_path(format!(
+                "{} is not a feature",
+                path
+            )));
+        }
+        selection.push(path);
+    }
+    match formula.validate(&selection, partial) {
+        Validation::Valid(configuration) => {
+            context.info(format!(
+                "The selection is {}",
+                "a valid configuration".green()
+            ));
+            if partial {
+                context.info("Example of a valid configuration:");
+                for path in &configuration {
+                    context.info(format!("  {}", path.strip_n_left(feature_root_path.len())));
+                }
+            }
+            context.output("valid", true);
+            context.output("configuration", configuration);
+            Ok(())
+        }
+        Validation::Invalid(reasons) => {
+            context.error(format!(
+                "The selection is {}, because",
+                "not a valid configuration".red()
+            ));
+            let reasons: Vec<String> = reasons
+                .iter()
+                .map(|reason| reason.display_relative_to(feature_root_path))
+                .collect();
+            for reason in &reasons {
+                context.error(format!("  {}", reason));
+            }
+            context.output("valid", false);
+            context.output("reasons", reasons);
+            Err(TanglError::model(
+                "The selection violates the feature model",
+            ))
+        }
+    }
+}
+
+fn log_features(
+    context: &mut CommandContext,
+    feature_root_path: &QualifiedPath,
+    title: &str,
+    features: Vec<QualifiedPath>,
+) {
+    if features.is_empty() {
+        context.info(format!("No {} features", title));
+    } else {
+        context.info(format!("{} {} features:", features.len(), title));
+    }
+    for feature in &features {
+        context.info(format!(
+            "  {}",
+            feature.strip_n_left(feature_root_path.len())
+        ));
+    }
+    context.output(&title.replace('-', "_"), features);
+}
+
+#[derive(Clone, Debug)]
+pub struct ValidateCommand;
+
+impl CommandDefinition for ValidateCommand {
+    fn build_command(&self) -> Command {
+        Command::new("validate")
+            .about("Validate a configuration or analyze the feature model")
+            .disable_help_subcommand(true)
+            .arg(
+                Arg::new(FEATURES)
+                    .action(ArgAction::Append)
+                    .help("Selected features. Without features, the feature model is analyzed"),
+            )
+            .arg(
+                Arg::new(PARTIAL)
+                    .long("partial")
+                    .action(ArgAction::SetTrue)
+                    .requires(FEATURES)
+                    .help(
+                        "Only check whether the selection can be extended to a valid configuration",
+                    ),
+            )
+            .arg(
+                Arg::new(DEAD)
+                    .long("dead")
+                    .action(ArgAction::SetTrue)
+                    .help("List features that cannot be selected"),
+            )
+            .arg(
+                Arg::new(FALSE_OPTIONAL)
+                    .long("false-optional")
+                    .action(ArgAction::SetTrue)
+                    .help("List optional features that are always selected with their parent"),
+            )
+            .arg(
+                Arg::new(COUNT)
+                    .long("count")
+                    .action(ArgAction::SetTrue)
+                    .help("Count the valid configurations"),
+            )
+    }
+}
+
+impl CommandInterface for ValidateCommand {
+    fn run_command(&self, context: &mut CommandContext) -> Result<(), TanglError> {
+        let constraints = load_constraints(&context.git)?;
+        let feature_root = match context.git.get_current_area()?.to_feature_root() {
+            Some(path) => path,
+            None => return Err(TanglError::usage("Nothing to validate: no features exist")),
+        };
+        let feature_root_path = feature_root.get_qualified_path();
+        let formula = FeatureModelFormula::new(&feature_root, &constraints);
+        drop(feature_root);
+
+        let flag = |id: &str| context.arg_helper.get_argument_value::<bool>(id) == Some(true);
+        let (mut dead, mut false_optional, mut count) =
+            (flag(DEAD), flag(FALSE_OPTIONAL), flag(COUNT));
+        let partial = flag(PARTIAL);
+        let features = context.arg_helper.get_argument_values::<String>(FEATURES);
+        if features.is_none() && !dead && !false_optional && !count {
+            (dead, false_optional, count) = (true, true, true);
+        }
+
+        if let Some(features) = features {
+            validate_selection(context, &formula, &feature_root_path, features, partial)?;
+        }
+        if !(dead || false_optional || count) {
+            return Ok(());
+        }
+        if formula.is_void() {
+            return Err(TanglError::model(
+                "The feature model is void: no configuration contains any feature",
+            ));
+        }
+        if dead {
+            log_features(context, &feature_root_path, "dead", formula.dead_features());
+        }
+        if false_optional {
+            log_features(
+                context,
+                &feature_root_path,
+                "false-optional",
+                formula.false_optional_features(),
+            );
+        }
+        if count {
+            let n_configurations = formula.count_configurations();
+            context.info(format!("{} valid configurations", n_configurations));
+            context.output("configurations", n_configurations);
+        }
+        Ok(())
+    }
+    fn shell_complete(
+        &self,
+        completion_helper: CompletionHelper,
+        context: &mut CommandContext,
+    ) -> Result<Vec<String>, TanglError> {
+        let maybe_feature_root = context.git.get_current_area()?.to_feature_root();
+        let result = match (completion_helper.currently_editing(), maybe_feature_root) {
+            (Some(arg), Some(feature_root)) if arg.get_id().as_str() == FEATURES => {
+                let feature_root_path = feature_root.get_qualified_path();
+                let to_filter = completion_helper
+                    .get_appendix_of(FEATURES)
+                    .into_iter()
+                    .map(|p| feature_root_path.clone() + QualifiedPath::from(p))
+                    .collect();
+                let transformer = ByQPathFilteringNodePathTransformer::new(
+                    to_filter,
+                    QPathFilteringMode::EXCLUDE,
+                );
+                completion_helper.complete_qualified_paths(
+                    feature_root_path,
+                    transformer
+                        .transform(feature_root.iter_children_req())
+                        .filter(|path| matches!(path.get_type(), NodeType::Feature))
+                        .map(|path| path.get_qualified_path()),
+                )
+            }
+            _ => vec![],
+        };
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::commands::TangleCommand;
+    use crate::git::interface::test_utils::{populate_with_features, prepare_empty_git_repo};
+    use crate::git::interface::{GitInterface, GitPath};
+    use serde_json::json;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_validate() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let mut interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        interface
+            .update_variability(vec![(
+                QualifiedPath::from("/main/feature/root"),
+                Variability::new(true, GroupKind::Alternative),
+            )])
+            .unwrap();
+        let repo = CommandRepository::new(
+            Box::new(ValidateCommand),
+            GitPath::CustomDirectory(path_buf),
+        );
+        repo.execute(ArgSource::SUPPLIED(vec!["validate", "root", "root/foo"]))
+            .unwrap();
+        repo.execute(ArgSource::SUPPLIED(vec![
+            "validate",
+            "--partial",
+            "root/bar",
+        ]))
+        .unwrap();
+        assert!(
+            repo.execute(ArgSource::SUPPLIED(vec!["validate", "root"]))
+                .is_err()
+        );
+        assert!(
+            repo.execute(ArgSource::SUPPLIED(vec![
+                "validate", "root", "root/foo", "root/bar",
+            ]))
+            .is_err()
+        );
+        assert!(
+            repo.execute(ArgSource::SUPPLIED(vec!["validate", "root/unknown"]))
+                .is_err()
+        );
+        repo.execute(ArgSource::SUPPLIED(vec!["validate"])).unwrap();
+    }
+
+    #[test]
+    fn test_validate_json_output() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let mut interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        interface
+            .update_variability(vec![(
+                QualifiedPath::from("/main/feature/root"),
+                Variability::new(true, GroupKind::Alternative),
+            )])
+            .unwrap();
+        let repo = CommandRepository::new(
+            Box::new(TangleCommand {}),
+            GitPath::CustomDirectory(path_buf),
+        );
+        let (result, document) = repo.execute_with_output(ArgSource::SUPPLIED(vec![
+            "tangl", "--output", "json", "validate", "root", "root/foo",
+        ]));
+        result.unwrap();
+        let data = &document.unwrap()["data"];
+        assert_eq!(data["valid"], true);
+        assert_eq!(
+            data["configuration"],
+            json!(["/main/feature/root", "/main/feature/root/foo"])
+        );
+
+        let (result, document) = repo.execute_with_output(ArgSource::SUPPLIED(vec![
+            "tangl", "--output", "json", "validate", "root",
+        ]));
+        assert!(matches!(result, Err(TanglError::Model(_))));
+        let data = &document.unwrap()["data"];
+        assert_eq!(data["valid"], false);
+        assert_eq!(data["reasons"].as_array().unwrap().len(), 1);
+
+        let (result, document) = repo.execute_with_output(ArgSource::SUPPLIED(vec![
+            "tangl", "--output", "json", "validate",
+        ]));
+        result.unwrap();
+        let data = &document.unwrap()["data"];
+        assert_eq!(data["dead"], json!([]));
+        assert_eq!(data["configurations"], 3);
+    }
+}
@@ -1,6 +1,8 @@
 use clap::{Arg, ArgAction};
 
 pub const VERBOSE: &str = "verbose";
+pub const DRY_RUN: &str = "dry_run";
+pub const AUTOSTASH: &str = "autostash";
 
 pub fn show_tags() -> Arg {
     Arg::new("show_tags")
@@ -9,6 +11,20 @@ pub fn show_tags() -> Arg {
         .help("Also show tags")
 }
 
+pub fn dry_run() -> Arg {
+    Arg::new(DRY_RUN)
+        .long("dry-run")
+        .action(ArgAction::SetTrue)
+        .help("Print what would be done without changing the repository")
+}
+
+pub fn autostash() -> Arg {
+    Arg::new(AUTOSTASH)
+        .long("autostash")
+        .action(ArgAction::SetTrue)
+        .help("Stash uncommitted changes before switching branches and restore them afterwards")
+}
+
 pub fn delete(force: bool) -> Arg {
     let short = if force { 'D' } else { 'd' };
     Arg::new("delete").short(short)
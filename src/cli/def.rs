@@ -1,13 +1,15 @@
-use crate::cli::ArgHelper;
 use crate::cli::completion::CompletionHelper;
+use crate::cli::{ArgHelper, DRY_RUN};
+use crate::error::TanglError;
 use crate::git::interface::GitInterface;
 use crate::model::ImportFormat;
 use crate::util::u8_to_string;
 use clap::{ArgMatches, Command};
 use colored::{Color, Colorize};
 use log::{LevelFilter, debug, error, info, trace, warn};
-use std::error::Error;
-use std::fmt::{Debug, Display, Formatter};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt::Debug;
 use std::process::Output;
 
 #[derive(Debug)]
@@ -25,8 +27,9 @@ impl CommandMap {
                 .get_subcommands()
                 .into_iter()
                 .map(|c| {
-                    let sub_command = c.build_command();
-                    children.push(CommandMap::new(c));
+                    let child = CommandMap::new(c);
+                    let sub_command = child.clap_command.clone();
+                    children.push(child);
                     sub_command
                 })
                 .collect::<Vec<Command>>(),
@@ -68,6 +71,23 @@ impl CommandMap {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    JSON,
+}
+
+impl<S: Into<String>> From<S> for OutputFormat {
+    fn from(value: S) -> Self {
+        let real = value.into();
+        match real.to_uppercase().as_str() {
+            "TEXT" => OutputFormat::Text,
+            "JSON" => OutputFormat::JSON,
+            _ => unreachable!("Output format '{}' is not supported", real),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct CommandContext<'a> {
     pub current_command: &'a CommandMap,
@@ -75,6 +95,8 @@ pub struct CommandContext<'a> {
     pub git: GitInterface,
     pub arg_helper: ArgHelper,
     pub import_format: ImportFormat,
+    pub output_format: OutputFormat,
+    output: Map<String, Value>,
 }
 
 impl CommandContext<'_> {
@@ -91,6 +113,42 @@ impl CommandContext<'_> {
             git,
             arg_helper,
             import_format,
+            output_format: OutputFormat::Text,
+            output: Map::new(),
+        }
+    }
+    pub fn is_json(&self) -> bool {
+        self.output_format == OutputFormat::JSON
+    }
+    /// Returns whether the command should only report what it would do.
+    pub fn is_dry_run(&self) -> bool {
+        self.arg_helper.has_arg(DRY_RUN)
+            && self.arg_helper.get_argument_value::<bool>(DRY_RUN) == Some(true)
+    }
+    /// Adds a value to the document printed with `--output json`. Does
+    /// nothing for text output.
+    pub fn output<T: Serialize>(&mut self, key: &str, value: T) {
+        if self.is_json() {
+            let value = serde_json::to_value(value).unwrap_or(Value::Null);
+            self.output.insert(key.to_string(), value);
+        }
+    }
+    pub fn get_output(&self) -> &Map<String, Value> {
+        &self.output
+    }
+    /// Removes and returns the values added so far, so they can be nested in
+    /// the document.
+    pub fn take_output(&mut self) -> Map<String, Value> {
+        std::mem::take(&mut self.output)
+    }
+    /// Prints text that is the result of a command, like an export, to
+    /// stdout. With `--output json` stdout is reserved for the document, so
+    /// the text is logged instead.
+    pub fn print<S: Into<String>>(&self, text: S) {
+        if self.is_json() {
+            self.info(text);
+        } else {
+            print!("{}", text.into());
         }
     }
     pub fn log_from_output(&self, output: &Output) {
@@ -115,7 +173,7 @@ impl CommandContext<'_> {
 
     fn log<S: Into<String>>(&self, message: S, level: LevelFilter) {
         let converted = message.into();
-        let trimmed = converted.trim();
+        let trimmed = converted.trim_end();
         if converted.len() > 0 {
             match level {
                 LevelFilter::Error => error!("{}", trimmed),
@@ -137,35 +195,17 @@ pub trait CommandDefinition: Debug {
 }
 
 pub trait CommandInterface: Debug {
-    fn run_command(&self, _context: &mut CommandContext) -> Result<(), Box<dyn Error>> {
+    fn run_command(&self, _context: &mut CommandContext) -> Result<(), TanglError> {
         Ok(())
     }
     fn shell_complete(
         &self,
         _completion_helper: CompletionHelper,
         _context: &mut CommandContext,
-    ) -> Result<Vec<String>, Box<dyn Error>> {
+    ) -> Result<Vec<String>, TanglError> {
         Ok(Vec::new())
     }
 }
 
 pub trait CommandImpl: CommandDefinition + CommandInterface + Debug {}
 impl<T: CommandDefinition + CommandInterface + Debug> CommandImpl for T {}
-
-#[derive(Debug, Clone)]
-pub struct CommandError {
-    msg: String,
-}
-impl CommandError {
-    pub fn new(msg: &str) -> CommandError {
-        CommandError {
-            msg: msg.to_string(),
-        }
-    }
-}
-impl Display for CommandError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.msg)
-    }
-}
-impl Error for CommandError {}
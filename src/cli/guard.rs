@@ -0,0 +1,236 @@
+use crate::cli::{AUTOSTASH, CommandContext};
+use crate::error::TanglError;
+use crate::model::QualifiedPath;
+use crate::util::u8_to_string;
+
+const AUTOSTASH_MESSAGE: &str = "tangl autostash";
+
+/// Refuses to start while a merge or cherry-pick is in progress.
+fn check_no_operation_in_progress(context: &CommandContext) -> Result<(), TanglError> {
+    if context.git.is_merge_in_progress()? {
+        return Err(TanglError::usage(
+            "A merge is in progress, conclude or abort it first",
+        ));
+    }
+    if context.git.is_cherry_pick_in_progress()? {
+        return Err(TanglError::usage(
+            "A cherry-pick is in progress, conclude or abort it first",
+        ));
+    }
+    Ok(())
+}
+
+/// Stashes changes of tracked files if `--autostash` is given, refuses to
+/// start otherwise. Untracked files are kept; checking out a branch that
+/// would overwrite them fails the operation. Returns whether changes were
+/// stashed.
+fn stash_changes(context: &mut CommandContext) -> Result<bool, TanglError> {
+    let changed: Vec<String> = context
+        .git
+        .get_file_status()?
+        .into_iter()
+        .filter(|file| file.status != "??")
+        .map(|file| file.path)
+        .collect();
+    if changed.is_empty() {
+        return Ok(false);
+    }
+    let autostash = context.arg_helper.has_arg(AUTOSTASH)
+        && context.arg_helper.get_argument_value::<bool>(AUTOSTASH) == Some(true);
+    if !autostash {
+        return Err(TanglError::usage(format!(
+            "Uncommitted changes in {}. Commit or stash them, or use --autostash",
+            changed.join(", ")
+        )));
+    }
+    let output = context.git.stash_push(AUTOSTASH_MESSAGE)?;
+    if !output.status.success() {
+        return Err(TanglError::git(format!(
+            "Cannot stash changes: {}",
+            u8_to_string(&output.stderr).trim()
+        )));
+    }
+    context.info(format!("Stashed changes in {}", changed.join(", ")));
+    Ok(true)
+}
+
+/// Aborts what a failed operation left unfinished and checks out the
+/// original branch again.
+fn restore_branch(context: &mut CommandContext, original: &QualifiedPath) {
+    let restored = (|| -> Result<(), TanglError> {
+        if context.git.is_merge_in_progress()? {
+            context.git.abort_merge()?;
+        }
+        if context.git.is_cherry_pick_in_progress()? {
+            context.git.abort_cherry_pick()?;
+        }
+        if &context.git.get_current_qualified_path()? != original {
+            context.git.checkout(original)?;
+            context.info(format!("Returned to {}", original));
+        }
+        Ok(())
+    })();
+    if let Err(err) = restored {
+        context.error(format!("Cannot return to {}: {}", original, err));
+    }
+}
+
+/// Runs an operation that checks out other branches.
+///
+/// The operation only starts on a clean index and working tree and while no
+/// merge or cherry-pick is in progress. With `--autostash`, changes are
+/// stashed first and restored on the original branch afterwards. If the
+/// operation fails, an unfinished merge or cherry-pick is aborted and the
+/// original branch is checked out again. Operations that stop at a conflict
+/// for the user to resolve succeed, so their merge is kept; stashed changes
+/// then stay in the stash.
+pub fn with_clean_working_tree(
+    context: &mut CommandContext,
+    operation: impl FnOnce(&mut CommandContext) -> Result<(), TanglError>,
+) -> Result<(), TanglError> {
+    check_no_operation_in_progress(context)?;
+    let original = context.git.get_current_qualified_path()?;
+    let stashed = stash_changes(context)?;
+    let result = operation(context);
+    if result.is_err() {
+        restore_branch(context, &original);
+    }
+    if !stashed {
+        return result;
+    }
+    if context.git.is_merge_in_progress()? || context.git.get_current_qualified_path()? != original
+    {
+        context.warn("Your changes remain stashed, run 'git stash pop' to restore them");
+        return result;
+    }
+    let output = context.git.stash_pop()?;
+    if !output.status.success() {
+        context.warn(format!(
+            "Cannot restore the stashed changes, they remain in the stash: {}",
+            u8_to_string(&output.stderr).trim()
+        ));
+        return result.and(Err(TanglError::git("Cannot restore the stashed changes")));
+    }
+    context.info("Restored the stashed changes");
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{ArgSource, CommandRepository, SpreadCommand};
+    use crate::git::interface::test_utils::{
+        commit_file_on_branch, populate_with_features, prepare_empty_git_repo,
+    };
+    use crate::git::interface::{GitInterface, GitPath};
+    use crate::model::ImportFormat;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_guard_refuses_and_autostashes() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/root", "file2", "root").unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        let root = QualifiedPath::from("/main/feature/root");
+        interface.checkout(&root).unwrap();
+        fs::write(path_buf.join("file1"), "local change").unwrap();
+        let repo = CommandRepository::new(
+            Box::new(SpreadCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+
+        let result = repo.execute(ArgSource::SUPPLIED(vec!["spread"]));
+        assert_eq!(result.unwrap_err().exit_code(), 2);
+        let foo_files = |interface: &GitInterface| {
+            interface
+                .get_files_managed_by_branch(&QualifiedPath::from("/main/feature/root/foo"))
+                .unwrap()
+        };
+        assert!(!foo_files(&interface).contains(&"file2".to_string()));
+
+        repo.execute(ArgSource::SUPPLIED(vec!["spread", "--autostash"]))
+            .unwrap();
+        assert!(foo_files(&interface).contains(&"file2".to_string()));
+        assert_eq!(interface.get_current_qualified_path().unwrap(), root);
+        assert_eq!(
+            fs::read_to_string(path_buf.join("file1")).unwrap(),
+            "local change"
+        );
+    }
+
+    #[test]
+    fn test_guard_returns_to_original_branch() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/root", "file1", "root").unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/bar", "file1", "bar")
+            .unwrap();
+        let root = QualifiedPath::from("/main/feature/root");
+        let bar = QualifiedPath::from("/main/feature/root/bar");
+        GitInterface::in_directory(path_buf.clone())
+            .unwrap()
+            .checkout(&root)
+            .unwrap();
+        fs::write(path_buf.join("file1"), "local change").unwrap();
+
+        let repo = CommandRepository::new(
+            Box::new(SpreadCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+        let mut context = repo
+            .build_context(
+                ArgSource::SUPPLIED(vec!["spread", "--autostash"]),
+                ImportFormat::Native,
+            )
+            .unwrap();
+        let result = with_clean_working_tree(&mut context, |context| {
+            context.git.checkout(&bar)?;
+            context.git.merge(&vec![root.clone()])?;
+            Err(TanglError::conflicts("Merging failed"))
+        });
+        assert_eq!(result.unwrap_err().exit_code(), 1);
+        assert_eq!(context.git.get_current_qualified_path().unwrap(), root);
+        assert!(!context.git.is_merge_in_progress().unwrap());
+        assert_eq!(
+            fs::read_to_string(path_buf.join("file1")).unwrap(),
+            "local change"
+        );
+    }
+
+    #[test]
+    fn test_guard_fails_if_untracked_files_would_be_overwritten() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/root", "file2", "root").unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/foo", "file3", "foo")
+            .unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        let root = QualifiedPath::from("/main/feature/root");
+        let foo = QualifiedPath::from("/main/feature/root/foo");
+        interface.checkout(&root).unwrap();
+        fs::write(path_buf.join("file3"), "untracked").unwrap();
+        let foo_commit = interface.get_commit_hash(&foo).unwrap();
+        let repo = CommandRepository::new(
+            Box::new(SpreadCommand),
+            GitPath::CustomDirectory(path_buf.clone()),
+        );
+
+        let result = repo.execute(ArgSource::SUPPLIED(vec!["spread"]));
+        assert_eq!(result.unwrap_err().exit_code(), 5);
+        assert_eq!(interface.get_current_qualified_path().unwrap(), root);
+        assert_eq!(interface.get_commit_hash(&foo).unwrap(), foo_commit);
+        assert_eq!(
+            fs::read_to_string(path_buf.join("file3")).unwrap(),
+            "untracked"
+        );
+    }
+}
@@ -3,10 +3,12 @@ mod commands;
 mod common;
 mod completion;
 pub mod def;
+mod guard;
 pub mod repo;
 
 pub use arg::*;
 pub use commands::*;
 pub use common::*;
 pub use def::*;
+pub use guard::*;
 pub use repo::*;
@@ -1,10 +1,23 @@
 use crate::cli::{ArgHelper, CommandContext, CommandImpl, CommandMap, VERBOSE};
+use crate::error::TanglError;
 use crate::git::interface::{GitInterface, GitPath};
 use crate::model::ImportFormat;
 use clap::ArgMatches;
 use log::LevelFilter;
-use std::error::Error;
+use serde_json::{Map, Value};
 use std::ffi::OsString;
+use std::io;
+
+/// Joins the names of the invoked subcommands, e.g. `cache clear`.
+fn command_name(matches: &ArgMatches) -> String {
+    let mut names = vec![];
+    let mut current = matches;
+    while let Some((name, sub_matches)) = current.subcommand() {
+        names.push(name);
+        current = sub_matches;
+    }
+    names.join(" ")
+}
 
 pub enum ArgSource<'a> {
     CLI,
@@ -33,10 +46,7 @@ impl CommandRepository {
             work_path,
         }
     }
-    fn execute_recursive<'a>(
-        &self,
-        mut context: CommandContext<'a>,
-    ) -> Result<CommandContext<'a>, Box<dyn Error>> {
+    fn execute_recursive<'a>(&self, context: &mut CommandContext<'a>) -> Result<(), TanglError> {
         if context.arg_helper.has_arg(VERBOSE) {
             match context.arg_helper.get_count(VERBOSE) {
                 0 => log::set_max_level(LevelFilter::Info),
@@ -46,11 +56,8 @@ impl CommandRepository {
         } else {
             log::set_max_level(LevelFilter::Info)
         }
-        let current = context.current_command;
-        match current.command.run_command(&mut context) {
-            Ok(_) => {}
-            Err(err) => return Err(err),
-        };
+        let current: &'a CommandMap = context.current_command;
+        current.command.run_command(context)?;
         match context.arg_helper.get_matches().subcommand() {
             Some((sub, sub_args)) => {
                 if let Some(child) = current.find_child(sub) {
@@ -62,39 +69,89 @@ impl CommandRepository {
                     let output = std::process::Command::new("git")
                         .arg(sub)
                         .args(ext_args)
-                        .output()
-                        .expect("failed to execute git");
+                        .output()?;
                     context.log_from_output(&output);
-                    Ok(context)
+                    if !output.status.success() {
+                        return Err(TanglError::git(format!("git {} failed", sub)));
+                    }
+                    Ok(())
                 }
             }
-            _ => Ok(context),
+            _ => Ok(()),
         }
     }
-    pub fn execute(&self, arg_source: ArgSource) -> Result<(), Box<dyn Error>> {
-        let context = self.build_context(arg_source, ImportFormat::Native);
-        self.execute_recursive(context)?;
-        Ok(())
+    pub fn execute(&self, arg_source: ArgSource) -> Result<(), TanglError> {
+        let (result, document) = self.execute_with_output(arg_source);
+        if let Some(document) = document {
+            let document = serde_json::to_string_pretty(&document).map_err(io::Error::from)?;
+            println!("{}", document);
+        }
+        result
     }
-    pub fn build_context(
+    /// Runs the command and returns the document requested with
+    /// `--output json`, which also describes failed runs.
+    pub fn execute_with_output(
         &self,
         arg_source: ArgSource,
-        import_format: ImportFormat,
-    ) -> CommandContext {
-        let args: ArgMatches = match arg_source {
+    ) -> (Result<(), TanglError>, Option<Value>) {
+        let matches = self.get_matches(arg_source);
+        let command = command_name(&matches);
+        let (result, output) =
+            match self.context_from_matches(matches.clone(), ImportFormat::Native) {
+                Ok(mut context) => {
+                    let result = self.execute_recursive(&mut context);
+                    let output = context.is_json().then(|| context.get_output().clone());
+                    (result, output)
+                }
+                // the context did not set the output format, so look at the arguments
+                Err(err) => {
+                    let json = matches
+                        .try_get_one::<String>("output")
+                        .is_ok_and(|format| format.is_some_and(|format| format == "json"));
+                    (Err(err), json.then(Map::new))
+                }
+            };
+        let output = match output {
+            Some(output) => output,
+            None => return (result, None),
+        };
+        let mut document = Map::new();
+        document.insert("command".to_string(), Value::String(command));
+        document.insert("success".to_string(), Value::Bool(result.is_ok()));
+        if let Err(err) = result.as_ref() {
+            document.insert("error".to_string(), Value::String(err.to_string()));
+        }
+        document.insert("data".to_string(), Value::Object(output));
+        (result, Some(Value::Object(document)))
+    }
+    fn get_matches(&self, arg_source: ArgSource) -> ArgMatches {
+        match arg_source {
             ArgSource::CLI => self.command_map.clap_command.clone().get_matches(),
             ArgSource::SUPPLIED(supplied) => self
                 .command_map
                 .clap_command
                 .clone()
                 .get_matches_from(supplied),
-        };
-        CommandContext::new(
+        }
+    }
+    fn context_from_matches(
+        &self,
+        matches: ArgMatches,
+        import_format: ImportFormat,
+    ) -> Result<CommandContext<'_>, TanglError> {
+        Ok(CommandContext::new(
             &self.command_map,
             &self.command_map,
-            GitInterface::new(self.work_path.clone()),
-            ArgHelper::new(args),
+            GitInterface::new(self.work_path.clone())?,
+            ArgHelper::new(matches),
             import_format,
-        )
+        ))
+    }
+    pub fn build_context(
+        &self,
+        arg_source: ArgSource,
+        import_format: ImportFormat,
+    ) -> Result<CommandContext<'_>, TanglError> {
+        self.context_from_matches(self.get_matches(arg_source), import_format)
     }
 }
@@ -0,0 +1,107 @@
+use crate::git::error::GitError;
+use crate::git::persistency::PersistencyError;
+use crate::model::{ImportError, WrongNodeTypeError};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
+
+/// Exit codes of tangl, also listed in the help of the `tangl` command.
+pub const EXIT_CODES: &str = "Exit codes:
+  0  success
+  1  conflicts were found
+  2  invalid arguments, or the command does not apply to the current state
+  3  a feature, product or branch does not exist or has the wrong type
+  4  the feature model or a selection of features is invalid
+  5  a git command failed
+  6  tangl metadata cannot be read or written
+  7  an I/O error occurred";
+
+/// Every error a command can end with. The variant decides the exit code of
+/// the process, see [EXIT_CODES].
+#[derive(Debug)]
+pub enum TanglError {
+    Conflicts(String),
+    Usage(String),
+    InvalidPath(String),
+    Model(String),
+    Git(String),
+    Metadata(PersistencyError),
+    Io(io::Error),
+}
+
+impl TanglError {
+    pub fn conflicts<S: Into<String>>(msg: S) -> Self {
+        TanglError::Conflicts(msg.into())
+    }
+    pub fn usage<S: Into<String>>(msg: S) -> Self {
+        TanglError::Usage(msg.into())
+    }
+    pub fn invalid_path<S: Into<String>>(msg: S) -> Self {
+        TanglError::InvalidPath(msg.into())
+    }
+    pub fn model<S: Into<String>>(msg: S) -> Self {
+        TanglError::Model(msg.into())
+    }
+    pub fn git<S: Into<String>>(msg: S) -> Self {
+        TanglError::Git(msg.into())
+    }
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TanglError::Conflicts(_) => 1,
+            TanglError::Usage(_) => 2,
+            TanglError::InvalidPath(_) => 3,
+            TanglError::Model(_) => 4,
+            TanglError::Git(_) => 5,
+            TanglError::Metadata(_) => 6,
+            TanglError::Io(_) => 7,
+        }
+    }
+}
+
+impl Display for TanglError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TanglError::Conflicts(msg)
+            | TanglError::Usage(msg)
+            | TanglError::InvalidPath(msg)
+            | TanglError::Model(msg)
+            | TanglError::Git(msg) => write!(f, "{}", msg),
+            TanglError::Metadata(err) => err.fmt(f),
+            TanglError::Io(err) => err.fmt(f),
+        }
+    }
+}
+impl Error for TanglError {}
+
+impl From<GitError> for TanglError {
+    fn from(value: GitError) -> Self {
+        match value {
+            GitError::Io(err) => TanglError::Io(err),
+            GitError::GitInterface(err) => TanglError::Git(err.to_string()),
+            GitError::WrongNodeType(err) => err.into(),
+            GitError::Persistency(err) => TanglError::Metadata(err),
+            GitError::DetachedHead => TanglError::Usage(value.to_string()),
+            GitError::UnmanagedBranch(_) => TanglError::InvalidPath(value.to_string()),
+        }
+    }
+}
+impl From<WrongNodeTypeError> for TanglError {
+    fn from(value: WrongNodeTypeError) -> Self {
+        TanglError::InvalidPath(value.to_string())
+    }
+}
+impl From<PersistencyError> for TanglError {
+    fn from(value: PersistencyError) -> Self {
+        TanglError::Metadata(value)
+    }
+}
+impl From<ImportError> for TanglError {
+    fn from(value: ImportError) -> Self {
+        TanglError::Model(value.to_string())
+    }
+}
+impl From<io::Error> for TanglError {
+    fn from(value: io::Error) -> Self {
+        TanglError::Io(value)
+    }
+}
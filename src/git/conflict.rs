@@ -1,13 +1,178 @@
 use crate::git::error::GitError;
 use crate::git::interface::GitInterface;
+use crate::git::persistency::{Document, PersistencyError};
+use crate::logging::ProgressIndicator;
 use crate::model::QualifiedPath;
 use colored::Colorize;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Display;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+const CONFLICT_START: &str = "<<<<<<<";
+const CONFLICT_BASE: &str = "|||||||";
+const CONFLICT_SEPARATOR: &str = "=======";
+const CONFLICT_END: &str = ">>>>>>>";
+
+/// Consecutive lines of one version of a file. Lines are counted from 1; an
+/// empty range lies after line `start - 1`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LineRange {
+    start: usize,
+    count: usize,
+}
+
+impl LineRange {
+    pub fn start(&self) -> usize {
+        self.start
+    }
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+impl Display for LineRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.count {
+            0 => write!(f, "none after line {}", self.start - 1),
+            1 => write!(f, "line {}", self.start),
+            _ => write!(f, "lines {}-{}", self.start, self.start + self.count - 1),
+        }
+    }
+}
+
+/// A region both sides of a merge changed differently. `left` refers to the
+/// file of the first feature, `right` to the file of the second one.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConflictHunk {
+    left: LineRange,
+    right: LineRange,
+}
+
+impl ConflictHunk {
+    fn swapped(&self) -> Self {
+        Self {
+            left: self.right.clone(),
+            right: self.left.clone(),
+        }
+    }
+    pub fn left(&self) -> &LineRange {
+        &self.left
+    }
+    pub fn right(&self) -> &LineRange {
+        &self.right
+    }
+}
+
+/// A file that could not be merged. Conflicts that are not about the
+/// content, e.g. a file deleted on one side, have no hunks.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConflictFile {
+    path: String,
+    hunks: Vec<ConflictHunk>,
+}
+
+impl ConflictFile {
+    /// Reads the hunks from the conflict markers of a merged file.
+    pub fn parse(path: &str, merged: &str) -> Self {
+        enum Section {
+            Both,
+            Left,
+            Base,
+            Right,
+        }
+        let mut hunks = Vec::new();
+        let mut section = Section::Both;
+        let (mut left_line, mut right_line) = (0, 0);
+        let mut hunk = ConflictHunk {
+            left: LineRange { start: 0, count: 0 },
+            right: LineRange { start: 0, count: 0 },
+        };
+        for line in merged.lines() {
+            match section {
+                Section::Both if line.starts_with(CONFLICT_START) => {
+                    hunk.left = LineRange {
+                        start: left_line + 1,
+                        count: 0,
+                    };
+                    hunk.right = LineRange {
+                        start: right_line + 1,
+                        count: 0,
+                    };
+                    section = Section::Left;
+                }
+                Section::Both => {
+                    left_line += 1;
+                    right_line += 1;
+                }
+                Section::Left | Section::Base if line.starts_with(CONFLICT_SEPARATOR) => {
+                    section = Section::Right;
+                }
+                Section::Left if line.starts_with(CONFLICT_BASE) => section = Section::Base,
+                Section::Left => {
+                    left_line += 1;
+                    hunk.left.count += 1;
+                }
+                Section::Base => {}
+                Section::Right if line.starts_with(CONFLICT_END) => {
+                    hunks.push(hunk.clone());
+                    section = Section::Both;
+                }
+                Section::Right => {
+                    right_line += 1;
+                    hunk.right.count += 1;
+                }
+            }
+        }
+        Self {
+            path: path.to_string(),
+            hunks,
+        }
+    }
+    pub fn path(&self) -> &String {
+        &self.path
+    }
+    pub fn hunks(&self) -> &Vec<ConflictHunk> {
+        &self.hunks
+    }
+    fn swapped(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            hunks: self.hunks.iter().map(ConflictHunk::swapped).collect(),
+        }
+    }
+}
+
+/// The outcome of merging branches in memory.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MergeResult {
+    Clean,
+    Conflict(Vec<ConflictFile>),
+}
+
+impl MergeResult {
+    pub fn is_clean(&self) -> bool {
+        matches!(self, MergeResult::Clean)
+    }
+    /// The result of merging the branches the other way around.
+    fn swapped(&self) -> Self {
+        match self {
+            MergeResult::Clean => MergeResult::Clean,
+            MergeResult::Conflict(files) => {
+                MergeResult::Conflict(files.iter().map(ConflictFile::swapped).collect())
+            }
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum ConflictStatistic {
     OK((QualifiedPath, QualifiedPath)),
-    CONFLICT((QualifiedPath, QualifiedPath)),
+    CONFLICT((QualifiedPath, QualifiedPath), Vec<ConflictFile>),
     ERROR((QualifiedPath, QualifiedPath), GitError),
 }
 
@@ -18,8 +183,8 @@ impl PartialEq for ConflictStatistic {
                 Self::OK((self_l, self_r)) => self_l == other_l && self_r == other_r,
                 _ => false,
             },
-            Self::CONFLICT((other_l, other_r)) => match self {
-                Self::CONFLICT((self_l, self_r)) => self_l == other_l && self_r == other_r,
+            Self::CONFLICT((other_l, other_r), _) => match self {
+                Self::CONFLICT((self_l, self_r), _) => self_l == other_l && self_r == other_r,
                 _ => false,
             },
             Self::ERROR((other_l, other_r), _) => match self {
@@ -30,13 +195,30 @@ impl PartialEq for ConflictStatistic {
     }
 }
 
+impl ConflictStatistic {
+    pub fn pair(&self) -> (&QualifiedPath, &QualifiedPath) {
+        match self {
+            ConflictStatistic::OK((l, r)) => (l, r),
+            ConflictStatistic::CONFLICT((l, r), _) => (l, r),
+            ConflictStatistic::ERROR((l, r), _) => (l, r),
+        }
+    }
+    /// Returns the files that could not be merged, empty unless conflicting.
+    pub fn files(&self) -> &[ConflictFile] {
+        match self {
+            ConflictStatistic::CONFLICT(_, files) => files,
+            _ => &[],
+        }
+    }
+}
+
 impl Display for ConflictStatistic {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let formatted = match self {
             ConflictStatistic::OK((l, r)) => {
                 format!("{} and {} ", l, r) + "OK".green().to_string().as_str()
             }
-            ConflictStatistic::CONFLICT((l, r)) => {
+            ConflictStatistic::CONFLICT((l, r), _) => {
                 format!("{} and {} ", l, r) + "CONFLICT".red().to_string().as_str()
             }
             ConflictStatistic::ERROR((l, r), _) => {
@@ -46,6 +228,27 @@ impl Display for ConflictStatistic {
         f.write_str(formatted.as_str())
     }
 }
+/// Serializes to `{"features": [l, r], "result": "ok|conflict|error"}`
+/// with the conflicting files or the error message.
+impl Serialize for ConflictStatistic {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (l, r) = self.pair();
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("features", &[l, r])?;
+        match self {
+            ConflictStatistic::OK(_) => map.serialize_entry("result", "ok")?,
+            ConflictStatistic::CONFLICT(_, files) => {
+                map.serialize_entry("result", "conflict")?;
+                map.serialize_entry("files", files)?;
+            }
+            ConflictStatistic::ERROR(_, err) => {
+                map.serialize_entry("result", "error")?;
+                map.serialize_entry("error", &err.to_string())?;
+            }
+        }
+        map.end()
+    }
+}
 impl Into<String> for ConflictStatistic {
     fn into(self) -> String {
         self.to_string()
@@ -57,10 +260,59 @@ impl Into<String> for &ConflictStatistic {
     }
 }
 
+/// The result of merging more than two features together. Only sets whose
+/// proper subsets all merge cleanly are reported.
+#[derive(Debug)]
+pub enum ConflictSet {
+    CONFLICT(Vec<QualifiedPath>),
+    ERROR(Vec<QualifiedPath>, GitError),
+}
+
+impl ConflictSet {
+    pub fn features(&self) -> &Vec<QualifiedPath> {
+        match self {
+            ConflictSet::CONFLICT(features) => features,
+            ConflictSet::ERROR(features, _) => features,
+        }
+    }
+}
+
+impl Display for ConflictSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let features = self.features();
+        let (last, others) = features.split_last().unwrap();
+        let others = others
+            .iter()
+            .map(|path| path.to_string())
+            .collect::<Vec<String>>();
+        let status = match self {
+            ConflictSet::CONFLICT(_) => "CONFLICT".red(),
+            ConflictSet::ERROR(_, _) => "ERROR".red(),
+        };
+        write!(f, "{} and {} together {}", others.join(", "), last, status)
+    }
+}
+
+impl Serialize for ConflictSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("features", self.features())?;
+        match self {
+            ConflictSet::CONFLICT(_) => map.serialize_entry("result", "conflict")?,
+            ConflictSet::ERROR(_, err) => {
+                map.serialize_entry("result", "error")?;
+                map.serialize_entry("error", &err.to_string())?;
+            }
+        }
+        map.end()
+    }
+}
+
 pub struct ConflictStatistics {
     ok: Vec<ConflictStatistic>,
     conflict: Vec<ConflictStatistic>,
     error: Vec<ConflictStatistic>,
+    sets: Vec<ConflictSet>,
 }
 
 impl ConflictStatistics {
@@ -69,6 +321,7 @@ impl ConflictStatistics {
             ok: vec![],
             conflict: vec![],
             error: vec![],
+            sets: vec![],
         }
     }
     pub fn from_iter<T: Iterator<Item = ConflictStatistic>>(statistics: T) -> Self {
@@ -81,10 +334,14 @@ impl ConflictStatistics {
     pub fn push(&mut self, statistic: ConflictStatistic) {
         match statistic {
             ConflictStatistic::OK(_) => self.ok.push(statistic),
-            ConflictStatistic::CONFLICT(_) => self.conflict.push(statistic),
+            ConflictStatistic::CONFLICT(_, _) => self.conflict.push(statistic),
             ConflictStatistic::ERROR(_, _) => self.error.push(statistic),
         }
     }
+    /// Adds the results of a higher-order check.
+    pub fn extend_sets<T: IntoIterator<Item = ConflictSet>>(&mut self, sets: T) {
+        self.sets.extend(sets);
+    }
     pub fn iter_all(&self) -> impl Iterator<Item = &ConflictStatistic> {
         self.iter_ok()
             .chain(self.iter_conflicts())
@@ -99,6 +356,9 @@ impl ConflictStatistics {
     pub fn iter_errors(&self) -> impl Iterator<Item = &ConflictStatistic> {
         self.error.iter()
     }
+    pub fn iter_sets(&self) -> impl Iterator<Item = &ConflictSet> {
+        self.sets.iter()
+    }
     pub fn n_ok(&self) -> usize {
         self.ok.len()
     }
@@ -108,6 +368,20 @@ impl ConflictStatistics {
     pub fn n_errors(&self) -> usize {
         self.error.len()
     }
+    /// Returns the number of conflicting sets of more than two features.
+    pub fn n_conflict_sets(&self) -> usize {
+        self.sets
+            .iter()
+            .filter(|set| matches!(set, ConflictSet::CONFLICT(_)))
+            .count()
+    }
+    /// Returns whether the pair was checked, in any order and with any result.
+    pub fn contains_pair(&self, l: &QualifiedPath, r: &QualifiedPath) -> bool {
+        self.iter_all().any(|statistic| {
+            let (a, b) = statistic.pair();
+            (a == l && b == r) || (a == r && b == l)
+        })
+    }
     pub fn contains(&self, statistic: &ConflictStatistic) -> bool {
         self.ok.contains(statistic)
             || self.conflict.contains(statistic)
@@ -115,37 +389,145 @@ impl ConflictStatistics {
     }
 }
 
+impl Serialize for ConflictStatistics {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("pairs", &self.iter_all().collect::<Vec<_>>())?;
+        map.serialize_entry("sets", &self.sets)?;
+        map.end()
+    }
+}
+
 impl FromIterator<ConflictStatistic> for ConflictStatistics {
     fn from_iter<T: IntoIterator<Item = ConflictStatistic>>(iter: T) -> Self {
         Self::from_iter(iter.into_iter())
     }
 }
 
+/// Results of earlier checks, keyed by the commits the merged branches
+/// pointed to. A merge of fixed commits always yields the same result, so
+/// entries never become stale. The hunks of a pair are stored for its
+/// commits in sorted order.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ConflictCache {
+    results: BTreeMap<String, MergeResult>,
+}
+
+impl ConflictCache {
+    fn key(commits: &[String]) -> String {
+        let mut commits = commits.to_vec();
+        commits.sort();
+        commits.join(":")
+    }
+    fn is_reversed(commits: &[String]) -> bool {
+        matches!(commits, [l, r] if l > r)
+    }
+    /// Returns the result of merging the commits, if known. The order of
+    /// the commits does not matter, the hunks of a pair are oriented as
+    /// the commits are given.
+    pub fn get(&self, commits: &[String]) -> Option<MergeResult> {
+        let result = self.results.get(&Self::key(commits))?;
+        if Self::is_reversed(commits) {
+            Some(result.swapped())
+        } else {
+            Some(result.clone())
+        }
+    }
+    pub fn insert(&mut self, commits: &[String], result: MergeResult) {
+        let result = if Self::is_reversed(commits) {
+            result.swapped()
+        } else {
+            result
+        };
+        self.results.insert(Self::key(commits), result);
+    }
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+}
+
+impl Document for ConflictCache {
+    const NAME: &'static str = "conflict_cache";
+    const VERSION: u32 = 3;
+
+    /// Version 1 only stored whether a merge was clean, version 2 stored the
+    /// hunks of a pair in the order it was first checked. Conflicts are
+    /// dropped, so they are checked again.
+    fn migrate(version: u32, data: Value) -> Result<Self, PersistencyError> {
+        let results: BTreeMap<String, bool> = if version == 1 {
+            serde_json::from_value(data)?
+        } else {
+            let results: BTreeMap<String, MergeResult> = serde_json::from_value(data)?;
+            results
+                .into_iter()
+                .map(|(key, result)| (key, result.is_clean()))
+                .collect()
+        };
+        Ok(Self {
+            results: results
+                .into_iter()
+                .filter(|(_, clean)| *clean)
+                .map(|(key, _)| (key, MergeResult::Clean))
+                .collect(),
+        })
+    }
+}
+
 pub struct ConflictChecker<'a> {
     interface: &'a GitInterface,
+    jobs: usize,
+    use_cache: bool,
+    store_results: bool,
 }
 
 impl<'a> ConflictChecker<'a> {
     pub fn new(interface: &'a GitInterface) -> Self {
-        Self { interface }
+        let jobs = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        Self {
+            interface,
+            jobs,
+            use_cache: true,
+            store_results: true,
+        }
+    }
+
+    /// Sets the number of checks running in parallel.
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Enables or disables the conflict cache. Without the cache, every pair
+    /// is merged again and no results are stored.
+    pub fn with_cache(mut self, use_cache: bool) -> Self {
+        self.use_cache = use_cache;
+        self
+    }
+
+    /// Enables or disables storing new results in the cache. Cached results
+    /// are still used, e.g. by dry runs that must not change the repository.
+    pub fn with_cache_writes(mut self, store_results: bool) -> Self {
+        self.store_results = store_results;
+        self
     }
 
     pub fn check_all(
         &self,
         paths: &Vec<QualifiedPath>,
     ) -> Result<impl Iterator<Item = ConflictStatistic>, GitError> {
-        let mut feature_combinations: Vec<(&QualifiedPath, &QualifiedPath)> = Vec::new();
+        let mut feature_combinations: Vec<(QualifiedPath, QualifiedPath)> = Vec::new();
         for (i, path) in paths.iter().enumerate() {
             for part in paths[i + 1..].iter() {
-                feature_combinations.push((path, part));
+                feature_combinations.push((path.clone(), part.clone()));
             }
         }
-
-        let iterator = feature_combinations.into_iter().map(|(l, r)| {
-            let statistic = self.check_two(l, r);
-            self.build_statistic(l.clone(), r.clone(), statistic)
-        });
-        Ok(iterator)
+        Ok(self.check_pairs(feature_combinations)?.into_iter())
     }
 
     pub fn check_1_to_n(
@@ -153,46 +535,429 @@ impl<'a> ConflictChecker<'a> {
         source: &QualifiedPath,
         targets: &Vec<QualifiedPath>,
     ) -> Result<impl Iterator<Item = ConflictStatistic>, GitError> {
-        let iterator = targets.into_iter().map(move |target| {
-            let statistic = self.check_two(source, target);
-            self.build_statistic(source.clone(), target.clone(), statistic)
+        let feature_combinations = targets
+            .iter()
+            .map(|target| (source.clone(), target.clone()))
+            .collect();
+        Ok(self.check_pairs(feature_combinations)?.into_iter())
+    }
+
+    fn check_pairs(
+        &self,
+        pairs: Vec<(QualifiedPath, QualifiedPath)>,
+    ) -> Result<Vec<ConflictStatistic>, GitError> {
+        let sets = pairs
+            .iter()
+            .map(|(l, r)| vec![l.clone(), r.clone()])
+            .collect();
+        let results = self.check_sets(sets)?;
+        Ok(pairs
+            .into_iter()
+            .zip(results)
+            .map(|((l, r), result)| self.build_statistic(l, r, result))
+            .collect())
+    }
+
+    /// Merges all features of a selection together. Returns whether the
+    /// merge is free of conflicts.
+    pub fn check_selection(&self, paths: &[QualifiedPath]) -> Result<bool, GitError> {
+        Ok(self
+            .check_sets(vec![paths.to_vec()])?
+            .pop()
+            .unwrap()?
+            .is_clean())
+    }
+
+    /// Returns a minimal subset of the selection that cannot be merged, or
+    /// `None` if the whole selection merges without conflicts. Features are
+    /// removed from the back first, so earlier features are kept if possible.
+    pub fn find_minimal_conflict(
+        &self,
+        paths: &[QualifiedPath],
+    ) -> Result<Option<Vec<QualifiedPath>>, GitError> {
+        if self.check_selection(paths)? {
+            return Ok(None);
+        }
+        let mut conflict = paths.to_vec();
+        let mut index = conflict.len();
+        while index > 0 && conflict.len() > 2 {
+            index -= 1;
+            let mut candidate = conflict.clone();
+            candidate.remove(index);
+            if !self.check_selection(&candidate)? {
+                conflict = candidate;
+            }
+        }
+        Ok(Some(conflict))
+    }
+
+    /// Finds all sets of 3 up to `order` features that conflict when merged
+    /// together, although every proper subset merges cleanly. Only sets
+    /// whose subsets are all conflict-free are checked. Pairs missing from
+    /// `pairwise` are checked first.
+    pub fn check_higher_order(
+        &self,
+        paths: &[QualifiedPath],
+        order: usize,
+        pairwise: &ConflictStatistics,
+    ) -> Result<Vec<ConflictSet>, GitError> {
+        let mut missing = Vec::new();
+        for (i, l) in paths.iter().enumerate() {
+            for r in paths[i + 1..].iter() {
+                if !pairwise.contains_pair(l, r) {
+                    missing.push((l.clone(), r.clone()));
+                }
+            }
+        }
+        let checked = self.check_pairs(missing)?;
+        let ids: BTreeMap<&QualifiedPath, usize> = paths
+            .iter()
+            .enumerate()
+            .map(|(i, path)| (path, i))
+            .collect();
+        let mut conflict_free: BTreeSet<Vec<usize>> = BTreeSet::new();
+        for statistic in pairwise.iter_ok().chain(checked.iter()) {
+            if let ConflictStatistic::OK((l, r)) = statistic
+                && let (Some(l), Some(r)) = (ids.get(l), ids.get(r))
+            {
+                conflict_free.insert(vec![*l.min(r), *l.max(r)]);
+            }
+        }
+
+        let mut conflict_sets = Vec::new();
+        for _ in 3..=order {
+            let mut candidates: Vec<Vec<usize>> = Vec::new();
+            for set in conflict_free.iter() {
+                for next in set[set.len() - 1] + 1..paths.len() {
+                    let mut candidate = set.clone();
+                    candidate.push(next);
+                    let all_subsets_free = (0..candidate.len()).all(|skip| {
+                        let mut subset = candidate.clone();
+                        subset.remove(skip);
+                        conflict_free.contains(&subset)
+                    });
+                    if all_subsets_free {
+                        candidates.push(candidate);
+                    }
+                }
+            }
+            if candidates.is_empty() {
+                break;
+            }
+            let sets: Vec<Vec<QualifiedPath>> = candidates
+                .iter()
+                .map(|ids| ids.iter().map(|id| paths[*id].clone()).collect())
+                .collect();
+            let results = self.check_sets(sets.clone())?;
+            conflict_free = BTreeSet::new();
+            for ((ids, set), result) in candidates.into_iter().zip(sets).zip(results) {
+                match result {
+                    Ok(MergeResult::Clean) => {
+                        conflict_free.insert(ids);
+                    }
+                    Ok(MergeResult::Conflict(_)) => conflict_sets.push(ConflictSet::CONFLICT(set)),
+                    Err(e) => conflict_sets.push(ConflictSet::ERROR(set, e)),
+                }
+            }
+        }
+        Ok(conflict_sets)
+    }
+
+    /// Checks all sets that are not cached yet and stores their results.
+    /// The results are returned in the order of the sets.
+    fn check_sets(
+        &self,
+        sets: Vec<Vec<QualifiedPath>>,
+    ) -> Result<Vec<Result<MergeResult, GitError>>, GitError> {
+        let mut results: Vec<Option<Result<MergeResult, GitError>>> =
+            sets.iter().map(|_| None).collect();
+        let mut commits: Vec<Option<Vec<String>>> = sets.iter().map(|_| None).collect();
+        let mut cache = ConflictCache::default();
+        if self.use_cache {
+            cache = self.interface.get_metadata_store().load()?;
+            let mut tips: BTreeMap<&QualifiedPath, Option<String>> = BTreeMap::new();
+            for (index, set) in sets.iter().enumerate() {
+                for path in set {
+                    if !tips.contains_key(path) {
+                        tips.insert(path, self.interface.get_commit_hash(path).ok());
+                    }
+                }
+                let set_commits: Option<Vec<String>> =
+                    set.iter().map(|path| tips[path].clone()).collect();
+                if let Some(set_commits) = set_commits {
+                    results[index] = cache.get(&set_commits).map(Ok);
+                    commits[index] = Some(set_commits);
+                }
+            }
+        }
+
+        let pending: Vec<usize> = (0..sets.len())
+            .filter(|index| results[*index].is_none())
+            .collect();
+        let checked = self.check_in_parallel(pending.iter().map(|index| &sets[*index]).collect());
+        let mut n_cached = 0;
+        for (index, result) in pending.into_iter().zip(checked) {
+            if let (Ok(merge_result), Some(set_commits)) = (&result, &commits[index]) {
+                cache.insert(set_commits, merge_result.clone());
+                n_cached += 1;
+            }
+            results[index] = Some(result);
+        }
+        if n_cached > 0 && self.store_results {
+            self.interface.get_metadata_store().store(&cache)?;
+        }
+        Ok(results.into_iter().map(|result| result.unwrap()).collect())
+    }
+
+    /// Merges sets on a pool of worker threads. The results are returned in
+    /// the order of the sets, independent of the number of jobs.
+    fn check_in_parallel(
+        &self,
+        sets: Vec<&Vec<QualifiedPath>>,
+    ) -> Vec<Result<MergeResult, GitError>> {
+        let git = self.interface.get_raw_interface();
+        let progress = ProgressIndicator::new("Checking for conflicts", sets.len());
+        let next = AtomicUsize::new(0);
+        let results: Vec<Mutex<Option<Result<MergeResult, GitError>>>> =
+            sets.iter().map(|_| Mutex::new(None)).collect();
+        thread::scope(|scope| {
+            for _ in 0..self.jobs.min(sets.len()) {
+                scope.spawn(|| {
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(set) = sets.get(index) else {
+                            break;
+                        };
+                        let result = git.merge_trees(set);
+                        *results[index].lock().unwrap() = Some(result);
+                        progress.advance();
+                    }
+                });
+            }
         });
-        Ok(iterator)
-    }
-
-    fn check_two(&self, l: &QualifiedPath, r: &QualifiedPath) -> Result<bool, GitError> {
-        let current_path = self.interface.get_current_qualified_path()?;
-        let current_area = self.interface.get_current_area()?;
-        self.interface
-            .checkout(&current_area.get_qualified_path())?;
-        let temporary = QualifiedPath::from("tmp");
-        self.interface.create_branch_no_mut(&temporary)?;
-        self.interface.checkout_raw(&temporary)?;
-        let success = self
-            .interface
-            .merge(&vec![l.clone(), r.clone()])?
-            .status
-            .success();
-        if !success {
-            self.interface.abort_merge()?;
-        }
-        self.interface.checkout(&current_path)?;
-        self.interface.delete_branch(&temporary)?;
-        Ok(success)
+        progress.finish();
+        results
+            .into_iter()
+            .map(|result| result.into_inner().unwrap().unwrap())
+            .collect()
     }
 
     fn build_statistic(
         &self,
         l: QualifiedPath,
         r: QualifiedPath,
-        result: Result<bool, GitError>,
+        result: Result<MergeResult, GitError>,
     ) -> ConflictStatistic {
         match result {
             Ok(stat) => match stat {
-                true => ConflictStatistic::OK((l, r)),
-                false => ConflictStatistic::CONFLICT((l, r)),
+                MergeResult::Clean => ConflictStatistic::OK((l, r)),
+                MergeResult::Conflict(files) => ConflictStatistic::CONFLICT((l, r), files),
             },
             Err(e) => ConflictStatistic::ERROR((l, r), e),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::interface::test_utils::{
+        commit_file_on_branch, populate_with_features, prepare_empty_git_repo,
+    };
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_check_does_not_touch_repository() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/foo", "file1", "foo")
+            .unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/bar", "file1", "bar")
+            .unwrap();
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/baz", "file2", "baz")
+            .unwrap();
+        std::fs::write(path_buf.join("untracked"), "").unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        let head = interface.get_head_hash().unwrap();
+        let paths = vec![
+            QualifiedPath::from("/main/feature/root/foo"),
+            QualifiedPath::from("/main/feature/root/bar"),
+            QualifiedPath::from("/main/feature/root/baz"),
+        ];
+        let statistics: ConflictStatistics = ConflictChecker::new(&interface)
+            .check_all(&paths)
+            .unwrap()
+            .collect();
+        assert_eq!(statistics.n_ok(), 2);
+        assert_eq!(statistics.n_errors(), 0);
+        assert!(statistics.contains(&ConflictStatistic::CONFLICT(
+            (
+                QualifiedPath::from("/main/feature/root/foo"),
+                QualifiedPath::from("/main/feature/root/bar"),
+            ),
+            vec![]
+        )));
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        assert_eq!(interface.get_head_hash().unwrap(), head);
+        assert_eq!(
+            interface.get_current_qualified_path().unwrap(),
+            QualifiedPath::from("/main")
+        );
+        assert_eq!(
+            interface
+                .get_model()
+                .get_qualified_paths_with_branches()
+                .len(),
+            5
+        );
+        assert!(path_buf.join("untracked").exists());
+        assert!(!interface.is_merge_in_progress().unwrap());
+    }
+
+    #[test]
+    fn test_check_uses_cache() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        let foo = QualifiedPath::from("/main/feature/root/foo");
+        let bar = QualifiedPath::from("/main/feature/root/bar");
+        let ok = ConflictStatistic::OK((foo.clone(), bar.clone()));
+        let check = |use_cache: bool| -> ConflictStatistics {
+            ConflictChecker::new(&interface)
+                .with_cache(use_cache)
+                .check_1_to_n(&foo, &vec![bar.clone()])
+                .unwrap()
+                .collect()
+        };
+        assert!(check(true).contains(&ok));
+        let store = interface.get_metadata_store();
+        let mut cache: ConflictCache = store.load().unwrap();
+        assert_eq!(cache.len(), 1);
+
+        let foo_commit = interface.get_commit_hash(&foo).unwrap();
+        let bar_commit = interface.get_commit_hash(&bar).unwrap();
+        assert_eq!(
+            cache.get(&[bar_commit.clone(), foo_commit.clone()]),
+            Some(MergeResult::Clean)
+        );
+        cache.insert(&[foo_commit, bar_commit], MergeResult::Conflict(vec![]));
+        store.store(&cache).unwrap();
+        assert!(check(true).contains(&ConflictStatistic::CONFLICT(
+            (foo.clone(), bar.clone()),
+            vec![]
+        )));
+        assert!(check(false).contains(&ok));
+
+        commit_file_on_branch(path_buf.clone(), "_main/_feature/_root/foo", "file", "foo").unwrap();
+        assert!(check(true).contains(&ok));
+        assert_eq!(store.load::<ConflictCache>().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_check_higher_order() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        for feature in ["foo", "bar", "baz"] {
+            let branch = format!("_main/_feature/_root/{}", feature);
+            commit_file_on_branch(path_buf.clone(), &branch, feature, feature).unwrap();
+        }
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        let paths: Vec<QualifiedPath> = ["root", "root/foo", "root/bar", "root/baz"]
+            .into_iter()
+            .map(|p| QualifiedPath::from("/main/feature") + QualifiedPath::from(p))
+            .collect();
+        // git rarely produces conflicts that no pair shows, so fake one
+        let commits: Vec<String> = paths
+            .iter()
+            .map(|p| interface.get_commit_hash(p).unwrap())
+            .collect();
+        let store = interface.get_metadata_store();
+        store
+            .update(|cache: &mut ConflictCache| {
+                cache.insert(&commits[1..], MergeResult::Conflict(vec![]));
+                cache.insert(&commits, MergeResult::Conflict(vec![]));
+            })
+            .unwrap();
+
+        let checker = ConflictChecker::new(&interface);
+        let pairwise: ConflictStatistics = checker.check_all(&paths).unwrap().collect();
+        assert_eq!(pairwise.n_ok(), 6);
+        let sets = checker.check_higher_order(&paths, 4, &pairwise).unwrap();
+        assert_eq!(sets.len(), 1);
+        assert!(matches!(&sets[0], ConflictSet::CONFLICT(features) if features == &paths[1..]));
+        assert_eq!(
+            checker.find_minimal_conflict(&paths).unwrap(),
+            Some(paths[1..].to_vec())
+        );
+        assert_eq!(checker.find_minimal_conflict(&paths[..3]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_conflict_file() {
+        let merged = "1\n<<<<<<< a\nA\nA\n||||||| base\n2\n=======\nB\n>>>>>>> b\n3\n\
+            <<<<<<< a\n=======\nB\n>>>>>>> b\n4\n";
+        let file = ConflictFile::parse("file", merged);
+        assert_eq!(file.path(), "file");
+        let ranges: Vec<(usize, usize, usize, usize)> = file
+            .hunks()
+            .iter()
+            .map(|h| {
+                (
+                    h.left().start(),
+                    h.left().count(),
+                    h.right().start(),
+                    h.right().count(),
+                )
+            })
+            .collect();
+        assert_eq!(ranges, vec![(2, 2, 2, 1), (5, 0, 4, 1)]);
+        assert_eq!(file.hunks()[0].left().to_string(), "lines 2-3");
+        assert_eq!(file.hunks()[1].left().to_string(), "none after line 4");
+        assert!(ConflictFile::parse("file", "1\n2\n").hunks().is_empty());
+    }
+
+    #[test]
+    fn test_cache_keeps_orientation() {
+        let file = ConflictFile::parse("file", "<<<<<<< a\nA\nA\n=======\nB\n>>>>>>> b\n");
+        let (a, b) = ("a".to_string(), "b".to_string());
+        let mut cache = ConflictCache::default();
+        cache.insert(
+            &[b.clone(), a.clone()],
+            MergeResult::Conflict(vec![file.clone()]),
+        );
+        assert_eq!(
+            cache.get(&[b.clone(), a.clone()]),
+            Some(MergeResult::Conflict(vec![file.clone()]))
+        );
+        assert_eq!(
+            cache.get(&[a, b]),
+            Some(MergeResult::Conflict(vec![file.swapped()]))
+        );
+        assert_eq!(file.swapped().hunks()[0].left().count(), 1);
+    }
+
+    #[test]
+    fn test_migrate_conflict_cache() {
+        let data = serde_json::json!({"a:b": true, "a:c": false});
+        let cache = ConflictCache::migrate(1, data).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(
+            cache.get(&["b".to_string(), "a".to_string()]),
+            Some(MergeResult::Clean)
+        );
+        let data = serde_json::json!({"a:b": "Clean", "a:c": {"Conflict": []}});
+        let cache = ConflictCache::migrate(2, data).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(
+            cache.get(&["a".to_string(), "b".to_string()]),
+            Some(MergeResult::Clean)
+        );
+    }
+}
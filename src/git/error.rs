@@ -1,3 +1,4 @@
+use crate::git::persistency::PersistencyError;
 use crate::model::WrongNodeTypeError;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
@@ -26,6 +27,11 @@ pub enum GitError {
     Io(io::Error),
     GitInterface(GitInterfaceError),
     WrongNodeType(WrongNodeTypeError),
+    Persistency(PersistencyError),
+    /// HEAD points to a commit instead of a branch.
+    DetachedHead,
+    /// The branch does not follow the naming scheme of tangl, e.g. `dev/x`.
+    UnmanagedBranch(String),
 }
 impl Display for GitError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -33,6 +39,13 @@ impl Display for GitError {
             GitError::Io(err) => err.fmt(f),
             GitError::GitInterface(err) => err.fmt(f),
             GitError::WrongNodeType(err) => err.fmt(f),
+            GitError::Persistency(err) => err.fmt(f),
+            GitError::DetachedHead => write!(f, "HEAD is detached, check out a branch first"),
+            GitError::UnmanagedBranch(branch) => write!(
+                f,
+                "Branch '{}' is not managed by tangl, check out an area, feature or product branch",
+                branch
+            ),
         }
     }
 }
@@ -52,3 +65,8 @@ impl From<WrongNodeTypeError> for GitError {
         GitError::WrongNodeType(value)
     }
 }
+impl From<PersistencyError> for GitError {
+    fn from(value: PersistencyError) -> Self {
+        GitError::Persistency(value)
+    }
+}
@@ -1,6 +1,11 @@
+use crate::git::conflict::{ConflictFile, MergeResult};
 use crate::git::error::{GitError, GitInterfaceError};
+use crate::git::persistency::{Document, MetadataStore, PersistencyError};
 use crate::model::*;
 use crate::util::u8_to_string;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::io;
 use std::path::PathBuf;
 use std::process::{Command, Output};
@@ -16,9 +21,6 @@ pub(super) struct GitCLI {
     path: GitPath,
 }
 impl GitCLI {
-    pub fn in_current_directory() -> Self {
-        Self::new(GitPath::CurrentDirectory)
-    }
     pub fn in_custom_directory(path: PathBuf) -> Self {
         Self::new(GitPath::CustomDirectory(path))
     }
@@ -39,6 +41,153 @@ impl GitCLI {
         transformed.extend(args);
         base.args(transformed).output()
     }
+    /// Merges two branches without touching the working tree, the index or
+    /// any ref. Returns whether the merge is free of conflicts.
+    pub fn merge_tree(&self, l: &QualifiedPath, r: &QualifiedPath) -> Result<bool, GitError> {
+        Ok(self.merge_trees(&[l.clone(), r.clone()])?.is_clean())
+    }
+    /// Merges any number of branches one after another like [Self::merge_tree].
+    /// Intermediate results are written as unreferenced commits, which git
+    /// eventually garbage collects. On a conflict, the conflicting files of
+    /// the failed step are returned.
+    pub fn merge_trees(&self, paths: &[QualifiedPath]) -> Result<MergeResult, GitError> {
+        let mut merged = match paths.first() {
+            Some(path) => path.to_git_branch(),
+            None => return Ok(MergeResult::Clean),
+        };
+        for (i, path) in paths.iter().enumerate().skip(1) {
+            let branch = path.to_git_branch();
+            let tree = match self.write_merge_tree(&merged, &branch)? {
+                Ok(tree) => tree,
+                Err(conflict) => return Ok(conflict),
+            };
+            if i + 1 == paths.len() {
+                break;
+            }
+            merged = self.commit_tree(&tree, &[merged.as_str(), branch.as_str()])?;
+        }
+        Ok(MergeResult::Clean)
+    }
+    /// Predicts whether cherry-picking a commit onto a branch conflicts,
+    /// without touching the working tree, the index or any ref. Merging
+    /// requires the parent of the commit as merge base, so both sides are
+    /// recreated as unreferenced commits on top of a commit with its tree.
+    pub fn cherry_pick_tree(
+        &self,
+        commit: &str,
+        onto: &QualifiedPath,
+    ) -> Result<MergeResult, GitError> {
+        let base = self.commit_tree(&format!("{}^^{{tree}}", commit), &[])?;
+        let theirs = self.commit_tree(&format!("{}^{{tree}}", commit), &[base.as_str()])?;
+        let ours = self.commit_tree(
+            &format!("{}^{{tree}}", onto.to_git_branch()),
+            &[base.as_str()],
+        )?;
+        Ok(match self.write_merge_tree(&ours, &theirs)? {
+            Ok(_) => MergeResult::Clean,
+            Err(conflict) => conflict,
+        })
+    }
+    /// Merges two revisions into a tree. Returns the tree, or the
+    /// conflicting files if the merge is not clean.
+    fn write_merge_tree(&self, l: &str, r: &str) -> Result<Result<String, MergeResult>, GitError> {
+        let output = self.run(vec![
+            "-c",
+            "merge.conflictStyle=merge",
+            "merge-tree",
+            "--write-tree",
+            "--name-only",
+            "--no-messages",
+            "-z",
+            l,
+            r,
+        ])?;
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let mut fields = stdout.split('\0');
+        let tree = fields.next().unwrap_or_default();
+        // invalid revisions also exit with 1, but write no tree
+        let has_tree = !tree.is_empty() && tree.chars().all(|c| c.is_ascii_hexdigit());
+        if !has_tree || !output.stderr.is_empty() {
+            return Err(GitError::GitInterface(GitInterfaceError::new(
+                format!(
+                    "Cannot merge {} and {}: {}",
+                    l,
+                    r,
+                    u8_to_string(&output.stderr).trim()
+                )
+                .as_str(),
+            )));
+        }
+        match output.status.code() {
+            Some(0) => Ok(Ok(tree.to_string())),
+            Some(1) => {
+                let mut files: Vec<&str> = fields.filter(|f| !f.is_empty()).collect();
+                files.dedup();
+                let files = files
+                    .into_iter()
+                    .map(|file| self.read_conflict_file(tree, file))
+                    .collect::<Result<Vec<ConflictFile>, GitError>>()?;
+                Ok(Err(MergeResult::Conflict(files)))
+            }
+            _ => Err(GitError::GitInterface(GitInterfaceError::new(
+                u8_to_string(&output.stderr).trim(),
+            ))),
+        }
+    }
+    /// Writes an unreferenced commit, which git eventually garbage collects.
+    fn commit_tree(&self, tree: &str, parents: &[&str]) -> Result<String, GitError> {
+        let mut args = vec![
+            "-c",
+            "user.name=tangl",
+            "-c",
+            "user.email=tangl@localhost",
+            "commit-tree",
+            tree,
+        ];
+        for parent in parents {
+            args.extend(["-p", parent]);
+        }
+        args.extend(["-m", "tangl trial merge"]);
+        let output = self.run(args)?;
+        if !output.status.success() {
+            return Err(GitError::GitInterface(GitInterfaceError::new(
+                u8_to_string(&output.stderr).trim(),
+            )));
+        }
+        Ok(u8_to_string(&output.stdout).trim().to_string())
+    }
+    fn read_conflict_file(&self, tree: &str, file: &str) -> Result<ConflictFile, GitError> {
+        let object = format!("{}:{}", tree, file);
+        let output = self.run(vec!["cat-file", "-p", object.as_str()])?;
+        // files deleted on one side are missing from the merged tree
+        let content = match output.status.success() {
+            true => String::from_utf8_lossy(&output.stdout).to_string(),
+            false => String::new(),
+        };
+        Ok(ConflictFile::parse(file, &content))
+    }
+}
+
+/// Variability of all features that differ from the default.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(transparent)]
+struct VariabilityDocument(BTreeMap<QualifiedPath, Variability>);
+
+impl Document for VariabilityDocument {
+    const NAME: &'static str = "variability";
+    const VERSION: u32 = 1;
+
+    fn migrate(_version: u32, data: Value) -> Result<Self, PersistencyError> {
+        Ok(serde_json::from_value(data)?)
+    }
+}
+
+/// A file with changes in the index or working tree. `status` holds the two
+/// status letters of `git status --porcelain`, e.g. `M ` or `??`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FileStatus {
+    pub status: String,
+    pub path: String,
 }
 
 #[derive(Clone, Debug)]
@@ -47,74 +196,141 @@ pub struct GitInterface {
     raw_git_interface: GitCLI,
 }
 impl GitInterface {
-    pub fn default() -> Self {
+    pub fn default() -> Result<Self, GitError> {
         Self::new(GitPath::CurrentDirectory)
     }
-    pub fn in_directory(path: PathBuf) -> Self {
+    pub fn in_directory(path: PathBuf) -> Result<Self, GitError> {
         Self::new(GitPath::CustomDirectory(path))
     }
-    pub fn new(path: GitPath) -> Self {
+    /// Reads the model from the branches and tags of the repository. Outside
+    /// of a repository the model is empty.
+    pub fn new(path: GitPath) -> Result<Self, GitError> {
         let raw_interface = GitCLI::new(path);
         let mut interface = Self {
             model: TreeDataModel::new(),
             raw_git_interface: raw_interface,
         };
-        match interface.update_complete_model() {
-            Ok(_) => interface,
-            Err(e) => panic!("{:?}", e),
-        }
+        interface.update_complete_model()?;
+        Ok(interface)
     }
     fn update_complete_model(&mut self) -> Result<(), GitError> {
-        let branch_output = self.raw_git_interface.run(vec!["branch"])?;
-        let all_branches: Vec<String> = u8_to_string(&branch_output.stdout)
-            .split("\n")
-            .map(|raw_string| raw_string.to_string())
-            .collect();
-        for branch in all_branches {
-            if !branch.is_empty() {
-                let mut path = QualifiedPath::from("");
-                path.push(branch);
-                self.model.insert_qualified_path(path, false)?;
+        let output = self.raw_git_interface.run(vec![
+            "for-each-ref",
+            "--format=%(refname)%00%(objectname)%00%(*objectname)",
+            "refs/heads",
+            "refs/tags",
+        ])?;
+        // outside of a repository the model stays empty
+        if !output.status.success() {
+            return Ok(());
+        }
+        for line in u8_to_string(&output.stdout).lines() {
+            let fields: Vec<&str> = line.split('\0').collect();
+            let [refname, object, peeled] = fields[..] else {
+                continue;
+            };
+            // annotated tags point to a tag object that points to the commit
+            let commit = if peeled.is_empty() { object } else { peeled };
+            let (name, is_tag) = match refname.strip_prefix("refs/heads/") {
+                Some(branch) => (branch, false),
+                None => match refname.strip_prefix("refs/tags/") {
+                    Some(tag) => (tag, true),
+                    None => continue,
+                },
+            };
+            if let Some(path) = QualifiedPath::from_git_branch(name) {
+                self.insert_ref(path, is_tag, Some(commit.to_string()))?;
             }
         }
-        let tag_output = self.raw_git_interface.run(vec!["tag"])?;
-        let all_tags: Vec<String> = u8_to_string(&tag_output.stdout)
-            .split("\n")
-            .map(|raw_string| raw_string.to_string())
-            .collect();
-        for tag in all_tags {
-            if !tag.is_empty() {
-                let mut path = QualifiedPath::from("");
-                path.push(tag);
-                self.model.insert_qualified_path(path, true)?;
+        self.load_variability()
+    }
+    /// Adds a branch or tag to the model. Refs that do not follow the naming
+    /// scheme of tangl are not part of the model.
+    fn insert_ref(
+        &mut self,
+        path: QualifiedPath,
+        is_tag: bool,
+        commit: Option<String>,
+    ) -> Result<(), GitError> {
+        // inserting fails halfway, so invalid refs are detected on a scratch model
+        if TreeDataModel::new()
+            .insert_qualified_path(path.clone(), is_tag)
+            .is_err()
+        {
+            return Ok(());
+        }
+        Ok(self.model.insert_ref(path, is_tag, commit)?)
+    }
+    fn load_variability(&mut self) -> Result<(), GitError> {
+        // outside of a repository there is nothing to load
+        let stored = match self.get_metadata_store().load::<VariabilityDocument>() {
+            Ok(stored) => stored,
+            Err(PersistencyError::Git(_)) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        for (path, variability) in stored.0 {
+            if self.model.get_node_path(&path).is_some() {
+                self.model.set_variability(&path, variability)?;
             }
         }
         Ok(())
     }
+    /// Applies and persists variability information of features.
+    pub fn update_variability(
+        &mut self,
+        changes: Vec<(QualifiedPath, Variability)>,
+    ) -> Result<(), GitError> {
+        for (path, variability) in changes {
+            self.model.set_variability(&path, variability)?;
+        }
+        self.get_metadata_store()
+            .store(&VariabilityDocument(self.model.collect_variability()))?;
+        Ok(())
+    }
+    pub(super) fn get_raw_interface(&self) -> &GitCLI {
+        &self.raw_git_interface
+    }
     pub fn get_model(&self) -> &TreeDataModel {
         &self.model
     }
+    pub fn get_metadata_store(&self) -> MetadataStore {
+        MetadataStore::new(self.raw_git_interface.clone())
+    }
     fn get_current_branch(&self) -> Result<String, GitError> {
-        Ok(u8_to_string(
-            &self
-                .raw_git_interface
-                .run(vec!["branch", "--show-current"])?
-                .stdout,
-        ))
+        let output = self
+            .raw_git_interface
+            .run(vec!["branch", "--show-current"])?;
+        if !output.status.success() {
+            return Err(GitError::GitInterface(GitInterfaceError::new(
+                u8_to_string(&output.stderr).trim(),
+            )));
+        }
+        let branch = u8_to_string(&output.stdout).trim().to_string();
+        if branch.is_empty() {
+            return Err(GitError::DetachedHead);
+        }
+        Ok(branch)
     }
     pub fn get_current_qualified_path(&self) -> Result<QualifiedPath, GitError> {
-        let mut base = QualifiedPath::from("");
-        base.push(self.get_current_branch()?);
-        Ok(base)
+        let branch = self.get_current_branch()?;
+        QualifiedPath::from_git_branch(&branch).ok_or(GitError::UnmanagedBranch(branch))
     }
     pub fn get_current_node_path(&self) -> Result<NodePath<AnyNodeType>, GitError> {
         let current_qualified_path = self.get_current_qualified_path()?;
-        Ok(self.model.get_node_path(&current_qualified_path).unwrap())
+        match self.model.get_node_path(&current_qualified_path) {
+            Some(path) => Ok(path),
+            None => Err(GitError::UnmanagedBranch(self.get_current_branch()?)),
+        }
     }
     pub fn get_current_area(&self) -> Result<NodePath<Area>, GitError> {
         let current_qualified_path = self.get_current_qualified_path()?;
-        let qualified_path = QualifiedPath::from(&current_qualified_path[1]);
-        Ok(self.model.get_area(&qualified_path).unwrap())
+        match current_qualified_path
+            .get(1)
+            .and_then(|area| self.model.get_area(&area))
+        {
+            Some(area) => Ok(area),
+            None => Err(GitError::UnmanagedBranch(self.get_current_branch()?)),
+        }
     }
 
     // all git commands
@@ -126,18 +342,56 @@ impl GitInterface {
     pub fn status(&self) -> Result<Output, GitError> {
         Ok(self.raw_git_interface.run(vec!["status"])?)
     }
+    pub fn get_file_status(&self) -> Result<Vec<FileStatus>, GitError> {
+        let output = self
+            .raw_git_interface
+            .run(vec!["status", "--porcelain", "-z"])?;
+        if !output.status.success() {
+            return Err(GitError::GitInterface(GitInterfaceError::new(
+                u8_to_string(&output.stderr).trim(),
+            )));
+        }
+        let stdout = u8_to_string(&output.stdout);
+        let mut entries = stdout.split('\0').filter(|entry| !entry.is_empty());
+        let mut files = vec![];
+        while let Some(entry) = entries.next() {
+            let (status, path) = entry.split_at(2);
+            // renames and copies are followed by the original path
+            if status.contains(['R', 'C']) {
+                entries.next();
+            }
+            files.push(FileStatus {
+                status: status.to_string(),
+                path: path[1..].to_string(),
+            });
+        }
+        Ok(files)
+    }
     pub(super) fn checkout_raw(&self, path: &QualifiedPath) -> Result<Output, GitError> {
         Ok(self
             .raw_git_interface
             .run(vec!["checkout", path.to_git_branch().as_str()])?)
     }
+    /// Checks out a branch. Fails if git refuses, e.g. because untracked
+    /// files would be overwritten, so callers never work on the wrong branch.
     pub fn checkout(&self, path: &QualifiedPath) -> Result<Output, GitError> {
         if !self.model.has_branch(&path) {
             return Err(GitError::GitInterface(GitInterfaceError::new(
                 format!("Cannot checkout branch {}: does not exist", path).as_str(),
             )));
         }
-        self.checkout_raw(&path)
+        let output = self.checkout_raw(&path)?;
+        if !output.status.success() {
+            return Err(GitError::GitInterface(GitInterfaceError::new(
+                format!(
+                    "Cannot checkout branch {}: {}",
+                    path,
+                    u8_to_string(&output.stderr).trim()
+                )
+                .as_str(),
+            )));
+        }
+        Ok(output)
     }
     pub(super) fn create_branch_no_mut(&self, path: &QualifiedPath) -> Result<Output, GitError> {
         let branch = path.to_git_branch();
@@ -146,8 +400,27 @@ impl GitInterface {
     }
     pub fn create_branch(&mut self, path: &QualifiedPath) -> Result<Output, GitError> {
         let output = self.create_branch_no_mut(path)?;
+        self.register_branch(path, output)
+    }
+    pub fn create_branch_from(
+        &mut self,
+        path: &QualifiedPath,
+        start: &QualifiedPath,
+    ) -> Result<Output, GitError> {
+        let branch = path.to_git_branch();
+        let start_branch = start.to_git_branch();
+        let commands = vec!["branch", branch.as_str(), start_branch.as_str()];
+        let output = self.raw_git_interface.run(commands)?;
+        self.register_branch(path, output)
+    }
+    fn register_branch(
+        &mut self,
+        path: &QualifiedPath,
+        output: Output,
+    ) -> Result<Output, GitError> {
         if output.status.success() {
-            self.model.insert_qualified_path(path.clone(), false)?;
+            let commit = self.get_commit_hash(path).ok();
+            self.model.insert_ref(path.clone(), false, commit)?;
             Ok(output)
         } else {
             Err(GitError::GitInterface(GitInterfaceError::new(
@@ -167,9 +440,70 @@ impl GitInterface {
         base.extend(converted_paths);
         Ok(self.raw_git_interface.run(base)?)
     }
+    /// Merges two branches without touching the working tree, the index or
+    /// any ref. Returns whether the merge is free of conflicts.
+    pub fn merge_tree(&self, l: &QualifiedPath, r: &QualifiedPath) -> Result<bool, GitError> {
+        self.raw_git_interface.merge_tree(l, r)
+    }
+    /// Predicts whether cherry-picking a commit onto a branch conflicts
+    /// without touching the working tree, the index or any ref.
+    pub fn cherry_pick_tree(
+        &self,
+        commit: &str,
+        onto: &QualifiedPath,
+    ) -> Result<MergeResult, GitError> {
+        self.raw_git_interface.cherry_pick_tree(commit, onto)
+    }
     pub fn abort_merge(&self) -> Result<Output, GitError> {
         Ok(self.raw_git_interface.run(vec!["merge", "--abort"])?)
     }
+    pub fn is_merge_in_progress(&self) -> Result<bool, GitError> {
+        Ok(self
+            .raw_git_interface
+            .run(vec!["rev-parse", "--verify", "--quiet", "MERGE_HEAD"])?
+            .status
+            .success())
+    }
+    /// Concludes a merge whose conflicts were resolved.
+    pub fn commit_merge(&self) -> Result<Output, GitError> {
+        Ok(self.raw_git_interface.run(vec!["commit", "--no-edit"])?)
+    }
+    /// Returns all files with unresolved conflicts.
+    pub fn get_unmerged_files(&self) -> Result<Vec<String>, GitError> {
+        let output = self
+            .raw_git_interface
+            .run(vec!["diff", "--name-only", "--diff-filter=U"])?;
+        Ok(u8_to_string(&output.stdout)
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| line.to_string())
+            .collect())
+    }
+    /// Returns whether the branch `ancestor` is contained in `descendant`.
+    pub fn is_ancestor(
+        &self,
+        ancestor: &QualifiedPath,
+        descendant: &QualifiedPath,
+    ) -> Result<bool, GitError> {
+        let output = self.raw_git_interface.run(vec![
+            "merge-base",
+            "--is-ancestor",
+            ancestor.to_git_branch().as_str(),
+            descendant.to_git_branch().as_str(),
+        ])?;
+        match output.status.code() {
+            Some(0) => Ok(true),
+            Some(1) => Ok(false),
+            _ => Err(GitError::GitInterface(GitInterfaceError::new(
+                u8_to_string(&output.stderr).trim(),
+            ))),
+        }
+    }
+    pub fn reset_hard(&self, commit: &str) -> Result<Output, GitError> {
+        Ok(self
+            .raw_git_interface
+            .run(vec!["reset", "--hard", commit])?)
+    }
     pub fn create_tag(&self, tag: &QualifiedPath) -> Result<Output, GitError> {
         let current_branch = self.get_current_qualified_path()?;
         let tagged = current_branch + tag.clone();
@@ -185,33 +519,55 @@ impl GitInterface {
             .run(vec!["tag", "-d", tagged.to_git_branch().as_str()])?)
     }
     pub fn get_commit_history(&self, branch: &QualifiedPath) -> Result<Vec<Commit>, GitError> {
-        let raw_hashes = u8_to_string(
-            &self
-                .raw_git_interface
-                .run(vec!["log", "--format=%H", branch.to_git_branch().as_str()])?
-                .stdout,
-        )
-        .trim()
-        .to_string();
-        let all_hashes = raw_hashes.split("\n").collect::<Vec<&str>>();
-        let commits: Vec<Commit> = all_hashes
-            .into_iter()
-            .map(|hash| {
-                let trimmed = hash.trim();
-                let commit_message = u8_to_string(
-                    &self
-                        .raw_git_interface
-                        .run(vec!["log", "--format=%B", "-n 1", trimmed])
-                        .unwrap()
-                        .stdout,
-                )
-                .trim()
-                .to_string();
-                Commit::new(trimmed, commit_message)
-            })
-            .collect();
+        let output = self.raw_git_interface.run(vec![
+            "log",
+            "--format=%H",
+            branch.to_git_branch().as_str(),
+        ])?;
+        if !output.status.success() {
+            return Err(GitError::GitInterface(GitInterfaceError::new(
+                format!("Cannot read the history of {}", branch).as_str(),
+            )));
+        }
+        let mut commits = vec![];
+        for hash in u8_to_string(&output.stdout).split_whitespace() {
+            let commit_message = u8_to_string(
+                &self
+                    .raw_git_interface
+                    .run(vec!["log", "--format=%B", "-n 1", hash])?
+                    .stdout,
+            )
+            .trim()
+            .to_string();
+            commits.push(Commit::new(hash, commit_message));
+        }
         Ok(commits)
     }
+    /// Returns the hashes of the commits on the branch that are not reachable
+    /// from any of the excluded commits, ignoring merge commits.
+    pub fn get_commits_excluding(
+        &self,
+        branch: &QualifiedPath,
+        excluded: &[String],
+    ) -> Result<Vec<String>, GitError> {
+        let branch = branch.to_git_branch();
+        let excluded: Vec<String> = excluded
+            .iter()
+            .map(|commit| format!("^{}", commit))
+            .collect();
+        let mut args = vec!["rev-list", "--no-merges", branch.as_str()];
+        args.extend(excluded.iter().map(|commit| commit.as_str()));
+        let output = self.raw_git_interface.run(args)?;
+        if !output.status.success() {
+            return Err(GitError::GitInterface(GitInterfaceError::new(
+                format!("Cannot read the history of {}", branch).as_str(),
+            )));
+        }
+        Ok(u8_to_string(&output.stdout)
+            .split_whitespace()
+            .map(|hash| hash.to_string())
+            .collect())
+    }
     pub fn get_files_managed_by_branch(
         &self,
         branch: &QualifiedPath,
@@ -240,6 +596,24 @@ impl GitInterface {
             .map(|e| e.to_string())
             .collect())
     }
+    /// Returns the hash of the commit a branch points to.
+    pub fn get_commit_hash(&self, branch: &QualifiedPath) -> Result<String, GitError> {
+        self.rev_parse(format!("{}^{{commit}}", branch.to_git_branch()).as_str())
+    }
+    pub fn get_head_hash(&self) -> Result<String, GitError> {
+        self.rev_parse("HEAD")
+    }
+    fn rev_parse(&self, revision: &str) -> Result<String, GitError> {
+        let output =
+            self.raw_git_interface
+                .run(vec!["rev-parse", "--verify", "--quiet", revision])?;
+        if !output.status.success() {
+            return Err(GitError::GitInterface(GitInterfaceError::new(
+                format!("Cannot resolve revision {}", revision).as_str(),
+            )));
+        }
+        Ok(u8_to_string(&output.stdout).trim().to_string())
+    }
     pub fn commit(&self, message: &str) -> Result<Output, GitError> {
         Ok(self.raw_git_interface.run(vec!["commit", "-m", message])?)
     }
@@ -251,12 +625,32 @@ impl GitInterface {
     pub fn cherry_pick(&self, commit: &str) -> Result<Output, GitError> {
         Ok(self.raw_git_interface.run(vec!["cherry-pick", commit])?)
     }
+    pub fn abort_cherry_pick(&self) -> Result<Output, GitError> {
+        Ok(self.raw_git_interface.run(vec!["cherry-pick", "--abort"])?)
+    }
+    pub fn is_cherry_pick_in_progress(&self) -> Result<bool, GitError> {
+        Ok(self
+            .raw_git_interface
+            .run(vec!["rev-parse", "--verify", "--quiet", "CHERRY_PICK_HEAD"])?
+            .status
+            .success())
+    }
+    /// Stashes the changes of tracked files. Untracked files stay in place.
+    pub fn stash_push(&self, message: &str) -> Result<Output, GitError> {
+        Ok(self
+            .raw_git_interface
+            .run(vec!["stash", "push", "-m", message])?)
+    }
+    pub fn stash_pop(&self) -> Result<Output, GitError> {
+        Ok(self.raw_git_interface.run(vec!["stash", "pop"])?)
+    }
 }
 
 #[cfg(test)]
 pub mod test_utils {
     use crate::git::error::GitError;
     use crate::git::interface::GitCLI;
+    use crate::util::u8_to_string;
     use std::fs;
     use std::path::PathBuf;
 
@@ -285,6 +679,29 @@ pub mod test_utils {
         Ok(())
     }
 
+    /// Commits a file with the given content on a branch, then returns to the
+    /// previously checked out branch.
+    pub fn commit_file_on_branch(
+        path: PathBuf,
+        branch: &str,
+        file_name: &str,
+        content: &str,
+    ) -> Result<(), GitError> {
+        let git = GitCLI::in_custom_directory(path.clone());
+        let current = u8_to_string(&git.run(vec!["branch", "--show-current"])?.stdout);
+        git.run(vec!["checkout", branch])?;
+        let file = path.join(file_name);
+        fs::write(file.clone(), content)?;
+        git.run(vec!["add", file.to_str().unwrap()])?;
+        git.run(vec![
+            "commit",
+            "-m",
+            format!("Change {}", file_name).as_str(),
+        ])?;
+        git.run(vec!["checkout", current.trim()])?;
+        Ok(())
+    }
+
     pub fn populate_with_products(path: PathBuf) -> Result<(), GitError> {
         let git = GitCLI::in_custom_directory(PathBuf::from(path));
         let branches = vec!["_main/_product/myprod"];
@@ -307,7 +724,7 @@ mod tests {
         let path_buf = PathBuf::from(path.path());
         prepare_empty_git_repo(path_buf.clone()).unwrap();
         populate_with_features(path_buf.clone()).unwrap();
-        let interface = GitInterface::new(GitPath::CustomDirectory(path_buf));
+        let interface = GitInterface::new(GitPath::CustomDirectory(path_buf)).unwrap();
         let paths = interface.get_model().get_qualified_paths_with_branches();
         assert_eq!(
             paths,
@@ -327,8 +744,87 @@ mod tests {
         let path_buf = PathBuf::from(path.path());
         prepare_empty_git_repo(path_buf.clone()).unwrap();
         populate_with_features(path_buf.clone()).unwrap();
-        let interface = GitInterface::new(GitPath::CustomDirectory(path_buf));
+        let interface = GitInterface::new(GitPath::CustomDirectory(path_buf)).unwrap();
         let current = interface.get_current_qualified_path().unwrap();
         assert_eq!(current, "/main")
     }
+
+    #[test]
+    fn interface_unmanaged_branches() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let git = GitCLI::in_custom_directory(path_buf.clone());
+        git.run(vec!["checkout", "-b", "dev/x"]).unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        assert_eq!(
+            interface
+                .get_model()
+                .get_qualified_paths_with_branches()
+                .len(),
+            5
+        );
+        assert!(matches!(
+            interface.get_current_node_path(),
+            Err(GitError::UnmanagedBranch(branch)) if branch == "dev/x"
+        ));
+        assert!(matches!(
+            interface.get_current_area(),
+            Err(GitError::UnmanagedBranch(_))
+        ));
+
+        git.run(vec!["checkout", "--detach", "main"]).unwrap();
+        let interface = GitInterface::in_directory(path_buf.clone()).unwrap();
+        assert!(matches!(
+            interface.get_current_node_path(),
+            Err(GitError::DetachedHead)
+        ));
+        assert!(
+            interface
+                .get_commit_history(&QualifiedPath::from("/main"))
+                .is_ok()
+        );
+        assert!(
+            interface
+                .get_commit_history(&QualifiedPath::from("/main/feature/missing"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn interface_merge_trees_of_missing_branch() {
+        let path = TempDir::new().unwrap();
+        let path_buf = PathBuf::from(path.path());
+        prepare_empty_git_repo(path_buf.clone()).unwrap();
+        populate_with_features(path_buf.clone()).unwrap();
+        let interface = GitInterface::in_directory(path_buf).unwrap();
+        let git = interface.get_raw_interface();
+        let root = QualifiedPath::from("/main/feature/root");
+        assert!(
+            git.merge_trees(&[root.clone(), QualifiedPath::from("/main/feature/root/foo")])
+                .unwrap()
+                .is_clean()
+        );
+        assert!(
+            git.merge_trees(&[root, QualifiedPath::from("/main/feature/missing")])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn interface_outside_of_repository() {
+        let path = TempDir::new().unwrap();
+        let interface = GitInterface::in_directory(PathBuf::from(path.path())).unwrap();
+        assert!(
+            interface
+                .get_model()
+                .get_qualified_paths_with_branches()
+                .is_empty()
+        );
+        assert!(matches!(
+            interface.get_current_node_path(),
+            Err(GitError::GitInterface(_))
+        ));
+    }
 }
@@ -0,0 +1,337 @@
+use crate::git::conflict::{ConflictStatistic, ConflictStatistics};
+use crate::model::QualifiedPath;
+use colored::{ColoredString, Colorize};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MatrixFormat {
+    Terminal,
+    CSV,
+    HTML,
+}
+
+impl<S: Into<String>> From<S> for MatrixFormat {
+    fn from(value: S) -> Self {
+        let real = value.into();
+        match real.to_uppercase().as_str() {
+            "TERMINAL" => MatrixFormat::Terminal,
+            "CSV" => MatrixFormat::CSV,
+            "HTML" => MatrixFormat::HTML,
+            _ => unreachable!("Matrix does not support format '{}'", real),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MatrixCell {
+    Same,
+    Unchecked,
+    OK,
+    Conflict,
+    Error,
+}
+
+impl MatrixCell {
+    fn label(&self) -> &'static str {
+        match self {
+            MatrixCell::Same | MatrixCell::Unchecked => "",
+            MatrixCell::OK => "OK",
+            MatrixCell::Conflict => "CONFLICT",
+            MatrixCell::Error => "ERROR",
+        }
+    }
+}
+
+/// A row of the matrix. Rows follow the feature tree, so features below
+/// the same parent are next to each other.
+pub struct MatrixRow {
+    feature: QualifiedPath,
+    name: String,
+    depth: usize,
+}
+
+/// The results of a pairwise check of all features as a symmetric table.
+pub struct ConflictMatrix {
+    rows: Vec<MatrixRow>,
+    cells: BTreeMap<(usize, usize), MatrixCell>,
+}
+
+impl ConflictMatrix {
+    /// Builds the matrix of all features appearing in the statistics. Names
+    /// are relative to the feature root.
+    pub fn new(feature_root: &QualifiedPath, statistics: &ConflictStatistics) -> Self {
+        let mut features: Vec<QualifiedPath> = statistics
+            .iter_all()
+            .flat_map(|statistic| {
+                let (l, r) = statistic.pair();
+                [l.clone(), r.clone()]
+            })
+            .collect();
+        features.sort();
+        features.dedup();
+        let rows: Vec<MatrixRow> = features
+            .into_iter()
+            .map(|feature| MatrixRow {
+                name: feature.last().cloned().unwrap_or_default(),
+                depth: feature.len().saturating_sub(feature_root.len() + 1),
+                feature,
+            })
+            .collect();
+        let ids: BTreeMap<&QualifiedPath, usize> = rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| (&row.feature, i))
+            .collect();
+        let mut cells = BTreeMap::new();
+        for statistic in statistics.iter_all() {
+            let (l, r) = statistic.pair();
+            let cell = match statistic {
+                ConflictStatistic::OK(_) => MatrixCell::OK,
+                ConflictStatistic::CONFLICT(_, _) => MatrixCell::Conflict,
+                ConflictStatistic::ERROR(_, _) => MatrixCell::Error,
+            };
+            cells.insert((ids[l], ids[r]), cell);
+            cells.insert((ids[r], ids[l]), cell);
+        }
+        Self { rows, cells }
+    }
+    pub fn rows(&self) -> &Vec<MatrixRow> {
+        &self.rows
+    }
+    pub fn get(&self, row: usize, column: usize) -> MatrixCell {
+        if row == column {
+            return MatrixCell::Same;
+        }
+        *self
+            .cells
+            .get(&(row, column))
+            .unwrap_or(&MatrixCell::Unchecked)
+    }
+    pub fn render(&self, format: MatrixFormat) -> String {
+        let writer: Box<dyn MatrixWriter> = match format {
+            MatrixFormat::Terminal => Box::new(TerminalMatrixWriter),
+            MatrixFormat::CSV => Box::new(CSVMatrixWriter),
+            MatrixFormat::HTML => Box::new(HTMLMatrixWriter),
+        };
+        writer.write(self)
+    }
+}
+
+pub trait MatrixWriter {
+    fn write(&self, matrix: &ConflictMatrix) -> String;
+}
+
+const TERMINAL_INDENT: &str = "  ";
+const TERMINAL_CELL_WIDTH: usize = 8;
+
+/// Writes a table with numbered columns; the numbers refer to the rows,
+/// which are indented like the feature tree.
+pub struct TerminalMatrixWriter;
+
+impl TerminalMatrixWriter {
+    fn color(cell: MatrixCell, text: String) -> ColoredString {
+        match cell {
+            MatrixCell::Same => "-".repeat(TERMINAL_CELL_WIDTH).dimmed(),
+            MatrixCell::Unchecked => text.normal(),
+            MatrixCell::OK => text.green(),
+            MatrixCell::Conflict => text.red(),
+            MatrixCell::Error => text.yellow(),
+        }
+    }
+}
+
+impl MatrixWriter for TerminalMatrixWriter {
+    fn write(&self, matrix: &ConflictMatrix) -> String {
+        let number_width = matrix.rows().len().to_string().len();
+        let labels: Vec<String> = matrix
+            .rows()
+            .iter()
+            .map(|row| TERMINAL_INDENT.repeat(row.depth) + row.name.as_str())
+            .collect();
+        let label_width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+
+        let mut out = " ".repeat(number_width + label_width + 1);
+        for column in 1..=matrix.rows().len() {
+            out.push_str(&format!(" {:>width$}", column, width = TERMINAL_CELL_WIDTH));
+        }
+        out.push('\n');
+        for (row, label) in labels.iter().enumerate() {
+            out.push_str(&format!(
+                "{:>number_width$} {:<label_width$}",
+                row + 1,
+                label,
+                number_width = number_width,
+                label_width = label_width
+            ));
+            for column in 0..matrix.rows().len() {
+                let cell = matrix.get(row, column);
+                let text = format!("{:>width$}", cell.label(), width = TERMINAL_CELL_WIDTH);
+                out.push_str(&format!(" {}", Self::color(cell, text)));
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Writes one line per feature, headed by the feature paths relative to the
+/// feature root. Cells on the diagonal and of unchecked pairs are empty.
+pub struct CSVMatrixWriter;
+
+impl CSVMatrixWriter {
+    fn quote(value: &str) -> String {
+        if value.contains([',', '"', '\n']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+}
+
+impl MatrixWriter for CSVMatrixWriter {
+    fn write(&self, matrix: &ConflictMatrix) -> String {
+        let names: Vec<String> = matrix
+            .rows()
+            .iter()
+            .map(|row| {
+                let relative = row.feature.strip_n_left(row.feature.len() - row.depth - 1);
+                Self::quote(&relative.to_string())
+            })
+            .collect();
+        let mut out = format!("feature,{}\n", names.join(","));
+        for (row, name) in names.iter().enumerate() {
+            let cells: Vec<&str> = (0..names.len())
+                .map(|column| matrix.get(row, column).label())
+                .collect();
+            out.push_str(&format!("{},{}\n", name, cells.join(",")));
+        }
+        out
+    }
+}
+
+const HTML_STYLE: &str = "table { border-collapse: collapse; font-family: sans-serif; }
+th, td { border: 1px solid #ccc; padding: 2px 6px; }
+th.feature { text-align: left; }
+td.ok { background: #d4edda; }
+td.conflict { background: #f8d7da; }
+td.error { background: #fff3cd; }
+td.same { background: #eee; }";
+
+/// Writes a standalone HTML page with a table colored by result.
+pub struct HTMLMatrixWriter;
+
+impl HTMLMatrixWriter {
+    fn escape(value: &str) -> String {
+        value
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+    fn class(cell: MatrixCell) -> &'static str {
+        match cell {
+            MatrixCell::Same => "same",
+            MatrixCell::Unchecked => "unchecked",
+            MatrixCell::OK => "ok",
+            MatrixCell::Conflict => "conflict",
+            MatrixCell::Error => "error",
+        }
+    }
+}
+
+impl MatrixWriter for HTMLMatrixWriter {
+    fn write(&self, matrix: &ConflictMatrix) -> String {
+        let mut out = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        out.push_str("<title>Conflict matrix</title>\n");
+        out.push_str(&format!("<style>\n{}\n</style>\n", HTML_STYLE));
+        out.push_str("</head>\n<body>\n<table>\n<tr><th></th>");
+        for row in matrix.rows() {
+            out.push_str(&format!(
+                "<th title=\"{}\">{}</th>",
+                Self::escape(&row.feature.to_string()),
+                Self::escape(&row.name)
+            ));
+        }
+        out.push_str("</tr>\n");
+        for (index, row) in matrix.rows().iter().enumerate() {
+            out.push_str(&format!(
+                "<tr><th class=\"feature\" style=\"padding-left: {}em\" title=\"{}\">{}</th>",
+                row.depth + 1,
+                Self::escape(&row.feature.to_string()),
+                Self::escape(&row.name)
+            ));
+            for column in 0..matrix.rows().len() {
+                let cell = matrix.get(index, column);
+                out.push_str(&format!(
+                    "<td class=\"{}\">{}</td>",
+                    Self::class(cell),
+                    cell.label()
+                ));
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</table>\n</body>\n</html>\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::error::{GitError, GitInterfaceError};
+
+    fn path(p: &str) -> QualifiedPath {
+        QualifiedPath::from("/main/feature") + QualifiedPath::from(p)
+    }
+
+    fn matrix() -> ConflictMatrix {
+        let statistics = ConflictStatistics::from_iter(
+            vec![
+                ConflictStatistic::OK((path("root"), path("root/foo"))),
+                ConflictStatistic::CONFLICT((path("root/foo"), path("root/bar")), vec![]),
+                ConflictStatistic::ERROR(
+                    (path("root"), path("root/bar")),
+                    GitError::GitInterface(GitInterfaceError::new("broken")),
+                ),
+            ]
+            .into_iter(),
+        );
+        ConflictMatrix::new(&QualifiedPath::from("/main/feature"), &statistics)
+    }
+
+    #[test]
+    fn test_matrix_follows_feature_tree() {
+        let matrix = matrix();
+        let names: Vec<(&str, usize)> = matrix
+            .rows()
+            .iter()
+            .map(|row| (row.name.as_str(), row.depth))
+            .collect();
+        assert_eq!(names, vec![("root", 0), ("bar", 1), ("foo", 1)]);
+        assert_eq!(matrix.get(0, 0), MatrixCell::Same);
+        assert_eq!(matrix.get(0, 2), MatrixCell::OK);
+        assert_eq!(matrix.get(2, 0), MatrixCell::OK);
+        assert_eq!(matrix.get(1, 2), MatrixCell::Conflict);
+        assert_eq!(matrix.get(0, 1), MatrixCell::Error);
+    }
+
+    #[test]
+    fn test_matrix_csv() {
+        assert_eq!(
+            matrix().render(MatrixFormat::CSV),
+            "feature,root,root/bar,root/foo\n\
+            root,,ERROR,OK\n\
+            root/bar,ERROR,,CONFLICT\n\
+            root/foo,OK,CONFLICT,\n"
+        );
+    }
+
+    #[test]
+    fn test_matrix_html() {
+        let html = matrix().render(MatrixFormat::HTML);
+        assert!(html.contains("<td class=\"conflict\">CONFLICT</td>"));
+        assert!(
+            html.contains("style=\"padding-left: 2em\" title=\"/main/feature/root/foo\">foo</th>")
+        );
+    }
+}
@@ -1,4 +1,5 @@
 pub mod conflict;
-mod error;
+pub mod error;
 pub mod interface;
+pub mod matrix;
 pub mod persistency;
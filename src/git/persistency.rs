@@ -1,45 +1,315 @@
 use crate::git::interface::GitCLI;
 use crate::util::u8_to_string;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::error::Error;
-use std::fs::{read_to_string, write};
+use std::fmt::{Display, Formatter};
+use std::fs::{create_dir_all, read_to_string, remove_file, rename, write};
+use std::io;
+use std::path::PathBuf;
 
-trait PersistencyHandler<E> {
+const TANGL_DIR: &str = "tangl";
+const TMP_SUFFIX: &str = ".tmp";
+
+#[derive(Debug)]
+pub enum PersistencyError {
+    Io(io::Error),
+    Git(String),
+    Json(serde_json::Error),
+    UnsupportedVersion {
+        document: String,
+        found: u32,
+        supported: u32,
+    },
+}
+impl Display for PersistencyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PersistencyError::Io(err) => err.fmt(f),
+            PersistencyError::Git(msg) => write!(f, "Cannot locate the git directory: {}", msg),
+            PersistencyError::Json(err) => write!(f, "Malformed tangl metadata: {}", err),
+            PersistencyError::UnsupportedVersion {
+                document,
+                found,
+                supported,
+            } => write!(
+                f,
+                "Metadata '{}' has version {}, but only versions up to {} are supported",
+                document, found, supported
+            ),
+        }
+    }
+}
+impl Error for PersistencyError {}
+impl From<io::Error> for PersistencyError {
+    fn from(err: io::Error) -> Self {
+        PersistencyError::Io(err)
+    }
+}
+impl From<serde_json::Error> for PersistencyError {
+    fn from(err: serde_json::Error) -> Self {
+        PersistencyError::Json(err)
+    }
+}
+
+pub trait PersistencyHandler<E> {
+    fn exists(&self) -> Result<bool, E>;
     fn read_file(&self) -> Result<String, E>;
     fn write_file(&self, data: &str) -> Result<(), E>;
+    fn remove_file(&self) -> Result<(), E>;
 }
 
+/// Stores a file below `.git/tangl/` of the repository. Linked worktrees
+/// share the directory of the main repository.
 pub struct GitDirPersistencyHandler {
-    file_path: String,
+    file_name: String,
     raw_git_interface: GitCLI,
 }
 
 impl GitDirPersistencyHandler {
-    pub fn new(file_name: &str) -> Self {
-        let path = String::from("tangl/") + file_name;
+    pub(super) fn new(file_name: &str, raw_git_interface: GitCLI) -> Self {
         Self {
-            file_path: path,
-            raw_git_interface: GitCLI::in_current_directory(),
+            file_name: file_name.to_string(),
+            raw_git_interface,
         }
     }
-    fn get_file_path(&self) -> String {
-        let maybe_output = self
-            .raw_git_interface
-            .run(vec!["rev-parse", "--show-toplevel"]);
-        match maybe_output {
-            Ok(output) => u8_to_string(&output.stdout),
-            Err(error) => {
-                panic!("{}", error)
+    fn get_file_path(&self) -> Result<PathBuf, PersistencyError> {
+        let mut path = get_tangl_dir(&self.raw_git_interface)?;
+        path.push(&self.file_name);
+        Ok(path)
+    }
+}
+
+fn get_tangl_dir(raw_git_interface: &GitCLI) -> Result<PathBuf, PersistencyError> {
+    let output = raw_git_interface.run(vec![
+        "rev-parse",
+        "--path-format=absolute",
+        "--git-common-dir",
+    ])?;
+    if !output.status.success() {
+        return Err(PersistencyError::Git(
+            u8_to_string(&output.stderr).trim().to_string(),
+        ));
+    }
+    let mut path = PathBuf::from(u8_to_string(&output.stdout).trim());
+    path.push(TANGL_DIR);
+    Ok(path)
+}
+
+impl PersistencyHandler<PersistencyError> for GitDirPersistencyHandler {
+    fn exists(&self) -> Result<bool, PersistencyError> {
+        Ok(self.get_file_path()?.exists())
+    }
+
+    fn read_file(&self) -> Result<String, PersistencyError> {
+        Ok(read_to_string(self.get_file_path()?)?)
+    }
+
+    /// Writes to a temporary file first and renames it afterwards, so readers
+    /// never observe a partially written file.
+    fn write_file(&self, data: &str) -> Result<(), PersistencyError> {
+        let path = self.get_file_path()?;
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(TMP_SUFFIX);
+        write(&tmp_path, data)?;
+        Ok(rename(tmp_path, path)?)
+    }
+
+    fn remove_file(&self) -> Result<(), PersistencyError> {
+        let path = self.get_file_path()?;
+        if path.exists() {
+            remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// A JSON document of the metadata store.
+///
+/// Documents are stored with their schema version. When a document with an
+/// older version is read, [Document::migrate] converts it to the current one.
+pub trait Document: Serialize + DeserializeOwned + Default {
+    /// The file name of the document, without extension.
+    const NAME: &'static str;
+    /// The current schema version, starting at 1.
+    const VERSION: u32;
+
+    /// Converts a document of an older version. Version 0 denotes documents
+    /// written without a version.
+    fn migrate(version: u32, data: Value) -> Result<Self, PersistencyError> {
+        let _ = data;
+        Err(PersistencyError::UnsupportedVersion {
+            document: Self::NAME.to_string(),
+            found: version,
+            supported: Self::VERSION,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct VersionedDocument<T> {
+    version: u32,
+    data: T,
+}
+
+/// Typed access to the JSON documents tangl keeps below `.git/tangl/`.
+pub struct MetadataStore {
+    raw_git_interface: GitCLI,
+}
+
+impl MetadataStore {
+    pub(super) fn new(raw_git_interface: GitCLI) -> Self {
+        Self { raw_git_interface }
+    }
+    fn get_handler<D: Document>(&self) -> GitDirPersistencyHandler {
+        GitDirPersistencyHandler::new(
+            format!("{}.json", D::NAME).as_str(),
+            self.raw_git_interface.clone(),
+        )
+    }
+    /// Returns the directory all documents are stored in.
+    pub fn get_directory(&self) -> Result<PathBuf, PersistencyError> {
+        get_tangl_dir(&self.raw_git_interface)
+    }
+    pub fn exists<D: Document>(&self) -> Result<bool, PersistencyError> {
+        self.get_handler::<D>().exists()
+    }
+    /// Reads a document, returns the default document if it does not exist.
+    pub fn load<D: Document>(&self) -> Result<D, PersistencyError> {
+        let handler = self.get_handler::<D>();
+        if !handler.exists()? {
+            return Ok(D::default());
+        }
+        let value: Value = serde_json::from_str(&handler.read_file()?)?;
+        let (version, data) = match value {
+            Value::Object(mut object) if object.len() == 2 && object.contains_key("data") => {
+                match object.get("version").and_then(Value::as_u64) {
+                    Some(version) => (version as u32, object.remove("data").unwrap()),
+                    None => (0, Value::Object(object)),
+                }
             }
+            other => (0, other),
+        };
+        if version == D::VERSION {
+            Ok(serde_json::from_value(data)?)
+        } else if version > D::VERSION {
+            Err(PersistencyError::UnsupportedVersion {
+                document: D::NAME.to_string(),
+                found: version,
+                supported: D::VERSION,
+            })
+        } else {
+            D::migrate(version, data)
         }
     }
+    pub fn store<D: Document>(&self, document: &D) -> Result<(), PersistencyError> {
+        let versioned = VersionedDocument {
+            version: D::VERSION,
+            data: document,
+        };
+        self.get_handler::<D>()
+            .write_file(&serde_json::to_string_pretty(&versioned)?)
+    }
+    /// Reads a document, applies `update` to it and stores the result.
+    pub fn update<D: Document, R>(
+        &self,
+        update: impl FnOnce(&mut D) -> R,
+    ) -> Result<R, PersistencyError> {
+        let mut document = self.load::<D>()?;
+        let result = update(&mut document);
+        self.store(&document)?;
+        Ok(result)
+    }
+    pub fn remove<D: Document>(&self) -> Result<(), PersistencyError> {
+        self.get_handler::<D>().remove_file()
+    }
 }
 
-impl PersistencyHandler<Box<dyn Error>> for GitDirPersistencyHandler {
-    fn read_file(&self) -> Result<String, Box<dyn Error>> {
-        Ok(read_to_string(&self.get_file_path())?)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::interface::test_utils::prepare_empty_git_repo;
+    use std::collections::BTreeMap;
+    use tempfile::TempDir;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Settings {
+        values: BTreeMap<String, String>,
+    }
+
+    impl Document for Settings {
+        const NAME: &'static str = "settings";
+        const VERSION: u32 = 2;
+
+        fn migrate(version: u32, data: Value) -> Result<Self, PersistencyError> {
+            match version {
+                1 | 0 => Ok(Settings {
+                    values: serde_json::from_value(data)?,
+                }),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn prepare_store() -> (TempDir, MetadataStore) {
+        let path = TempDir::new().unwrap();
+        prepare_empty_git_repo(PathBuf::from(path.path())).unwrap();
+        let store = MetadataStore::new(GitCLI::in_custom_directory(PathBuf::from(path.path())));
+        (path, store)
+    }
+
+    #[test]
+    fn test_store_and_load() {
+        let (path, store) = prepare_store();
+        assert!(!store.exists::<Settings>().unwrap());
+        assert_eq!(store.load::<Settings>().unwrap(), Settings::default());
+        store
+            .update(|settings: &mut Settings| {
+                settings
+                    .values
+                    .insert("key".to_string(), "value".to_string())
+            })
+            .unwrap();
+        assert!(store.exists::<Settings>().unwrap());
+        assert_eq!(
+            store.load::<Settings>().unwrap().values.get("key").unwrap(),
+            "value"
+        );
+        let file = path.path().join(".git/tangl/settings.json");
+        let raw: Value = serde_json::from_str(&read_to_string(&file).unwrap()).unwrap();
+        assert_eq!(raw["version"], 2);
+        assert!(!path.path().join(".git/tangl/settings.json.tmp").exists());
+        store.remove::<Settings>().unwrap();
+        assert!(!file.exists());
     }
 
-    fn write_file(&self, data: &str) -> Result<(), Box<dyn Error>> {
-        Ok(write(self.get_file_path(), data)?)
+    #[test]
+    fn test_migration() {
+        let (_path, store) = prepare_store();
+        let dir = store.get_directory().unwrap();
+        create_dir_all(&dir).unwrap();
+        write(dir.join("settings.json"), r#"{"key": "old"}"#).unwrap();
+        assert_eq!(
+            store.load::<Settings>().unwrap().values.get("key").unwrap(),
+            "old"
+        );
+        write(
+            dir.join("settings.json"),
+            r#"{"version": 1, "data": {"key": "v1"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            store.load::<Settings>().unwrap().values.get("key").unwrap(),
+            "v1"
+        );
+        write(dir.join("settings.json"), r#"{"version": 3, "data": {}}"#).unwrap();
+        assert!(matches!(
+            store.load::<Settings>(),
+            Err(PersistencyError::UnsupportedVersion { found: 3, .. })
+        ));
     }
 }
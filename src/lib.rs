@@ -1,4 +1,5 @@
 pub mod cli;
+pub mod error;
 pub mod git;
 pub mod logging;
 pub mod model;
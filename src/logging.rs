@@ -1,4 +1,13 @@
 use log::{Log, Metadata, Record, max_level};
+use std::io::{IsTerminal, Write, stderr};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+static LOG_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Sends all logs to stderr, used when stdout is reserved for a document.
+pub fn log_to_stderr(enabled: bool) {
+    LOG_TO_STDERR.store(enabled, Ordering::Relaxed);
+}
 
 pub struct PrintingLogger;
 impl Log for PrintingLogger {
@@ -8,9 +17,48 @@ impl Log for PrintingLogger {
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
-            println!("{}", record.args());
+            if LOG_TO_STDERR.load(Ordering::Relaxed) {
+                eprintln!("{}", record.args());
+            } else {
+                println!("{}", record.args());
+            }
         }
     }
 
     fn flush(&self) {}
 }
+
+/// Shows the progress of a long running operation on stderr. Nothing is
+/// printed if stderr is not a terminal, so logs and pipes stay clean.
+pub struct ProgressIndicator {
+    label: String,
+    total: usize,
+    done: AtomicUsize,
+    enabled: bool,
+}
+
+impl ProgressIndicator {
+    pub fn new<S: Into<String>>(label: S, total: usize) -> Self {
+        Self {
+            label: label.into(),
+            total,
+            done: AtomicUsize::new(0),
+            enabled: total > 1 && stderr().is_terminal(),
+        }
+    }
+    /// Marks one step as done. Can be called from several threads.
+    pub fn advance(&self) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.enabled {
+            let mut err = stderr().lock();
+            let _ = write!(err, "\r{} {}/{}", self.label, done, self.total);
+            let _ = err.flush();
+        }
+    }
+    pub fn finish(&self) {
+        if self.enabled {
+            let width = self.label.len() + 2 * self.total.to_string().len() + 2;
+            let _ = write!(stderr().lock(), "\r{}\r", " ".repeat(width));
+        }
+    }
+}
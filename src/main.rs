@@ -1,4 +1,5 @@
 use log::{LevelFilter, error, set_logger, set_max_level};
+use std::process::exit;
 use tangl::cli::{ArgSource, CommandRepository, TangleCommand};
 use tangl::git::interface::GitPath;
 use tangl::logging::PrintingLogger;
@@ -11,7 +12,8 @@ fn main() {
     match command_repository.execute(ArgSource::CLI) {
         Ok(_) => {}
         Err(error) => {
-            error!("{}", error)
+            error!("{}", error);
+            exit(error.exit_code());
         }
     }
 }
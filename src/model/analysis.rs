@@ -0,0 +1,431 @@
+use crate::model::*;
+use std::collections::HashMap;
+
+/// The part of the feature model a clause of a [FeatureModelFormula] was
+/// derived from. Used to explain invalid configurations.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reason {
+    Parent {
+        child: QualifiedPath,
+        parent: QualifiedPath,
+    },
+    Mandatory {
+        parent: QualifiedPath,
+        child: QualifiedPath,
+    },
+    Group {
+        parent: QualifiedPath,
+        kind: GroupKind,
+    },
+    Constraint(Constraint),
+}
+
+impl Reason {
+    pub fn display_relative_to(&self, base: &QualifiedPath) -> String {
+        let relative = |path: &QualifiedPath| path.strip_n_left(base.len()).to_string();
+        match self {
+            Reason::Parent { child, parent } => {
+                format!(
+                    "{} requires its parent {}",
+                    relative(child),
+                    relative(parent)
+                )
+            }
+            Reason::Mandatory { child, .. } => format!("{} is mandatory", relative(child)),
+            Reason::Group { parent, kind } => {
+                let expected = match kind {
+                    GroupKind::Alternative => "exactly one child",
+                    _ => "at least one child",
+                };
+                format!(
+                    "{} has an {} group and requires {} to be selected",
+                    relative(parent),
+                    kind,
+                    expected
+                )
+            }
+            Reason::Constraint(constraint) => {
+                format!("constraint {}", constraint.display_relative_to(base))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Validation {
+    /// The selection is valid, contains all features of a valid configuration.
+    Valid(Vec<QualifiedPath>),
+    /// The selection is invalid, contains a minimal set of reasons.
+    Invalid(Vec<Reason>),
+}
+
+/// The propositional formula of the features below a feature root, their
+/// groups and the cross-tree constraints between them.
+///
+/// Every feature node is a variable. Features without a branch are treated as
+/// abstract: they are never fixed by a selection.
+pub struct FeatureModelFormula {
+    features: Vec<QualifiedPath>,
+    has_branch: Vec<bool>,
+    variability: Vec<Variability>,
+    parents: Vec<Option<usize>>,
+    ids: HashMap<QualifiedPath, usize>,
+    formula: Formula,
+    reasons: Vec<Reason>,
+}
+
+impl FeatureModelFormula {
+    pub fn new(feature_root: &NodePath<FeatureRoot>, constraints: &Constraints) -> Self {
+        let mut nodes: Vec<NodePath<AnyNodeType>> = feature_root
+            .iter_children_req()
+            .filter(|node| matches!(node.get_type(), NodeType::Feature))
+            .collect();
+        nodes.sort_by_key(|node| node.get_qualified_path());
+        let features: Vec<QualifiedPath> =
+            nodes.iter().map(|node| node.get_qualified_path()).collect();
+        let ids: HashMap<QualifiedPath, usize> = features
+            .iter()
+            .enumerate()
+            .map(|(id, path)| (path.clone(), id))
+            .collect();
+        let parents = features
+            .iter()
+            .map(|path| ids.get(&path.strip_n_right(path.len() - 1)).copied())
+            .collect();
+        let mut result = Self {
+            has_branch: nodes
+                .iter()
+                .map(|n| n.get_metadata().has_branch())
+                .collect(),
+            variability: nodes
+                .iter()
+                .map(|n| n.get_metadata().variability().clone())
+                .collect(),
+            parents,
+            features,
+            ids,
+            formula: Formula::new(nodes.len()),
+            reasons: vec![],
+        };
+        result.add_tree_clauses(&feature_root.get_qualified_path());
+        result.add_constraint_clauses(constraints);
+        result
+    }
+
+    fn add_clause(&mut self, clause: Clause, reason: Reason) {
+        self.formula.add_clause(clause);
+        self.reasons.push(reason);
+    }
+
+    fn children_of(&self, parent: Option<usize>) -> Vec<usize> {
+        (0..self.features.len())
+            .filter(|id| self.parents[*id] == parent)
+            .collect()
+    }
+
+    fn add_tree_clauses(&mut self, root_path: &QualifiedPath) {
+        for id in 0..self.features.len() {
+            let child = self.features[id].clone();
+            match self.parents[id] {
+                Some(parent_id) => {
+                    let parent = self.features[parent_id].clone();
+                    self.add_clause(
+                        vec![Literal::negative(id), Literal::positive(parent_id)],
+                        Reason::Parent {
+                            child: child.clone(),
+                            parent: parent.clone(),
+                        },
+                    );
+                    if self.variability[id].is_mandatory()
+                        && self.variability[parent_id].group() == &GroupKind::And
+                    {
+                        self.add_clause(
+                            vec![Literal::negative(parent_id), Literal::positive(id)],
+                            Reason::Mandatory { parent, child },
+                        );
+                    }
+                }
+                None => {
+                    if self.variability[id].is_mandatory() {
+                        self.add_clause(
+                            vec![Literal::positive(id)],
+                            Reason::Mandatory {
+                                parent: root_path.clone(),
+                                child,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+        for id in 0..self.features.len() {
+            let kind = self.variability[id].group().clone();
+            let children = self.children_of(Some(id));
+            if kind == GroupKind::And || children.is_empty() {
+                continue;
+            }
+            let reason = Reason::Group {
+                parent: self.features[id].clone(),
+                kind: kind.clone(),
+            };
+            let mut at_least_one = vec![Literal::negative(id)];
+            at_least_one.extend(children.iter().map(|c| Literal::positive(*c)));
+            self.add_clause(at_least_one, reason.clone());
+            if kind == GroupKind::Alternative {
+                for (i, left) in children.iter().enumerate() {
+                    for right in children[i + 1..].iter() {
+                        self.add_clause(
+                            vec![Literal::negative(*left), Literal::negative(*right)],
+                            reason.clone(),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn add_constraint_clauses(&mut self, constraints: &Constraints) {
+        for constraint in constraints.iter() {
+            let (source, target) = match (
+                self.ids.get(constraint.source()),
+                self.ids.get(constraint.target()),
+            ) {
+                (Some(source), Some(target)) => (*source, *target),
+                _ => continue,
+            };
+            let target = match constraint.kind() {
+                ConstraintKind::Requires => Literal::positive(target),
+                ConstraintKind::Excludes => Literal::negative(target),
+            };
+            self.add_clause(
+                vec![Literal::negative(source), target],
+                Reason::Constraint(constraint.clone()),
+            );
+        }
+    }
+
+    pub fn features(&self) -> &Vec<QualifiedPath> {
+        &self.features
+    }
+    pub fn contains(&self, feature: &QualifiedPath) -> bool {
+        self.ids.contains_key(feature)
+    }
+
+    fn to_paths(&self, assignment: Vec<bool>) -> Vec<QualifiedPath> {
+        assignment
+            .into_iter()
+            .enumerate()
+            .filter(|(_, selected)| *selected)
+            .map(|(id, _)| self.features[id].clone())
+            .collect()
+    }
+
+    /// Validates a selection of absolute feature paths. Unless `partial` is
+    /// set, all features with a branch that are not selected are deselected.
+    /// Features unknown to the model are ignored.
+    pub fn validate(&self, selection: &[QualifiedPath], partial: bool) -> Validation {
+        let selected: Vec<usize> = selection
+            .iter()
+            .filter_map(|path| self.ids.get(path).copied())
+            .collect();
+        let mut assumptions: Vec<Literal> =
+            selected.iter().map(|id| Literal::positive(*id)).collect();
+        if !partial {
+            assumptions.extend(
+                (0..self.features.len())
+                    .filter(|id| self.has_branch[*id] && !selected.contains(id))
+                    .map(Literal::negative),
+            );
+        }
+        match self.formula.solve(&assumptions) {
+            Some(assignment) => Validation::Valid(self.to_paths(assignment)),
+            None => {
+                let core = self.formula.explain(&assumptions).unwrap_or_default();
+                let mut reasons: Vec<Reason> = Vec::new();
+                for clause in core {
+                    if !reasons.contains(&self.reasons[clause]) {
+                        reasons.push(self.reasons[clause].clone());
+                    }
+                }
+                Validation::Invalid(reasons)
+            }
+        }
+    }
+
+    /// Returns true if no valid configuration with at least one feature
+    /// exists.
+    pub fn is_void(&self) -> bool {
+        if self.features.is_empty() {
+            return false;
+        }
+        let any_feature: Clause = (0..self.features.len()).map(Literal::positive).collect();
+        let mut formula = self.formula.clone();
+        formula.add_clause(any_feature);
+        !formula.is_satisfiable(&[])
+    }
+
+    /// Returns all features that are not part of any valid configuration.
+    pub fn dead_features(&self) -> Vec<QualifiedPath> {
+        let mut alive = vec![false; self.features.len()];
+        let mut dead = Vec::new();
+        for id in 0..self.features.len() {
+            if alive[id] {
+                continue;
+            }
+            match self.formula.solve(&[Literal::positive(id)]) {
+                Some(assignment) => {
+                    for (other, selected) in assignment.into_iter().enumerate() {
+                        alive[other] |= selected;
+                    }
+                }
+                None => dead.push(self.features[id].clone()),
+            }
+        }
+        dead
+    }
+
+    /// Returns all optional features that are selected in every valid
+    /// configuration containing their parent.
+    pub fn false_optional_features(&self) -> Vec<QualifiedPath> {
+        let mut result = Vec::new();
+        for id in 0..self.features.len() {
+            if self.variability[id].is_mandatory() {
+                continue;
+            }
+            let mut assumptions = vec![Literal::negative(id)];
+            if let Some(parent) = self.parents[id] {
+                if self.variability[parent].group() != &GroupKind::And
+                    || !self.formula.is_satisfiable(&[Literal::positive(parent)])
+                {
+                    continue;
+                }
+                assumptions.push(Literal::positive(parent));
+            }
+            if !self.formula.is_satisfiable(&assumptions) {
+                result.push(self.features[id].clone());
+            }
+        }
+        result
+    }
+
+    /// Returns the number of valid configurations, including the empty one.
+    pub fn count_configurations(&self) -> u128 {
+        self.formula.count(&[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prepare_formula(constraints: &[(&str, &str, &str)]) -> FeatureModelFormula {
+        let mut model = TreeDataModel::new();
+        for path in [
+            "/main/feature/root",
+            "/main/feature/root/db",
+            "/main/feature/root/db/postgres",
+            "/main/feature/root/db/maria",
+            "/main/feature/root/log",
+        ] {
+            model
+                .insert_qualified_path(QualifiedPath::from(path), false)
+                .unwrap();
+        }
+        model
+            .set_variability(
+                &QualifiedPath::from("/main/feature/root"),
+                Variability::new(true, GroupKind::And),
+            )
+            .unwrap();
+        model
+            .set_variability(
+                &QualifiedPath::from("/main/feature/root/db"),
+                Variability::new(true, GroupKind::Alternative),
+            )
+            .unwrap();
+        let mut all_constraints = Constraints::new();
+        for (source, kind, target) in constraints {
+            all_constraints.add(Constraint::new(
+                ConstraintKind::from(*kind),
+                QualifiedPath::from(*source),
+                QualifiedPath::from(*target),
+            ));
+        }
+        let feature_root = model
+            .get_area(&QualifiedPath::from("main"))
+            .unwrap()
+            .to_feature_root()
+            .unwrap();
+        FeatureModelFormula::new(&feature_root, &all_constraints)
+    }
+
+    #[test]
+    fn test_validate() {
+        let formula = prepare_formula(&[]);
+        let valid = formula.validate(
+            &[
+                QualifiedPath::from("/main/feature/root"),
+                QualifiedPath::from("/main/feature/root/db"),
+                QualifiedPath::from("/main/feature/root/db/maria"),
+            ],
+            false,
+        );
+        assert!(matches!(valid, Validation::Valid(_)));
+        let partial = formula.validate(&[QualifiedPath::from("/main/feature/root/log")], true);
+        match partial {
+            Validation::Valid(configuration) => assert_eq!(configuration.len(), 4),
+            Validation::Invalid(_) => panic!("Should be valid"),
+        }
+        let invalid = formula.validate(
+            &[
+                QualifiedPath::from("/main/feature/root"),
+                QualifiedPath::from("/main/feature/root/db"),
+            ],
+            false,
+        );
+        assert_eq!(
+            invalid,
+            Validation::Invalid(vec![Reason::Group {
+                parent: QualifiedPath::from("/main/feature/root/db"),
+                kind: GroupKind::Alternative,
+            }])
+        );
+    }
+
+    #[test]
+    fn test_analyses() {
+        let formula = prepare_formula(&[]);
+        assert!(!formula.is_void());
+        assert!(formula.dead_features().is_empty());
+        assert!(formula.false_optional_features().is_empty());
+        assert_eq!(formula.count_configurations(), 4);
+
+        let formula = prepare_formula(&[
+            (
+                "/main/feature/root/db/maria",
+                "requires",
+                "/main/feature/root/log",
+            ),
+            (
+                "/main/feature/root/log",
+                "excludes",
+                "/main/feature/root/db/maria",
+            ),
+            (
+                "/main/feature/root/db/postgres",
+                "requires",
+                "/main/feature/root/log",
+            ),
+        ]);
+        assert!(!formula.is_void());
+        assert_eq!(
+            formula.dead_features(),
+            vec![QualifiedPath::from("/main/feature/root/db/maria")]
+        );
+        assert_eq!(
+            formula.false_optional_features(),
+            vec![QualifiedPath::from("/main/feature/root/log")]
+        );
+        assert_eq!(formula.count_configurations(), 1);
+    }
+}
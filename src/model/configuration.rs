@@ -0,0 +1,143 @@
+use crate::model::{ParseError, QualifiedPath};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigurationFormat {
+    /// `{"name": "...", "features": ["root/foo", ...]}` with features
+    /// relative to the feature root.
+    Native,
+    /// The JSON configurations of UVL tools, which select features by name:
+    /// `{"file": "model.uvl", "config": {"foo": true, ...}}`.
+    UVL,
+}
+
+impl ConfigurationFormat {
+    pub fn from_file_extension(path: &Path) -> Option<ConfigurationFormat> {
+        match path.extension()?.to_str()?.to_uppercase().as_str() {
+            "JSON" => Some(ConfigurationFormat::Native),
+            "UVL-CONFIG" => Some(ConfigurationFormat::UVL),
+            _ => None,
+        }
+    }
+}
+
+/// A selected feature, either by its path relative to the feature root or
+/// by its name as written by the UVL export.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfiguredFeature {
+    Path(QualifiedPath),
+    Name(String),
+}
+
+/// A named product and the features selected for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProductConfiguration {
+    name: String,
+    features: Vec<ConfiguredFeature>,
+}
+
+#[derive(Deserialize)]
+struct NativeConfiguration {
+    name: Option<String>,
+    features: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct UVLConfiguration {
+    name: Option<String>,
+    config: BTreeMap<String, Value>,
+}
+
+impl ProductConfiguration {
+    pub fn new(name: String, features: Vec<ConfiguredFeature>) -> Self {
+        Self { name, features }
+    }
+    /// Parses a configuration. Configurations without a name are named
+    /// `default_name`, usually the stem of their file name.
+    pub fn parse(
+        data: &str,
+        format: ConfigurationFormat,
+        default_name: &str,
+    ) -> Result<Self, ParseError> {
+        let to_parse_error =
+            |err: serde_json::Error| ParseError::new(err.line(), err.column(), err.to_string());
+        let (name, features) = match format {
+            ConfigurationFormat::Native => {
+                let config: NativeConfiguration =
+                    serde_json::from_str(data).map_err(to_parse_error)?;
+                let features = config
+                    .features
+                    .into_iter()
+                    .map(|feature| ConfiguredFeature::Path(QualifiedPath::from(feature)))
+                    .collect();
+                (config.name, features)
+            }
+            ConfigurationFormat::UVL => {
+                let config: UVLConfiguration =
+                    serde_json::from_str(data).map_err(to_parse_error)?;
+                // attributes have non-boolean values and deselected features are false
+                let features = config
+                    .config
+                    .into_iter()
+                    .filter(|(_, value)| value == &Value::Bool(true))
+                    .map(|(name, _)| ConfiguredFeature::Name(name))
+                    .collect();
+                (config.name, features)
+            }
+        };
+        Ok(Self::new(
+            name.unwrap_or_else(|| default_name.to_string()),
+            features,
+        ))
+    }
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+    pub fn features(&self) -> &Vec<ConfiguredFeature> {
+        &self.features
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_configurations() {
+        let native = ProductConfiguration::parse(
+            r#"{"name": "myprod", "features": ["root/foo", "root/bar"]}"#,
+            ConfigurationFormat::Native,
+            "product",
+        )
+        .unwrap();
+        assert_eq!(native.name(), "myprod");
+        assert_eq!(
+            native.features(),
+            &vec![
+                ConfiguredFeature::Path(QualifiedPath::from("root/foo")),
+                ConfiguredFeature::Path(QualifiedPath::from("root/bar")),
+            ]
+        );
+        let uvl = ProductConfiguration::parse(
+            r#"{"file": "model.uvl", "config": {"foo": true, "bar": false, "size": 3}}"#,
+            ConfigurationFormat::UVL,
+            "product",
+        )
+        .unwrap();
+        assert_eq!(uvl.name(), "product");
+        assert_eq!(
+            uvl.features(),
+            &vec![ConfiguredFeature::Name("foo".to_string())]
+        );
+        let error = ProductConfiguration::parse(
+            "{\"features\": [1]}",
+            ConfigurationFormat::Native,
+            "product",
+        )
+        .unwrap_err();
+        assert_eq!(error.line(), 1);
+    }
+}
@@ -0,0 +1,240 @@
+use crate::model::QualifiedPath;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConstraintKind {
+    Requires,
+    Excludes,
+}
+
+impl<S: Into<String>> From<S> for ConstraintKind {
+    fn from(value: S) -> Self {
+        let real = value.into();
+        match real.to_uppercase().as_str() {
+            "REQUIRES" => ConstraintKind::Requires,
+            "EXCLUDES" => ConstraintKind::Excludes,
+            _ => unreachable!("Unknown constraint kind '{}'", real),
+        }
+    }
+}
+
+impl Display for ConstraintKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConstraintKind::Requires => f.write_str("requires"),
+            ConstraintKind::Excludes => f.write_str("excludes"),
+        }
+    }
+}
+
+/// A cross-tree constraint between two features, stored with absolute paths.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Constraint {
+    kind: ConstraintKind,
+    source: QualifiedPath,
+    target: QualifiedPath,
+}
+
+impl PartialEq for Constraint {
+    fn eq(&self, other: &Self) -> bool {
+        if self.kind != other.kind {
+            return false;
+        }
+        let same = self.source == other.source && self.target == other.target;
+        match self.kind {
+            ConstraintKind::Requires => same,
+            ConstraintKind::Excludes => {
+                same || (self.source == other.target && self.target == other.source)
+            }
+        }
+    }
+}
+
+impl Display for Constraint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.source, self.kind, self.target)
+    }
+}
+
+impl Constraint {
+    pub fn new(kind: ConstraintKind, source: QualifiedPath, target: QualifiedPath) -> Self {
+        Self {
+            kind,
+            source,
+            target,
+        }
+    }
+    pub fn kind(&self) -> &ConstraintKind {
+        &self.kind
+    }
+    pub fn source(&self) -> &QualifiedPath {
+        &self.source
+    }
+    pub fn target(&self) -> &QualifiedPath {
+        &self.target
+    }
+    pub fn is_satisfied_by(&self, selection: &[QualifiedPath]) -> bool {
+        let has_source = selection.contains(&self.source);
+        let has_target = selection.contains(&self.target);
+        match self.kind {
+            ConstraintKind::Requires => !has_source || has_target,
+            ConstraintKind::Excludes => !(has_source && has_target),
+        }
+    }
+    pub fn display_relative_to(&self, base: &QualifiedPath) -> String {
+        format!(
+            "{} {} {}",
+            self.source.strip_n_left(base.len()),
+            self.kind,
+            self.target.strip_n_left(base.len())
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Constraints {
+    constraints: Vec<Constraint>,
+}
+
+impl Constraints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds a constraint, returns false if it already existed.
+    pub fn add(&mut self, constraint: Constraint) -> bool {
+        if self.constraints.contains(&constraint) {
+            return false;
+        }
+        self.constraints.push(constraint);
+        true
+    }
+    /// Removes a constraint, returns false if it did not exist.
+    pub fn remove(&mut self, constraint: &Constraint) -> bool {
+        let n_before = self.constraints.len();
+        self.constraints.retain(|c| c != constraint);
+        n_before != self.constraints.len()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &Constraint> {
+        self.constraints.iter()
+    }
+    pub fn iter_below<'a>(
+        &'a self,
+        prefix: &'a QualifiedPath,
+    ) -> impl Iterator<Item = &'a Constraint> {
+        self.constraints
+            .iter()
+            .filter(|c| c.source.starts_with(prefix) || c.target.starts_with(prefix))
+    }
+    pub fn is_empty(&self) -> bool {
+        self.constraints.is_empty()
+    }
+    pub fn violations(&self, selection: &[QualifiedPath]) -> Vec<&Constraint> {
+        self.constraints
+            .iter()
+            .filter(|c| !c.is_satisfied_by(selection))
+            .collect()
+    }
+    /// Returns the selection extended by all transitively required features.
+    pub fn complete(&self, selection: &[QualifiedPath]) -> Vec<QualifiedPath> {
+        let mut completed = selection.to_vec();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for constraint in self.constraints.iter() {
+                if constraint.kind == ConstraintKind::Requires
+                    && completed.contains(&constraint.source)
+                    && !completed.contains(&constraint.target)
+                {
+                    completed.push(constraint.target.clone());
+                    changed = true;
+                }
+            }
+        }
+        completed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prepare_constraints() -> Constraints {
+        let mut constraints = Constraints::new();
+        constraints.add(Constraint::new(
+            ConstraintKind::Requires,
+            QualifiedPath::from("/main/feature/a"),
+            QualifiedPath::from("/main/feature/b"),
+        ));
+        constraints.add(Constraint::new(
+            ConstraintKind::Requires,
+            QualifiedPath::from("/main/feature/b"),
+            QualifiedPath::from("/main/feature/c"),
+        ));
+        constraints.add(Constraint::new(
+            ConstraintKind::Excludes,
+            QualifiedPath::from("/main/feature/c"),
+            QualifiedPath::from("/main/feature/d"),
+        ));
+        constraints
+    }
+
+    #[test]
+    fn test_constraints_no_duplicates() {
+        let mut constraints = prepare_constraints();
+        assert!(!constraints.add(Constraint::new(
+            ConstraintKind::Excludes,
+            QualifiedPath::from("/main/feature/d"),
+            QualifiedPath::from("/main/feature/c"),
+        )));
+        assert!(constraints.add(Constraint::new(
+            ConstraintKind::Requires,
+            QualifiedPath::from("/main/feature/b"),
+            QualifiedPath::from("/main/feature/a"),
+        )));
+    }
+
+    #[test]
+    fn test_constraints_violations() {
+        let constraints = prepare_constraints();
+        let selection = vec![
+            QualifiedPath::from("/main/feature/a"),
+            QualifiedPath::from("/main/feature/c"),
+            QualifiedPath::from("/main/feature/d"),
+        ];
+        let violations = constraints.violations(&selection);
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[0].kind(), &ConstraintKind::Requires);
+        assert_eq!(violations[1].kind(), &ConstraintKind::Excludes);
+    }
+
+    #[test]
+    fn test_constraints_complete() {
+        let constraints = prepare_constraints();
+        let completed = constraints.complete(&[QualifiedPath::from("/main/feature/a")]);
+        assert_eq!(
+            completed,
+            vec!["/main/feature/a", "/main/feature/b", "/main/feature/c"]
+        );
+        assert!(constraints.violations(&completed).is_empty());
+    }
+
+    #[test]
+    fn test_constraints_remove() {
+        let mut constraints = prepare_constraints();
+        assert!(constraints.remove(&Constraint::new(
+            ConstraintKind::Excludes,
+            QualifiedPath::from("/main/feature/d"),
+            QualifiedPath::from("/main/feature/c"),
+        )));
+        assert!(
+            constraints
+                .violations(&[
+                    QualifiedPath::from("/main/feature/c"),
+                    QualifiedPath::from("/main/feature/d"),
+                ])
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use crate::model::QualifiedPath;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A feature merged into a product, with the commit its branch pointed to
+/// at derivation time. Records migrated from derivation commits do not know
+/// the commit.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DerivedFeature {
+    path: QualifiedPath,
+    commit: Option<String>,
+}
+
+impl DerivedFeature {
+    pub fn new(path: QualifiedPath, commit: Option<String>) -> Self {
+        Self { path, commit }
+    }
+    pub fn path(&self) -> &QualifiedPath {
+        &self.path
+    }
+    pub fn commit(&self) -> Option<&String> {
+        self.commit.as_ref()
+    }
+}
+
+/// Describes how a product branch was derived. `commit` is the commit of the
+/// product branch after the features were first merged. Extending or
+/// updating the product keeps it and records the new feature commits; later
+/// commits on the product that belong to no feature are changes made to the
+/// product itself.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DerivationRecord {
+    product: QualifiedPath,
+    features: Vec<DerivedFeature>,
+    commit: String,
+    timestamp: u64,
+    tangl_version: String,
+}
+
+impl DerivationRecord {
+    pub fn new(product: QualifiedPath, features: Vec<DerivedFeature>, commit: String) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self {
+            product,
+            features,
+            commit,
+            timestamp,
+            tangl_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+    pub fn product(&self) -> &QualifiedPath {
+        &self.product
+    }
+    pub fn features(&self) -> &Vec<DerivedFeature> {
+        &self.features
+    }
+    pub fn feature_paths(&self) -> Vec<QualifiedPath> {
+        self.features.iter().map(|f| f.path.clone()).collect()
+    }
+    pub fn commit(&self) -> &String {
+        &self.commit
+    }
+    /// Seconds since the unix epoch.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+    pub fn tangl_version(&self) -> &String {
+        &self.tangl_version
+    }
+}
+
+/// All derivations of a repository, oldest first.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DerivationRecords {
+    records: Vec<DerivationRecord>,
+}
+
+impl DerivationRecords {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn add(&mut self, record: DerivationRecord) {
+        self.records.push(record);
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &DerivationRecord> {
+        self.records.iter()
+    }
+    /// Returns the most recent derivation of a product.
+    pub fn latest(&self, product: &QualifiedPath) -> Option<&DerivationRecord> {
+        self.records.iter().rev().find(|r| &r.product == product)
+    }
+    /// Removes all derivations of a product, returns true if any existed.
+    pub fn remove(&mut self, product: &QualifiedPath) -> bool {
+        let n_before = self.records.len();
+        self.records.retain(|r| &r.product != product);
+        n_before != self.records.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(product: &str, commit: &str) -> DerivationRecord {
+        DerivationRecord::new(
+            QualifiedPath::from(product),
+            vec![DerivedFeature::new(
+                QualifiedPath::from("/main/feature/root"),
+                Some("abc".to_string()),
+            )],
+            commit.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_derivation_records() {
+        let mut records = DerivationRecords::new();
+        records.add(record("/main/product/a", "1"));
+        records.add(record("/main/product/b", "2"));
+        records.add(record("/main/product/a", "3"));
+        assert_eq!(
+            records
+                .latest(&QualifiedPath::from("/main/product/a"))
+                .unwrap()
+                .commit(),
+            "3"
+        );
+        assert!(records.remove(&QualifiedPath::from("/main/product/a")));
+        assert!(
+            records
+                .latest(&QualifiedPath::from("/main/product/a"))
+                .is_none()
+        );
+        assert_eq!(records.iter().count(), 1);
+    }
+}
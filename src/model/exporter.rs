@@ -0,0 +1,399 @@
+use crate::model::*;
+use serde::Serialize;
+use std::fmt::Debug;
+
+#[derive(Debug, Clone)]
+pub enum ExportFormat {
+    UVL,
+    JSON,
+    DOT,
+}
+
+impl<S: Into<String>> From<S> for ExportFormat {
+    fn from(value: S) -> Self {
+        let real = value.into();
+        match real.to_uppercase().as_str() {
+            "UVL" => ExportFormat::UVL,
+            "JSON" => ExportFormat::JSON,
+            "DOT" => ExportFormat::DOT,
+            _ => unreachable!("Exporter does not support format '{}'", real),
+        }
+    }
+}
+
+/// Serializes the trees of the given areas into a single document.
+pub trait FormatWriter {
+    fn write(&self, areas: &[NodePath<Area>]) -> String;
+}
+
+pub struct ModelExporter {
+    writer: Box<dyn FormatWriter>,
+}
+
+impl ModelExporter {
+    pub fn new(format: ExportFormat) -> ModelExporter {
+        let writer: Box<dyn FormatWriter> = match format {
+            ExportFormat::UVL => Box::new(UVLExporter),
+            ExportFormat::JSON => Box::new(JSONExporter),
+            ExportFormat::DOT => Box::new(DOTExporter),
+        };
+        ModelExporter { writer }
+    }
+    pub fn export(&self, areas: &[NodePath<Area>]) -> String {
+        self.writer.write(areas)
+    }
+}
+
+fn sorted_children<T: Clone + Debug>(path: &NodePath<T>) -> Vec<NodePath<AnyNodeType>> {
+    let mut children = path
+        .iter_children()
+        .filter(|child| !matches!(child.get_type(), NodeType::Tag))
+        .collect::<Vec<_>>();
+    children.sort_by(|a, b| a.get_name().cmp(b.get_name()));
+    children
+}
+
+fn sorted_subtree<T: Clone + Debug>(path: &NodePath<T>) -> Vec<NodePath<AnyNodeType>> {
+    let mut subtree = path
+        .iter_children_req()
+        .filter(|child| !matches!(child.get_type(), NodeType::Tag))
+        .collect::<Vec<_>>();
+    subtree.sort_by_key(|child| child.get_qualified_path());
+    subtree
+}
+
+fn tag_names<T: Clone + Debug>(path: &NodePath<T>) -> Vec<String> {
+    let mut tags = path
+        .get_tags()
+        .iter()
+        .map(|tag| tag.to_string())
+        .collect::<Vec<_>>();
+    tags.sort();
+    tags
+}
+
+pub const UVL_ROOT_NAME: &str = "tangl";
+const UVL_INDENT: &str = "    ";
+
+/// Writes the feature hierarchy and its group semantics as UVL. If a single
+/// area with a single root feature is exported, that feature becomes the UVL
+/// root, so the file can be imported again without changes. Otherwise each
+/// area becomes an abstract feature; if several areas are exported they share
+/// an abstract root.
+pub struct UVLExporter;
+
+impl UVLExporter {
+    fn format_name(name: &str) -> String {
+        let is_identifier = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if is_identifier {
+            name.to_string()
+        } else {
+            format!("\"{}\"", name)
+        }
+    }
+    fn write_line(out: &mut String, depth: usize, content: &str) {
+        out.push_str(&UVL_INDENT.repeat(depth));
+        out.push_str(content);
+        out.push('\n');
+    }
+    fn write_group(
+        out: &mut String,
+        keyword: &str,
+        children: &[&NodePath<AnyNodeType>],
+        depth: usize,
+    ) {
+        if children.is_empty() {
+            return;
+        }
+        Self::write_line(out, depth, keyword);
+        for child in children.iter() {
+            Self::write_feature(out, child, depth + 1);
+        }
+    }
+    fn write_feature(out: &mut String, path: &NodePath<AnyNodeType>, depth: usize) {
+        Self::write_line(out, depth, &Self::format_name(path.get_name()));
+        let children = sorted_children(path);
+        let all = children.iter().collect::<Vec<_>>();
+        match path.get_metadata().variability().group() {
+            GroupKind::And => {
+                let (mandatory, optional): (Vec<_>, Vec<_>) = all
+                    .into_iter()
+                    .partition(|child| child.get_metadata().variability().is_mandatory());
+                Self::write_group(out, "mandatory", &mandatory, depth + 1);
+                Self::write_group(out, "optional", &optional, depth + 1);
+            }
+            GroupKind::Or => Self::write_group(out, "or", &all, depth + 1),
+            GroupKind::Alternative => Self::write_group(out, "alternative", &all, depth + 1),
+        }
+    }
+    fn write_area(out: &mut String, area: &NodePath<Area>, depth: usize) {
+        Self::write_line(
+            out,
+            depth,
+            &format!("{} {{abstract}}", Self::format_name(area.get_name())),
+        );
+        let features = Self::root_features(area);
+        if !features.is_empty() {
+            Self::write_line(out, depth + 1, "optional");
+            for feature in features.iter() {
+                Self::write_feature(out, feature, depth + 2);
+            }
+        }
+    }
+    fn root_features(area: &NodePath<Area>) -> Vec<NodePath<AnyNodeType>> {
+        match area.clone().to_feature_root() {
+            Some(feature_root) => sorted_children(&feature_root),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl FormatWriter for UVLExporter {
+    fn write(&self, areas: &[NodePath<Area>]) -> String {
+        let mut out = String::from("features\n");
+        match areas.len() {
+            0 => {}
+            1 => match Self::root_features(&areas[0]).as_slice() {
+                [root] => Self::write_feature(&mut out, root, 1),
+                _ => Self::write_area(&mut out, &areas[0], 1),
+            },
+            _ => {
+                Self::write_line(&mut out, 1, &format!("{} {{abstract}}", UVL_ROOT_NAME));
+                Self::write_line(&mut out, 2, "optional");
+                for area in areas.iter() {
+                    Self::write_area(&mut out, area, 3);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// A node and its subtree as written by the JSON export.
+#[derive(Serialize)]
+pub struct ExportedNode {
+    name: String,
+    path: String,
+    #[serde(rename = "type")]
+    node_type: NodeType,
+    has_branch: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    variability: Option<Variability>,
+    tags: Vec<String>,
+    children: Vec<ExportedNode>,
+}
+
+impl ExportedNode {
+    pub fn build<T: Clone + Debug>(path: &NodePath<T>) -> Self {
+        Self {
+            name: path.get_name().clone(),
+            path: path.get_qualified_path().to_string(),
+            node_type: path.get_type().clone(),
+            has_branch: path.get_metadata().has_branch(),
+            commit: path.get_metadata().commit().cloned(),
+            variability: match path.get_type() {
+                NodeType::Feature => Some(path.get_metadata().variability().clone()),
+                _ => None,
+            },
+            tags: tag_names(path),
+            children: sorted_children(path).iter().map(Self::build).collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ExportedModel {
+    areas: Vec<ExportedNode>,
+}
+
+/// Writes the complete tree of every area, including products, as JSON.
+pub struct JSONExporter;
+
+impl FormatWriter for JSONExporter {
+    fn write(&self, areas: &[NodePath<Area>]) -> String {
+        let model = ExportedModel {
+            areas: areas.iter().map(ExportedNode::build).collect(),
+        };
+        serde_json::to_string_pretty(&model).unwrap()
+    }
+}
+
+/// Writes the complete tree of every area as a Graphviz digraph.
+/// Nodes without a branch are drawn dashed.
+pub struct DOTExporter;
+
+impl DOTExporter {
+    fn shape_of(node_type: &NodeType) -> &str {
+        match node_type {
+            NodeType::Area => "box",
+            NodeType::FeatureRoot | NodeType::ProductRoot => "folder",
+            NodeType::Product => "component",
+            _ => "ellipse",
+        }
+    }
+    /// Escapes a string for use inside a quoted DOT ID.
+    fn escape(value: &str) -> String {
+        value.replace('\\', "\\\\").replace('"', "\\\"")
+    }
+    fn write_node<T: Clone + Debug>(out: &mut String, path: &NodePath<T>) {
+        let mut label = Self::escape(path.get_name());
+        let tags = tag_names(path);
+        if !tags.is_empty() {
+            label = format!("{}\\n[{}]", label, Self::escape(&tags.join(", ")));
+        }
+        let style = if path.get_metadata().has_branch() {
+            "solid"
+        } else {
+            "dashed"
+        };
+        out.push_str(&format!(
+            "    \"{}\" [label=\"{}\", shape={}, style={}];\n",
+            Self::escape(&path.get_qualified_path().to_string()),
+            label,
+            Self::shape_of(path.get_type()),
+            style
+        ));
+    }
+}
+
+impl FormatWriter for DOTExporter {
+    fn write(&self, areas: &[NodePath<Area>]) -> String {
+        let mut out = String::from("digraph tangl {\n");
+        for area in areas.iter() {
+            Self::write_node(&mut out, area);
+            for node in sorted_subtree(area) {
+                Self::write_node(&mut out, &node);
+                let path = node.get_qualified_path();
+                out.push_str(&format!(
+                    "    \"{}\" -> \"{}\";\n",
+                    Self::escape(&path.strip_n_right(path.len() - 1).to_string()),
+                    Self::escape(&path.to_string())
+                ));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prepare_areas() -> (TreeDataModel, Vec<QualifiedPath>) {
+        let mut model = TreeDataModel::new();
+        for path in [
+            "/main",
+            "/main/feature/root",
+            "/main/feature/root/foo",
+            "/main/feature/root/bar-baz",
+            "/main/product/myprod",
+        ] {
+            model
+                .insert_qualified_path(QualifiedPath::from(path), false)
+                .unwrap();
+        }
+        model
+            .insert_qualified_path(QualifiedPath::from("/main/feature/root/foo/v1"), true)
+            .unwrap();
+        model
+            .set_variability(
+                &QualifiedPath::from("/main/feature/root/foo"),
+                Variability::new(true, GroupKind::And),
+            )
+            .unwrap();
+        (model, vec![QualifiedPath::from("main")])
+    }
+
+    fn export(format: ExportFormat) -> String {
+        let (model, area_paths) = prepare_areas();
+        let areas = area_paths
+            .iter()
+            .map(|path| model.get_area(path).unwrap())
+            .collect::<Vec<_>>();
+        ModelExporter::new(format).export(&areas)
+    }
+
+    #[test]
+    fn test_export_uvl() {
+        assert_eq!(
+            export(ExportFormat::UVL),
+            "features
+    root
+        mandatory
+            foo
+        optional
+            \"bar-baz\"
+"
+        );
+    }
+
+    #[test]
+    fn test_export_uvl_round_trip() {
+        let (model, area_paths) = prepare_areas();
+        let area = model.get_area(&area_paths[0]).unwrap();
+        let exported = ModelExporter::new(ExportFormat::UVL).export(&[area]);
+        let imported = ModelImporter::new(ImportFormat::UVL)
+            .import(&exported, &QualifiedPath::from("/main/feature"))
+            .unwrap();
+        let mut paths = imported.get_qualified_paths_with_branches().clone();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                QualifiedPath::from("/main/feature/root"),
+                QualifiedPath::from("/main/feature/root/bar-baz"),
+                QualifiedPath::from("/main/feature/root/foo"),
+            ]
+        );
+        assert_eq!(imported.collect_variability(), model.collect_variability());
+    }
+
+    #[test]
+    fn test_export_json() {
+        let value: serde_json::Value = serde_json::from_str(&export(ExportFormat::JSON)).unwrap();
+        let area = &value["areas"][0];
+        assert_eq!(area["type"], "area");
+        assert_eq!(area["children"][0]["type"], "feature_root");
+        assert_eq!(area["children"][0]["has_branch"], false);
+        let foo = &area["children"][0]["children"][0]["children"][1];
+        assert_eq!(foo["path"], "/main/feature/root/foo");
+        assert_eq!(foo["has_branch"], true);
+        assert_eq!(foo["tags"], serde_json::json!(["v1"]));
+        assert_eq!(foo["children"], serde_json::json!([]));
+        assert_eq!(
+            foo["variability"],
+            serde_json::json!({"mandatory": true, "group": "and"})
+        );
+        assert!(area["variability"].is_null());
+        assert_eq!(area["children"][1]["children"][0]["type"], "product");
+    }
+
+    #[test]
+    fn test_export_dot() {
+        let dot = export(ExportFormat::DOT);
+        assert!(dot.starts_with("digraph tangl {\n"));
+        assert!(dot.contains(
+            "\"/main/feature/root/foo\" [label=\"foo\\n[v1]\", shape=ellipse, style=solid];"
+        ));
+        assert!(dot.contains("\"/main/feature\" [label=\"feature\", shape=folder, style=dashed];"));
+        assert!(dot.contains("\"/main/feature/root\" -> \"/main/feature/root/foo\";"));
+        assert!(!dot.contains("/main/feature/root/foo/v1"));
+    }
+
+    #[test]
+    fn test_export_dot_escapes_names() {
+        let mut model = TreeDataModel::new();
+        model
+            .insert_qualified_path(QualifiedPath::from("/main/feature/root/say\"hi\\"), false)
+            .unwrap();
+        let area = model.get_area(&QualifiedPath::from("main")).unwrap();
+        let dot = ModelExporter::new(ExportFormat::DOT).export(&[area]);
+        assert!(dot.contains(
+            "\"/main/feature/root/say\\\"hi\\\\\" [label=\"say\\\"hi\\\\\", shape=ellipse"
+        ));
+    }
+}
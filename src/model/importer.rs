@@ -1,4 +1,8 @@
-use crate::model::{QualifiedPath, TreeDataModel, WrongNodeTypeError};
+use crate::model::{GroupKind, QualifiedPath, TreeDataModel, Variability, WrongNodeTypeError};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
 
 #[derive(Debug, Clone)]
 pub enum ImportFormat {
@@ -19,8 +23,97 @@ impl<S: Into<String>> From<S> for ImportFormat {
     }
 }
 
+impl ImportFormat {
+    pub fn from_file_extension(path: &Path) -> Option<ImportFormat> {
+        match path.extension()?.to_str()?.to_uppercase().as_str() {
+            "UVL" => Some(ImportFormat::UVL),
+            "WAFFLE" => Some(ImportFormat::Waffle),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    line: usize,
+    column: usize,
+    msg: String,
+}
+impl ParseError {
+    pub fn new<S: Into<String>>(line: usize, column: usize, msg: S) -> ParseError {
+        ParseError {
+            line,
+            column,
+            msg: msg.into(),
+        }
+    }
+    pub fn line(&self) -> usize {
+        self.line
+    }
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.msg
+        )
+    }
+}
+impl Error for ParseError {}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Parse(ParseError),
+    WrongNodeType(WrongNodeTypeError),
+}
+impl Display for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Parse(err) => err.fmt(f),
+            ImportError::WrongNodeType(err) => err.fmt(f),
+        }
+    }
+}
+impl Error for ImportError {}
+impl From<ParseError> for ImportError {
+    fn from(value: ParseError) -> Self {
+        ImportError::Parse(value)
+    }
+}
+impl From<WrongNodeTypeError> for ImportError {
+    fn from(value: WrongNodeTypeError) -> Self {
+        ImportError::WrongNodeType(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedFeature {
+    path: QualifiedPath,
+    variability: Variability,
+}
+impl ImportedFeature {
+    pub fn new(path: QualifiedPath) -> Self {
+        Self {
+            path,
+            variability: Variability::default(),
+        }
+    }
+    pub fn path(&self) -> &QualifiedPath {
+        &self.path
+    }
+    pub fn variability(&self) -> &Variability {
+        &self.variability
+    }
+}
+
+/// Parses a feature model file into features relative to the feature root.
+/// Parents are always returned before their children.
 pub trait FormatParser {
-    fn parse(&self, data: &str) -> Vec<QualifiedPath>;
+    fn parse(&self, data: &str) -> Result<Vec<ImportedFeature>, ParseError>;
 }
 
 pub struct ModelImporter {
@@ -29,30 +122,460 @@ pub struct ModelImporter {
 
 impl ModelImporter {
     pub fn new(format: ImportFormat) -> ModelImporter {
-        let parser = match format {
-            ImportFormat::Waffle => WaffleImporter,
-            _ => {
-                todo!()
+        let parser: Box<dyn FormatParser> = match format {
+            ImportFormat::Waffle => Box::new(WaffleImporter),
+            ImportFormat::UVL => Box::new(UVLImporter),
+            ImportFormat::Native => {
+                unreachable!("The native format is read from the repository and cannot be imported")
             }
         };
-        ModelImporter {
-            parser: Box::new(parser),
-        }
+        ModelImporter { parser }
     }
-    pub fn import(&self, data: &str) -> Result<TreeDataModel, WrongNodeTypeError> {
-        let paths = self.parser.parse(&data);
+    pub fn import(
+        &self,
+        data: &str,
+        feature_root: &QualifiedPath,
+    ) -> Result<TreeDataModel, ImportError> {
+        let features = self.parser.parse(data)?;
         let mut model = TreeDataModel::new();
-        for path in paths {
-            model.insert_qualified_path(path, false)?;
+        for feature in features {
+            let path = feature_root.clone() + feature.path;
+            model.insert_qualified_path(path.clone(), false)?;
+            model.set_variability(&path, feature.variability)?;
         }
         Ok(model)
     }
 }
 
+const INVALID_NAME_CHARACTERS: [char; 10] = ['/', '\\', ' ', '~', '^', ':', '?', '*', '[', ']'];
+
+/// Checks that a feature name can be used as a segment of a git branch.
+/// `column` is the 1-based column the name starts at.
+fn validate_feature_name(name: &str, line: usize, column: usize) -> Result<(), ParseError> {
+    if name.is_empty() {
+        return Err(ParseError::new(line, column, "Missing feature name"));
+    }
+    if name == "." || name == ".." || name.starts_with('-') || name.ends_with('.') {
+        return Err(ParseError::new(
+            line,
+            column,
+            format!("'{}' is not a valid feature name", name),
+        ));
+    }
+    match name
+        .chars()
+        .position(|c| INVALID_NAME_CHARACTERS.contains(&c) || c.is_control())
+    {
+        Some(position) => Err(ParseError::new(
+            line,
+            column + position,
+            format!(
+                "Feature name '{}' contains invalid character '{}'",
+                name,
+                name.chars().nth(position).unwrap()
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn column_of(line: &str, content: &str) -> usize {
+    line[..line.len() - line.trim_start().len()].chars().count()
+        + line.trim_start().find(content).unwrap_or(0)
+        + 1
+}
+
+const WAFFLE_COMMENT: char = '#';
+
+/// Parser for the Waffle format.
+///
+/// Every non-empty line declares one feature by its path relative to the
+/// feature root, e.g. `root/database/postgres`. Lines starting with `#` are
+/// comments. Parents that are not declared explicitly are added implicitly.
 pub struct WaffleImporter;
 
 impl FormatParser for WaffleImporter {
-    fn parse(&self, data: &str) -> Vec<QualifiedPath> {
-        todo!()
+    fn parse(&self, data: &str) -> Result<Vec<ImportedFeature>, ParseError> {
+        let mut paths: Vec<QualifiedPath> = Vec::new();
+        let mut declared: HashSet<QualifiedPath> = HashSet::new();
+        for (index, line) in data.lines().enumerate() {
+            let line_number = index + 1;
+            let content = line.trim();
+            if content.is_empty() || content.starts_with(WAFFLE_COMMENT) {
+                continue;
+            }
+            let mut column = column_of(line, content);
+            if content.starts_with('/') {
+                return Err(ParseError::new(
+                    line_number,
+                    column,
+                    "Feature paths must be relative to the feature root",
+                ));
+            }
+            let mut path = QualifiedPath::new();
+            for segment in content.split('/') {
+                validate_feature_name(segment, line_number, column)?;
+                path.push(segment);
+                if !paths.contains(&path) {
+                    paths.push(path.clone());
+                }
+                column += segment.chars().count() + 1;
+            }
+            if !declared.insert(path.clone()) {
+                return Err(ParseError::new(
+                    line_number,
+                    column_of(line, content),
+                    format!("Feature '{}' is declared more than once", content),
+                ));
+            }
+        }
+        Ok(paths.into_iter().map(ImportedFeature::new).collect())
+    }
+}
+
+const UVL_FEATURES_KEYWORD: &str = "features";
+const UVL_GROUP_KEYWORDS: [&str; 4] = ["mandatory", "optional", "alternative", "or"];
+const UVL_TYPE_KEYWORDS: [&str; 4] = ["Boolean", "Integer", "Real", "String"];
+
+enum UVLBlockEntry {
+    Feature(String, usize),
+    Group(UVLGroup),
+}
+
+#[derive(Clone, PartialEq)]
+enum UVLGroup {
+    Mandatory,
+    Optional,
+    Kind(GroupKind),
+}
+
+/// Parser for the `features` block of the Universal Variability Language.
+///
+/// The feature hierarchy and the group semantics are extracted, everything
+/// outside the `features` block is ignored. Group cardinalities are mapped to
+/// the closest group kind. A feature may either have `mandatory` and
+/// `optional` groups or a single `alternative` or `or` group.
+pub struct UVLImporter;
+
+impl UVLImporter {
+    fn indentation_of(line: &str) -> usize {
+        line.chars()
+            .take_while(|c| c.is_whitespace())
+            .map(|c| if c == '\t' { 4 } else { 1 })
+            .sum()
+    }
+    fn strip_comment(line: &str) -> &str {
+        match line.find("//") {
+            Some(index) => &line[..index],
+            None => line,
+        }
+    }
+    fn parse_group(content: &str) -> Option<UVLGroup> {
+        match content {
+            "mandatory" => return Some(UVLGroup::Mandatory),
+            "optional" => return Some(UVLGroup::Optional),
+            "alternative" => return Some(UVLGroup::Kind(GroupKind::Alternative)),
+            "or" => return Some(UVLGroup::Kind(GroupKind::Or)),
+            _ => {}
+        }
+        let cardinality = content.strip_prefix('[')?.strip_suffix(']')?;
+        let (lower, upper) = cardinality
+            .split_once("..")
+            .unwrap_or((cardinality, cardinality));
+        match (lower.trim(), upper.trim()) {
+            ("0", _) => Some(UVLGroup::Optional),
+            ("1", "1") => Some(UVLGroup::Kind(GroupKind::Alternative)),
+            _ => Some(UVLGroup::Kind(GroupKind::Or)),
+        }
+    }
+    fn parse_feature_name(content: &str, line: usize, column: usize) -> Result<String, ParseError> {
+        let (content, column) = match content.split_once(char::is_whitespace) {
+            Some((first, rest)) if UVL_TYPE_KEYWORDS.contains(&first) => {
+                let trimmed = rest.trim_start();
+                let skipped = content.chars().count() - trimmed.chars().count();
+                (trimmed, column + skipped)
+            }
+            _ => (content, column),
+        };
+        let name = match content.strip_prefix('"') {
+            Some(quoted) => match quoted.find('"') {
+                Some(end) => {
+                    validate_feature_name(&quoted[..end], line, column + 1)?;
+                    &quoted[..end]
+                }
+                None => {
+                    return Err(ParseError::new(
+                        line,
+                        column,
+                        "Unterminated quoted feature name",
+                    ));
+                }
+            },
+            None => {
+                let name = content
+                    .split(|c: char| c.is_whitespace() || c == '{')
+                    .next()
+                    .unwrap_or_default();
+                validate_feature_name(name, line, column)?;
+                name
+            }
+        };
+        Ok(name.to_string())
+    }
+}
+
+impl FormatParser for UVLImporter {
+    fn parse(&self, data: &str) -> Result<Vec<ImportedFeature>, ParseError> {
+        let mut features: Vec<ImportedFeature> = Vec::new();
+        let mut groups: Vec<Vec<UVLGroup>> = Vec::new();
+        let mut stack: Vec<(usize, UVLBlockEntry)> = Vec::new();
+        let mut in_features = false;
+        for (index, raw_line) in data.lines().enumerate() {
+            let line_number = index + 1;
+            let line = Self::strip_comment(raw_line);
+            let content = line.trim();
+            if content.is_empty() {
+                continue;
+            }
+            let indentation = Self::indentation_of(line);
+            if indentation == 0 {
+                in_features = content == UVL_FEATURES_KEYWORD;
+                stack.clear();
+                continue;
+            }
+            if !in_features {
+                continue;
+            }
+            let column = column_of(line, content);
+            while stack.last().is_some_and(|(i, _)| *i >= indentation) {
+                stack.pop();
+            }
+            if let Some(group) = Self::parse_group(content) {
+                let parent = match stack.last() {
+                    Some((_, UVLBlockEntry::Feature(_, parent))) => *parent,
+                    _ => {
+                        return Err(ParseError::new(
+                            line_number,
+                            column,
+                            format!("Group '{}' must be nested below a feature", content),
+                        ));
+                    }
+                };
+                let seen = &mut groups[parent];
+                let combinable =
+                    |g: &UVLGroup| matches!(g, UVLGroup::Mandatory | UVLGroup::Optional);
+                let compatible = combinable(&group) && seen.iter().all(combinable);
+                if !seen.is_empty() && !compatible {
+                    return Err(ParseError::new(
+                        line_number,
+                        column,
+                        "Alternative and or groups cannot be combined with other groups",
+                    ));
+                }
+                if let UVLGroup::Kind(kind) = &group {
+                    features[parent].variability.set_group(kind.clone());
+                }
+                seen.push(group.clone());
+                stack.push((indentation, UVLBlockEntry::Group(group)));
+                continue;
+            }
+            let name = Self::parse_feature_name(content, line_number, column)?;
+            let mandatory = match stack.last() {
+                Some((_, UVLBlockEntry::Group(group))) => group == &UVLGroup::Mandatory,
+                Some((_, UVLBlockEntry::Feature(_, _))) => {
+                    return Err(ParseError::new(
+                        line_number,
+                        column,
+                        format!(
+                            "Feature '{}' must be nested in a group ({})",
+                            name,
+                            UVL_GROUP_KEYWORDS.join(", ")
+                        ),
+                    ));
+                }
+                None => {
+                    if !features.is_empty() {
+                        return Err(ParseError::new(
+                            line_number,
+                            column,
+                            "Only one root feature is allowed",
+                        ));
+                    }
+                    false
+                }
+            };
+            let mut path = QualifiedPath::new();
+            for (_, entry) in stack.iter() {
+                if let UVLBlockEntry::Feature(parent, _) = entry {
+                    path.push(parent);
+                }
+            }
+            path.push(name.clone());
+            let mut feature = ImportedFeature::new(path);
+            feature.variability.set_mandatory(mandatory);
+            features.push(feature);
+            groups.push(Vec::new());
+            stack.push((
+                indentation,
+                UVLBlockEntry::Feature(name, features.len() - 1),
+            ));
+        }
+        Ok(features)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UVL_MODEL: &str = "namespace Server
+
+features
+    Server {abstract}
+        mandatory
+            Database
+                alternative
+                    Postgres
+                    \"Maria\"
+        optional
+            Logging // writes logs
+                or
+                    File
+                    Syslog
+            Integer Workers
+            Cache cardinality [0..2]
+
+constraints
+    Logging => Database
+";
+
+    #[test]
+    fn test_uvl_parse_hierarchy() {
+        let features = UVLImporter.parse(UVL_MODEL).unwrap();
+        assert_eq!(
+            features
+                .iter()
+                .map(|f| f.path().clone())
+                .collect::<Vec<_>>(),
+            vec![
+                "Server",
+                "Server/Database",
+                "Server/Database/Postgres",
+                "Server/Database/Maria",
+                "Server/Logging",
+                "Server/Logging/File",
+                "Server/Logging/Syslog",
+                "Server/Workers",
+                "Server/Cache",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_uvl_parse_tabs_and_cardinality_groups() {
+        let data = "features\n\tRoot\n\t\t[1..2]\n\t\t\tA\n\t\t\tB\n";
+        let features = UVLImporter.parse(data).unwrap();
+        assert_eq!(
+            features
+                .iter()
+                .map(|f| f.path().clone())
+                .collect::<Vec<_>>(),
+            vec!["Root", "Root/A", "Root/B"]
+        );
+        assert_eq!(features[0].variability().group(), &GroupKind::Or);
+    }
+
+    #[test]
+    fn test_uvl_parse_variability() {
+        let features = UVLImporter.parse(UVL_MODEL).unwrap();
+        let variability = |path: &str| {
+            features
+                .iter()
+                .find(|f| f.path() == &QualifiedPath::from(path))
+                .unwrap()
+                .variability()
+                .clone()
+        };
+        assert_eq!(variability("Server"), Variability::default());
+        assert_eq!(
+            variability("Server/Database"),
+            Variability::new(true, GroupKind::Alternative)
+        );
+        assert_eq!(
+            variability("Server/Logging"),
+            Variability::new(false, GroupKind::Or)
+        );
+        assert_eq!(
+            variability("Server/Database/Postgres"),
+            Variability::default()
+        );
+        let mixed = "features\n    Root\n        alternative\n            A\n        optional\n            B\n";
+        let error = UVLImporter.parse(mixed).unwrap_err();
+        assert_eq!((error.line(), error.column()), (5, 9));
+    }
+
+    #[test]
+    fn test_uvl_import_under_feature_root() {
+        let model = ModelImporter::new(ImportFormat::UVL)
+            .import(UVL_MODEL, &QualifiedPath::from("/main/feature"))
+            .unwrap();
+        assert!(model.has_branch(&QualifiedPath::from("/main/feature/Server/Logging/File")));
+        assert!(!model.has_branch(&QualifiedPath::from("/main/feature")));
+    }
+
+    #[test]
+    fn test_uvl_parse_errors() {
+        let missing_group = "features\n    Root\n        A\n";
+        assert_eq!(
+            UVLImporter.parse(missing_group).unwrap_err(),
+            ParseError::new(
+                3,
+                9,
+                "Feature 'A' must be nested in a group (mandatory, optional, alternative, or)"
+            )
+        );
+        let dangling_group = "features\n    optional\n        A\n";
+        let error = UVLImporter.parse(dangling_group).unwrap_err();
+        assert_eq!((error.line(), error.column()), (2, 5));
+        let unterminated = "features\n    Root\n        optional\n            \"A B\n";
+        let error = UVLImporter.parse(unterminated).unwrap_err();
+        assert_eq!((error.line(), error.column()), (4, 13));
+        let invalid_name = "features\n    Root\n        optional\n            \"A B\"\n";
+        let error = UVLImporter.parse(invalid_name).unwrap_err();
+        assert_eq!((error.line(), error.column()), (4, 15));
+        let two_roots = "features\n    Root\n    Other\n";
+        let error = UVLImporter.parse(two_roots).unwrap_err();
+        assert_eq!((error.line(), error.column()), (3, 5));
+    }
+
+    #[test]
+    fn test_waffle_parse() {
+        let data = "# server model\nroot\nroot/database/postgres\n\n  root/logging\n";
+        let features = WaffleImporter.parse(data).unwrap();
+        assert_eq!(
+            features
+                .iter()
+                .map(|f| f.path().clone())
+                .collect::<Vec<_>>(),
+            vec![
+                "root",
+                "root/database",
+                "root/database/postgres",
+                "root/logging"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_waffle_parse_errors() {
+        let error = WaffleImporter.parse("root\nroot/da ta\n").unwrap_err();
+        assert_eq!((error.line(), error.column()), (2, 8));
+        let error = WaffleImporter.parse("root\n  /root/a\n").unwrap_err();
+        assert_eq!((error.line(), error.column()), (2, 3));
+        let error = WaffleImporter.parse("root//a\n").unwrap_err();
+        assert_eq!((error.line(), error.column()), (1, 6));
+        let error = WaffleImporter.parse("root\nroot\n").unwrap_err();
+        assert_eq!((error.line(), error.column()), (2, 1));
+        assert!(WaffleImporter.parse("root/_data_base\n").is_ok());
     }
 }
@@ -1,13 +1,27 @@
+mod analysis;
 mod commit;
+mod configuration;
+mod constraint;
+mod derivation;
+mod exporter;
 mod importer;
 mod node;
 mod node_path;
 mod qualified_path;
+mod solver;
 mod tree;
+mod variability;
 
+pub use analysis::*;
 pub use commit::*;
+pub use configuration::*;
+pub use constraint::*;
+pub use derivation::*;
+pub use exporter::*;
 pub use importer::*;
 pub use node::*;
 pub use node_path::*;
 pub use qualified_path::*;
+pub use solver::*;
 pub use tree::*;
+pub use variability::*;
@@ -1,5 +1,6 @@
 use crate::model::*;
 use colored::{ColoredString, Colorize};
+use serde::Serialize;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
@@ -39,7 +40,8 @@ pub struct VirtualRoot;
 pub struct Tag;
 #[derive(Clone, Debug)]
 pub struct AnyNodeType;
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum NodeType {
     Feature,
     Product,
@@ -83,22 +85,44 @@ impl NodeType {
     }
 }
 
+/// `commit` is the commit the branch or tag pointed to when the model was
+/// read from the repository.
 #[derive(Clone, Debug)]
 pub struct NodeMetadata {
     has_branch: bool,
+    variability: Variability,
+    commit: Option<String>,
 }
 impl NodeMetadata {
     pub fn new(has_branch: bool) -> Self {
-        Self { has_branch }
+        Self {
+            has_branch,
+            variability: Variability::default(),
+            commit: None,
+        }
     }
     pub fn default() -> Self {
         let i = "".to_string();
         drop(i);
-        Self { has_branch: false }
+        Self {
+            has_branch: false,
+            variability: Variability::default(),
+            commit: None,
+        }
+    }
+    pub fn with_commit(mut self, commit: Option<String>) -> Self {
+        self.commit = commit;
+        self
     }
     pub fn has_branch(&self) -> bool {
         self.has_branch
     }
+    pub fn variability(&self) -> &Variability {
+        &self.variability
+    }
+    pub fn commit(&self) -> Option<&String> {
+        self.commit.as_ref()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -200,7 +224,11 @@ impl Node {
             0 => Ok(()),
             1 => {
                 match self.get_child_mut(&name) {
-                    Some(node) => node.update_metadata(metadata),
+                    // variability is managed separately and survives re-insertion
+                    Some(node) => node.update_metadata(NodeMetadata {
+                        variability: node.metadata.variability.clone(),
+                        ..metadata
+                    }),
                     None => {
                         self.add_child(name.clone(), metadata, is_tag)?;
                     }
@@ -219,6 +247,33 @@ impl Node {
             }
         }
     }
+    pub fn set_variability(
+        &mut self,
+        path: &QualifiedPath,
+        variability: Variability,
+    ) -> Result<(), WrongNodeTypeError> {
+        match path.len() {
+            0 => {
+                match self.node_type {
+                    NodeType::Feature => self.metadata.variability = variability,
+                    _ => {
+                        return Err(WrongNodeTypeError::new(format!(
+                            "'{}' is not a feature and cannot carry variability information",
+                            self.name
+                        )));
+                    }
+                }
+                Ok(())
+            }
+            _ => match self.get_child_mut(&path[0]) {
+                Some(child) => child.set_variability(&path.strip_n_left(1), variability),
+                None => Err(WrongNodeTypeError::new(format!(
+                    "'{}' does not exist below '{}'",
+                    path, self.name
+                ))),
+            },
+        }
+    }
     pub fn as_qualified_path(&self) -> QualifiedPath {
         QualifiedPath::from(self.name.clone())
     }
@@ -140,6 +140,12 @@ impl<T: Clone + Debug> NodePath<T> {
     pub fn get_metadata(&self) -> &NodeMetadata {
         self.get_node().get_metadata()
     }
+    pub fn get_type(&self) -> &NodeType {
+        self.get_node().get_type()
+    }
+    pub fn get_name(&self) -> &String {
+        self.get_node().get_name()
+    }
     pub fn transform_to_any_type(self) -> NodePath<AnyNodeType> {
         NodePath::<AnyNodeType>::from_concrete(self)
     }
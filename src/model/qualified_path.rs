@@ -1,4 +1,5 @@
 use colored::Colorize;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt::{Display, Formatter};
 use std::ops::{Add, Index};
 
@@ -39,6 +40,16 @@ impl From<QualifiedPath> for String {
         value.to_string()
     }
 }
+impl Serialize for QualifiedPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.to_string().as_str())
+    }
+}
+impl<'de> Deserialize<'de> for QualifiedPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(QualifiedPath::from(String::deserialize(deserializer)?))
+    }
+}
 impl PartialEq for QualifiedPath {
     fn eq(&self, other: &Self) -> bool {
         self.path == other.path
@@ -112,6 +123,11 @@ impl Index<usize> for QualifiedPath {
         &self.path[index]
     }
 }
+impl Default for QualifiedPath {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl QualifiedPath {
     pub fn new() -> Self {
         Self { path: Vec::new() }
@@ -135,11 +151,24 @@ impl QualifiedPath {
         self.path.join("/")
     }
     pub fn push<S: Into<String>>(&mut self, path: S) {
-        let qualified_str = path.into().replace("*", "").replace("_", "");
-        for split in qualified_str.trim().split(SEPARATOR) {
+        for split in path.into().trim().split(SEPARATOR) {
             self.path.push(split.to_string());
         }
     }
+    /// Converts a branch or tag name written by [QualifiedPath::to_git_branch]
+    /// back to an absolute path. Only the `_` prefix of the inner segments is
+    /// removed, names may contain underscores. Returns `None` if an inner
+    /// segment lacks the prefix, as the ref was not created by tangl.
+    pub fn from_git_branch(branch: &str) -> Option<QualifiedPath> {
+        let segments: Vec<&str> = branch.trim().split(SEPARATOR).collect();
+        let (last, inner) = segments.split_last()?;
+        let mut path = vec![String::new()];
+        for segment in inner {
+            path.push(segment.strip_prefix('_')?.to_string());
+        }
+        path.push(last.to_string());
+        Some(Self { path })
+    }
     pub fn strip_n(&self, n_left: usize, n_right: usize) -> QualifiedPath {
         QualifiedPath::from(self.path[n_left..n_right].to_vec())
     }
@@ -234,15 +263,32 @@ mod tests {
 
     #[test]
     fn test_qualified_path_from_git_branch() {
-        assert_eq!(QualifiedPath::from("_foo/bar").path, vec!["foo", "bar"]);
         assert_eq!(
-            QualifiedPath::from("_foo/bar".to_string()).path,
-            vec!["foo", "bar"]
+            QualifiedPath::from_git_branch("_foo/bar").unwrap(),
+            QualifiedPath::from("/foo/bar")
         );
         assert_eq!(
-            QualifiedPath::from("_foo/_bar/baz").path,
-            vec!["foo", "bar", "baz"]
+            QualifiedPath::from_git_branch("_foo/_bar/baz")
+                .unwrap()
+                .path,
+            vec!["", "foo", "bar", "baz"]
         );
+        assert_eq!(
+            QualifiedPath::from_git_branch("main").unwrap(),
+            QualifiedPath::from("/main")
+        );
+        assert_eq!(
+            QualifiedPath::from_git_branch("_main/_feature/my_feature").unwrap(),
+            QualifiedPath::from("/main/feature/my_feature")
+        );
+        assert_eq!(
+            QualifiedPath::from_git_branch("_my_area/_feature/_a_b/c_d")
+                .unwrap()
+                .to_git_branch(),
+            "_my_area/_feature/_a_b/c_d"
+        );
+        assert_eq!(QualifiedPath::from_git_branch("dev/x"), None);
+        assert_eq!(QualifiedPath::from("_foo/bar").path, vec!["_foo", "bar"]);
     }
 
     #[test]
@@ -340,4 +386,13 @@ mod tests {
         assert!(absolute.is_absolute());
         assert_eq!(absolute, "/foo/bar");
     }
+
+    #[test]
+    fn test_qualified_path_serde() {
+        let path = QualifiedPath::from("/main/feature/root");
+        let serialized = serde_json::to_string(&path).unwrap();
+        assert_eq!(serialized, "\"/main/feature/root\"");
+        let deserialized: QualifiedPath = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, path);
+    }
 }
@@ -0,0 +1,327 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A boolean variable or its negation. Variables are numbered from zero.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Literal {
+    var: usize,
+    positive: bool,
+}
+
+impl Literal {
+    pub fn positive(var: usize) -> Self {
+        Self {
+            var,
+            positive: true,
+        }
+    }
+    pub fn negative(var: usize) -> Self {
+        Self {
+            var,
+            positive: false,
+        }
+    }
+    pub fn var(&self) -> usize {
+        self.var
+    }
+    pub fn is_positive(&self) -> bool {
+        self.positive
+    }
+    pub fn negate(&self) -> Self {
+        Self {
+            var: self.var,
+            positive: !self.positive,
+        }
+    }
+}
+
+pub type Clause = Vec<Literal>;
+
+/// A formula in conjunctive normal form with a small DPLL solver.
+///
+/// The solver works on residual formulas: assigning a literal removes all
+/// clauses it satisfies and the negated literal from all others. This keeps
+/// the implementation simple and is fast enough for feature models with a
+/// few hundred features.
+#[derive(Clone, Debug, Default)]
+pub struct Formula {
+    n_vars: usize,
+    clauses: Vec<Clause>,
+}
+
+impl Formula {
+    pub fn new(n_vars: usize) -> Self {
+        Self {
+            n_vars,
+            clauses: vec![],
+        }
+    }
+    pub fn n_vars(&self) -> usize {
+        self.n_vars
+    }
+    pub fn clauses(&self) -> &Vec<Clause> {
+        &self.clauses
+    }
+    /// Adds a clause and returns its index.
+    pub fn add_clause(&mut self, clause: Clause) -> usize {
+        debug_assert!(clause.iter().all(|l| l.var < self.n_vars));
+        self.clauses.push(clause);
+        self.clauses.len() - 1
+    }
+
+    /// Returns a satisfying assignment under the given assumptions, if any.
+    pub fn solve(&self, assumptions: &[Literal]) -> Option<Vec<bool>> {
+        self.solve_subset(assumptions, &self.all_clause_ids())
+    }
+    pub fn is_satisfiable(&self, assumptions: &[Literal]) -> bool {
+        self.solve(assumptions).is_some()
+    }
+
+    /// Returns the number of satisfying assignments under the given
+    /// assumptions.
+    pub fn count(&self, assumptions: &[Literal]) -> u128 {
+        let clauses = self.clauses.clone();
+        let vars: BTreeSet<usize> = (0..self.n_vars).collect();
+        match assign_all(clauses, assumptions) {
+            Some((clauses, assigned)) => {
+                if has_contradiction(assumptions) {
+                    return 0;
+                }
+                let vars = vars.difference(&assigned).copied().collect();
+                count_residual(clauses, vars)
+            }
+            None => 0,
+        }
+    }
+
+    /// Returns a minimal set of clause indices that is unsatisfiable together
+    /// with the assumptions, or `None` if the formula is satisfiable.
+    pub fn explain(&self, assumptions: &[Literal]) -> Option<Vec<usize>> {
+        let mut core = self.all_clause_ids();
+        if self.solve_subset(assumptions, &core).is_some() {
+            return None;
+        }
+        let mut index = 0;
+        while index < core.len() {
+            let mut candidate = core.clone();
+            candidate.remove(index);
+            if self.solve_subset(assumptions, &candidate).is_none() {
+                core = candidate;
+            } else {
+                index += 1;
+            }
+        }
+        Some(core)
+    }
+
+    fn all_clause_ids(&self) -> Vec<usize> {
+        (0..self.clauses.len()).collect()
+    }
+
+    fn solve_subset(&self, assumptions: &[Literal], clause_ids: &[usize]) -> Option<Vec<bool>> {
+        if has_contradiction(assumptions) {
+            return None;
+        }
+        let clauses = clause_ids
+            .iter()
+            .map(|i| self.clauses[*i].clone())
+            .collect();
+        let mut assignment = vec![None; self.n_vars];
+        for literal in assumptions {
+            assignment[literal.var] = Some(literal.positive);
+        }
+        let (clauses, _) = assign_all(clauses, assumptions)?;
+        if solve_residual(clauses, &mut assignment) {
+            Some(assignment.into_iter().map(|v| v.unwrap_or(false)).collect())
+        } else {
+            None
+        }
+    }
+}
+
+fn has_contradiction(literals: &[Literal]) -> bool {
+    literals.iter().any(|l| literals.contains(&l.negate()))
+}
+
+/// Simplifies the clauses by a literal, returns `None` on an empty clause.
+fn assign(clauses: Vec<Clause>, literal: Literal) -> Option<Vec<Clause>> {
+    let negated = literal.negate();
+    let mut result = Vec::with_capacity(clauses.len());
+    for clause in clauses {
+        if clause.contains(&literal) {
+            continue;
+        }
+        let reduced: Clause = clause.into_iter().filter(|l| *l != negated).collect();
+        if reduced.is_empty() {
+            return None;
+        }
+        result.push(reduced);
+    }
+    Some(result)
+}
+
+fn assign_all(
+    mut clauses: Vec<Clause>,
+    literals: &[Literal],
+) -> Option<(Vec<Clause>, BTreeSet<usize>)> {
+    let mut assigned = BTreeSet::new();
+    for literal in literals {
+        clauses = assign(clauses, *literal)?;
+        assigned.insert(literal.var);
+    }
+    Some((clauses, assigned))
+}
+
+/// Assigns all unit clauses until none are left. Returns the forced literals.
+fn propagate(mut clauses: Vec<Clause>) -> Option<(Vec<Clause>, Vec<Literal>)> {
+    let mut forced = Vec::new();
+    while let Some(unit) = clauses.iter().find(|c| c.len() == 1).map(|c| c[0]) {
+        clauses = assign(clauses, unit)?;
+        forced.push(unit);
+    }
+    Some((clauses, forced))
+}
+
+fn branching_var(clauses: &[Clause]) -> usize {
+    clauses
+        .iter()
+        .flat_map(|c| c.iter().map(|l| l.var))
+        .min()
+        .unwrap()
+}
+
+fn solve_residual(clauses: Vec<Clause>, assignment: &mut Vec<Option<bool>>) -> bool {
+    let (clauses, forced) = match propagate(clauses) {
+        Some(result) => result,
+        None => return false,
+    };
+    for literal in forced.iter() {
+        assignment[literal.var] = Some(literal.positive);
+    }
+    if clauses.is_empty() {
+        return true;
+    }
+    let var = branching_var(&clauses);
+    for literal in [Literal::positive(var), Literal::negative(var)] {
+        if let Some(reduced) = assign(clauses.clone(), literal) {
+            let mut branch = assignment.clone();
+            branch[var] = Some(literal.positive);
+            if solve_residual(reduced, &mut branch) {
+                *assignment = branch;
+                return true;
+            }
+        }
+    }
+    for literal in forced {
+        assignment[literal.var] = None;
+    }
+    false
+}
+
+/// Counts the assignments of `vars` satisfying the clauses. Independent
+/// components are counted separately and multiplied.
+fn count_residual(clauses: Vec<Clause>, mut vars: BTreeSet<usize>) -> u128 {
+    let (clauses, forced) = match propagate(clauses) {
+        Some(result) => result,
+        None => return 0,
+    };
+    for literal in forced {
+        vars.remove(&literal.var);
+    }
+    let mut result: u128 = 1;
+    let mut constrained = BTreeSet::new();
+    for (component, component_vars) in split_components(clauses) {
+        constrained.extend(component_vars.iter().copied());
+        let var = branching_var(&component);
+        let remaining: BTreeSet<usize> = component_vars.into_iter().filter(|v| *v != var).collect();
+        let mut n = 0;
+        for literal in [Literal::positive(var), Literal::negative(var)] {
+            if let Some(reduced) = assign(component.clone(), literal) {
+                n += count_residual(reduced, remaining.clone());
+            }
+        }
+        result *= n;
+        if result == 0 {
+            return 0;
+        }
+    }
+    let n_free = vars.difference(&constrained).count();
+    result << n_free
+}
+
+fn split_components(clauses: Vec<Clause>) -> Vec<(Vec<Clause>, BTreeSet<usize>)> {
+    let mut parent: BTreeMap<usize, usize> = BTreeMap::new();
+    fn find(parent: &mut BTreeMap<usize, usize>, var: usize) -> usize {
+        let next = *parent.entry(var).or_insert(var);
+        if next == var {
+            return var;
+        }
+        let root = find(parent, next);
+        parent.insert(var, root);
+        root
+    }
+    for clause in clauses.iter() {
+        let first = find(&mut parent, clause[0].var);
+        for literal in clause.iter().skip(1) {
+            let other = find(&mut parent, literal.var);
+            parent.insert(other, first);
+        }
+    }
+    let mut components: BTreeMap<usize, (Vec<Clause>, BTreeSet<usize>)> = BTreeMap::new();
+    for clause in clauses {
+        let root = find(&mut parent, clause[0].var);
+        let entry = components.entry(root).or_default();
+        entry.1.extend(clause.iter().map(|l| l.var));
+        entry.0.push(clause);
+    }
+    components.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(var: usize) -> Literal {
+        Literal::positive(var)
+    }
+    fn n(var: usize) -> Literal {
+        Literal::negative(var)
+    }
+
+    #[test]
+    fn test_solve() {
+        let mut formula = Formula::new(3);
+        formula.add_clause(vec![p(0), p(1)]);
+        formula.add_clause(vec![n(0), p(2)]);
+        formula.add_clause(vec![n(1), n(2)]);
+        let model = formula.solve(&[]).unwrap();
+        assert!(model[0] || model[1]);
+        assert!(!model[0] || model[2]);
+        assert!(!model[1] || !model[2]);
+        assert!(formula.is_satisfiable(&[p(1)]));
+        assert!(!formula.is_satisfiable(&[p(1), p(0)]));
+        assert!(!formula.is_satisfiable(&[p(1), n(1)]));
+    }
+
+    #[test]
+    fn test_count() {
+        let mut formula = Formula::new(4);
+        assert_eq!(formula.count(&[]), 16);
+        formula.add_clause(vec![p(0), p(1)]);
+        assert_eq!(formula.count(&[]), 12);
+        formula.add_clause(vec![n(2), p(3)]);
+        assert_eq!(formula.count(&[]), 9);
+        assert_eq!(formula.count(&[p(2)]), 3);
+        assert_eq!(formula.count(&[p(2), n(3)]), 0);
+    }
+
+    #[test]
+    fn test_explain() {
+        let mut formula = Formula::new(3);
+        formula.add_clause(vec![n(0), p(1)]);
+        formula.add_clause(vec![p(2), p(1)]);
+        formula.add_clause(vec![n(1), n(2)]);
+        formula.add_clause(vec![n(1), p(2)]);
+        assert_eq!(formula.explain(&[p(2)]), None);
+        assert_eq!(formula.explain(&[p(0)]), Some(vec![0, 2, 3]));
+    }
+}
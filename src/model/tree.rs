@@ -1,4 +1,5 @@
 use crate::model::*;
+use std::collections::BTreeMap;
 use std::rc::Rc;
 
 pub const FEATURES_PREFIX: &str = "feature";
@@ -24,16 +25,51 @@ impl TreeDataModel {
         &mut self,
         path: QualifiedPath,
         is_tag: bool,
+    ) -> Result<(), WrongNodeTypeError> {
+        self.insert_ref(path, is_tag, None)
+    }
+    /// Inserts a branch or tag together with the commit it points to.
+    pub fn insert_ref(
+        &mut self,
+        path: QualifiedPath,
+        is_tag: bool,
+        commit: Option<String>,
     ) -> Result<(), WrongNodeTypeError> {
         if !path.is_absolute() {
             panic!("To insert a path, it must be absolute");
         }
+        let metadata = NodeMetadata::new(true).with_commit(commit);
         Rc::get_mut(&mut self.virtual_root)
             .unwrap()
-            .insert_node_path(&path.strip_n_left(1), NodeMetadata::new(true), is_tag)?;
+            .insert_node_path(&path.strip_n_left(1), metadata, is_tag)?;
         self.qualified_paths_with_branch.push(path);
         Ok(())
     }
+    pub fn set_variability(
+        &mut self,
+        path: &QualifiedPath,
+        variability: Variability,
+    ) -> Result<(), WrongNodeTypeError> {
+        Rc::get_mut(&mut self.virtual_root)
+            .unwrap()
+            .set_variability(&path.strip_n_left(1), variability)
+    }
+    /// Returns the variability of all features that deviate from the default.
+    pub fn collect_variability(&self) -> BTreeMap<QualifiedPath, Variability> {
+        self.get_virtual_root()
+            .iter_children_req()
+            .filter(|path| {
+                matches!(path.get_type(), NodeType::Feature)
+                    && path.get_metadata().variability() != &Variability::default()
+            })
+            .map(|path| {
+                (
+                    path.get_qualified_path(),
+                    path.get_metadata().variability().clone(),
+                )
+            })
+            .collect()
+    }
     pub fn get_area(&self, path: &QualifiedPath) -> Option<NodePath<Area>> {
         self.get_virtual_root().to_area(path)
     }
@@ -0,0 +1,253 @@
+use crate::model::*;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Describes how the children of a feature may be selected.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupKind {
+    /// Children are selected independently, according to their mandatory flag.
+    #[default]
+    And,
+    /// At least one child must be selected.
+    Or,
+    /// Exactly one child must be selected.
+    Alternative,
+}
+
+impl<S: Into<String>> From<S> for GroupKind {
+    fn from(value: S) -> Self {
+        let real = value.into();
+        match real.to_uppercase().as_str() {
+            "AND" => GroupKind::And,
+            "OR" => GroupKind::Or,
+            "ALTERNATIVE" => GroupKind::Alternative,
+            _ => unreachable!("Unknown group kind '{}'", real),
+        }
+    }
+}
+
+impl Display for GroupKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GroupKind::And => f.write_str("and"),
+            GroupKind::Or => f.write_str("or"),
+            GroupKind::Alternative => f.write_str("alternative"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Variability {
+    mandatory: bool,
+    group: GroupKind,
+}
+
+impl Variability {
+    pub fn new(mandatory: bool, group: GroupKind) -> Self {
+        Self { mandatory, group }
+    }
+    pub fn is_mandatory(&self) -> bool {
+        self.mandatory
+    }
+    pub fn group(&self) -> &GroupKind {
+        &self.group
+    }
+    pub fn set_mandatory(&mut self, mandatory: bool) {
+        self.mandatory = mandatory;
+    }
+    pub fn set_group(&mut self, group: GroupKind) {
+        self.group = group;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroupViolation {
+    parent: QualifiedPath,
+    group: GroupKind,
+    n_selected: usize,
+}
+
+impl GroupViolation {
+    pub fn parent(&self) -> &QualifiedPath {
+        &self.parent
+    }
+    pub fn display_relative_to(&self, base: &QualifiedPath) -> String {
+        let expected = match self.group {
+            GroupKind::Alternative => "exactly one child",
+            _ => "at least one child",
+        };
+        format!(
+            "{} has an {} group and requires {} to be selected, but {} are selected",
+            self.parent.strip_n_left(base.len()),
+            self.group,
+            expected,
+            self.n_selected
+        )
+    }
+}
+
+fn iter_feature_children<T: Clone + std::fmt::Debug>(
+    path: &NodePath<T>,
+) -> impl Iterator<Item = NodePath<AnyNodeType>> {
+    path.iter_children()
+        .filter(|child| matches!(child.get_type(), NodeType::Feature))
+}
+
+impl NodePath<FeatureRoot> {
+    /// Extends a selection of absolute feature paths by all ancestors and all
+    /// mandatory children of selected features. Only features with a branch
+    /// are added.
+    pub fn complete_selection(&self, selection: &[QualifiedPath]) -> Vec<QualifiedPath> {
+        let root_path = self.get_qualified_path();
+        let mut completed = selection.to_vec();
+        let mut index = 0;
+        while index < completed.len() {
+            let path = completed[index].clone();
+            index += 1;
+            let mut candidates: Vec<QualifiedPath> = (root_path.len() + 1..path.len())
+                .map(|n| path.strip_n_right(n))
+                .collect();
+            if let Some(node) = self.clone().to(&path.strip_n_left(root_path.len()))
+                && node.get_metadata().variability().group() == &GroupKind::And
+            {
+                candidates.extend(
+                    iter_feature_children(&node)
+                        .filter(|child| child.get_metadata().variability().is_mandatory())
+                        .map(|child| child.get_qualified_path()),
+                );
+            }
+            for candidate in candidates {
+                let has_branch = self
+                    .clone()
+                    .to(&candidate.strip_n_left(root_path.len()))
+                    .is_some_and(|node| node.get_metadata().has_branch());
+                if has_branch && !completed.contains(&candidate) {
+                    completed.push(candidate);
+                }
+            }
+        }
+        completed
+    }
+
+    /// Returns all or- and alternative-groups of selected features whose
+    /// children are not selected according to the group kind.
+    pub fn check_groups(&self, selection: &[QualifiedPath]) -> Vec<GroupViolation> {
+        let mut violations = Vec::new();
+        for node in self.iter_children_req() {
+            let path = node.get_qualified_path();
+            if !selection.contains(&path) {
+                continue;
+            }
+            let group = node.get_metadata().variability().group().clone();
+            let n_selected = iter_feature_children(&node)
+                .filter(|child| selection.contains(&child.get_qualified_path()))
+                .count();
+            let valid = match group {
+                GroupKind::And => true,
+                GroupKind::Or => n_selected >= 1,
+                GroupKind::Alternative => n_selected == 1,
+            };
+            if !valid {
+                violations.push(GroupViolation {
+                    parent: path,
+                    group,
+                    n_selected,
+                });
+            }
+        }
+        violations.sort_by(|a, b| a.parent.cmp(&b.parent));
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prepare_model() -> TreeDataModel {
+        let mut model = TreeDataModel::new();
+        for path in [
+            "/main/feature/root",
+            "/main/feature/root/db",
+            "/main/feature/root/db/postgres",
+            "/main/feature/root/db/maria",
+            "/main/feature/root/log",
+            "/main/feature/root/log/file",
+            "/main/feature/root/core",
+        ] {
+            model
+                .insert_qualified_path(QualifiedPath::from(path), false)
+                .unwrap();
+        }
+        model
+            .set_variability(
+                &QualifiedPath::from("/main/feature/root/db"),
+                Variability::new(true, GroupKind::Alternative),
+            )
+            .unwrap();
+        model
+            .set_variability(
+                &QualifiedPath::from("/main/feature/root/core"),
+                Variability::new(true, GroupKind::And),
+            )
+            .unwrap();
+        model
+            .set_variability(
+                &QualifiedPath::from("/main/feature/root/log"),
+                Variability::new(false, GroupKind::Or),
+            )
+            .unwrap();
+        model
+    }
+
+    #[test]
+    fn test_complete_selection() {
+        let model = prepare_model();
+        let feature_root = model
+            .get_area(&QualifiedPath::from("main"))
+            .unwrap()
+            .to_feature_root()
+            .unwrap();
+        let mut completed =
+            feature_root.complete_selection(&[QualifiedPath::from("/main/feature/root/db/maria")]);
+        completed.sort();
+        assert_eq!(
+            completed,
+            vec![
+                "/main/feature/root",
+                "/main/feature/root/core",
+                "/main/feature/root/db",
+                "/main/feature/root/db/maria",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_groups() {
+        let model = prepare_model();
+        let feature_root = model
+            .get_area(&QualifiedPath::from("main"))
+            .unwrap()
+            .to_feature_root()
+            .unwrap();
+        let selection = feature_root.complete_selection(&[
+            QualifiedPath::from("/main/feature/root/db/maria"),
+            QualifiedPath::from("/main/feature/root/db/postgres"),
+            QualifiedPath::from("/main/feature/root/log"),
+        ]);
+        let violations = feature_root.check_groups(&selection);
+        assert_eq!(
+            violations
+                .iter()
+                .map(|v| v.parent().clone())
+                .collect::<Vec<_>>(),
+            vec!["/main/feature/root/db", "/main/feature/root/log"]
+        );
+        let valid = feature_root.complete_selection(&[
+            QualifiedPath::from("/main/feature/root/db/maria"),
+            QualifiedPath::from("/main/feature/root/log/file"),
+        ]);
+        assert!(feature_root.check_groups(&valid).is_empty());
+    }
+}